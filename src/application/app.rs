@@ -1,14 +1,34 @@
+use super::file_handling::album_art::*;
+use super::file_handling::albums::*;
 use super::file_handling::audio_player::*;
+use super::file_handling::browser::*;
+use super::file_handling::columns::*;
+use super::file_handling::export::*;
 use super::file_handling::file_handling::*;
+use super::file_handling::history::*;
+use super::file_handling::import::*;
+use super::file_handling::lyrics::*;
+use super::file_handling::playlist_files::*;
+use super::file_handling::playlist_folders::*;
+use super::file_handling::playlist_stats::*;
+use super::file_handling::profiles::*;
+use super::file_handling::query::*;
+use super::file_handling::ratings::*;
+use super::file_handling::relocate::*;
+use super::file_handling::roots::*;
+use super::file_handling::search::*;
+use super::file_handling::smart_playlist::*;
+use super::file_handling::tag_editor::*;
+use super::track_table::*;
 use egui::Color32;
 use egui::WidgetType::ComboBox;
 use egui::*;
 use egui::{Image, TextureHandle, Ui};
 use egui_modal;
 use realfft::RealFftPlanner;
-use std::collections::VecDeque;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
 use std::fs::File;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::sync::atomic::Ordering;
 
@@ -20,6 +40,8 @@ use std::sync::atomic::Ordering;
 pub struct TemplateApp {
     music_library: Vec<MusicFile>,
     playlists: Vec<MusicCollection>,
+    playlist_folders: PlaylistFolders,
+    smart_playlists: Vec<SmartPlaylist>,
     #[serde(skip)]
     audio_player: AudioHandler,
     #[serde(skip)]
@@ -30,12 +52,23 @@ pub struct TemplateApp {
     visualizer_parameters: VisualizerParameters,
     #[serde(skip)]
     current_collection: Vec<MusicFile>,
-    #[serde(skip)]
     song_queue: VecDeque<MusicFile>,
     #[serde(skip)]
     current_song: String,
     #[serde(skip)]
-    playlist_state: usize,
+    current_song_path: Option<PathBuf>,
+    #[serde(skip)]
+    current_song_index: Option<(u64, Option<usize>)>, // (library generation, library index) of the playing song
+    #[serde(skip)]
+    playlist_state: Option<u32>, // the id of the playlist on screen
+    #[serde(skip)]
+    playlist_tracks: Option<PlaylistTracks>,
+    #[serde(skip)]
+    smart_playlist_state: Option<SmartView>,
+    #[serde(skip)]
+    favourites: SmartPlaylist,
+    #[serde(skip)]
+    smart_playlist_editor: Option<SmartPlaylistEditor>,
     #[serde(skip)]
     song_holder: Option<MusicFile>,
     #[serde(skip)]
@@ -44,25 +77,197 @@ pub struct TemplateApp {
     party_mode_on: bool,
     #[serde(skip)]
     modal_is_open: bool,
+    #[serde(skip)]
+    selected_tracks: BTreeSet<usize>,
+    #[serde(skip)]
+    selection_anchor: Option<usize>,
+    #[serde(skip)]
+    batch_editor: Option<BatchTagEditor>,
+    #[serde(skip)]
+    album_art: AlbumArtLoader,
+    library_view: LibraryView,
+    column_layouts: ColumnLayouts,
+    write_ratings_to_tags: bool,
+    show_lyrics: bool,
+    history: ListeningHistory,
+    library_roots: LibraryRoots,
+    ids: IdCounters,
+    #[serde(skip)]
+    new_root: String,
+    #[serde(skip)]
+    root_editor: Option<(u32, String)>,
+    #[serde(skip)]
+    roots_result: Option<String>,
+    #[serde(skip)]
+    play_tracker: PlayTracker,
+    #[serde(skip)]
+    show_statistics: bool,
+    #[serde(skip)]
+    statistics_range: TimeRange,
+    #[serde(skip)]
+    statistics: Option<(TimeRange, usize, ListeningStats)>,
+    #[serde(skip)]
+    library_generation: u64,
+    #[serde(skip)]
+    album_groups: Option<(u64, Vec<AlbumGroup>)>,
+    #[serde(skip)]
+    selected_album: Option<(String, String)>,
+    #[serde(skip)]
+    browser: Option<(u64, LibraryBrowser)>,
+    #[serde(skip)]
+    browse_filter: Option<BrowseFilter>,
+    #[serde(skip)]
+    track_positions: Option<(u64, HashMap<u32, usize>)>,
+    #[serde(skip)]
+    search_query: String,
+    #[serde(skip)]
+    search_index: Option<(u64, SearchIndex)>,
+    #[serde(skip)]
+    search_results: Option<(u64, String, Vec<SearchHit>)>,
+    #[serde(skip)]
+    search_error: Option<QueryError>,
+    #[serde(skip)]
+    table_rows: Option<(TableRowsKey, Vec<usize>)>,
+    #[serde(skip)]
+    missing_files: HashSet<PathBuf>,
+    #[serde(skip)]
+    relocate_root: String,
+    #[serde(skip)]
+    relocate_result: Option<String>,
+    #[serde(skip)]
+    import_source: ImportSource,
+    #[serde(skip)]
+    import_path: String,
+    #[serde(skip)]
+    import_playlists_path: String,
+    #[serde(skip)]
+    import_result: Option<Result<ImportReport, String>>,
+    #[serde(skip)]
+    export_settings: ExportSettings,
+    #[serde(skip)]
+    export_result: Option<Result<String, String>>,
+    #[serde(skip)]
+    restore_error: Option<String>,
+    #[serde(skip)]
+    add_music_error: Option<String>,
+    #[serde(skip)]
+    library_upgrade: Option<LibraryUpgrade>,
+    #[serde(skip)]
+    relocation: Option<RelocationSearch>,
+    #[serde(skip)]
+    lyrics: Option<(PathBuf, Option<Lyrics>)>,
+    #[serde(skip)]
+    lyrics_line: Option<usize>,
+    #[serde(skip)]
+    lyrics_editor: Option<(PathBuf, String)>,
+    #[serde(skip)]
+    lyrics_error: Option<String>,
+    #[serde(skip)]
+    playlist_rename: Option<(PlaylistNode, String)>,
+    #[serde(skip)]
+    playlist_file_path: String,
+    #[serde(skip)]
+    playlist_export: u32,
+    #[serde(skip)]
+    playlist_export_relative: bool,
+    #[serde(skip)]
+    playlist_import_result: Option<Result<PlaylistImport, String>>,
+    #[serde(skip)]
+    playlist_export_result: Option<Result<String, String>>,
+    #[serde(skip)]
+    profiles: ProfileList,
+    #[serde(skip)]
+    profile_action: Option<ProfileAction>,
+    #[serde(skip)]
+    new_profile: String,
+    #[serde(skip)]
+    profile_rename: Option<(u32, String)>,
+    #[serde(skip)]
+    profile_error: Option<String>,
 }
 
 impl Default for TemplateApp {
     fn default() -> Self {
         Self {
             music_library: new_library(),
-            audio_player: AudioHandler::new(),
+            audio_player: AudioHandler::idle(), // new() connects it, loading saved state doesn't
             seek: 1.0,
             fp: "".to_owned(),
             visualizer_parameters: VisualizerParameters::new(),
             song_queue: VecDeque::new(),
             playlists: Vec::new(),
+            playlist_folders: PlaylistFolders::default(),
+            smart_playlists: Vec::new(),
             current_collection: Vec::new(),
             current_song: String::new(),
-            playlist_state: 0,
+            current_song_path: None,
+            current_song_index: None,
+            playlist_state: None,
+            playlist_tracks: None,
+            smart_playlist_state: None,
+            favourites: SmartPlaylist::favourites(),
+            smart_playlist_editor: None,
             song_holder: None,
             colors: 0,
             party_mode_on: false,
             modal_is_open: false,
+            selected_tracks: BTreeSet::new(),
+            selection_anchor: None,
+            batch_editor: None,
+            album_art: AlbumArtLoader::new(),
+            library_view: LibraryView::Tracks,
+            column_layouts: ColumnLayouts::default(),
+            write_ratings_to_tags: false,
+            show_lyrics: false,
+            history: ListeningHistory::default(),
+            library_roots: LibraryRoots::default(),
+            ids: IdCounters::default(),
+            new_root: String::new(),
+            root_editor: None,
+            roots_result: None,
+            play_tracker: PlayTracker::default(),
+            show_statistics: false,
+            statistics_range: TimeRange::Month,
+            statistics: None,
+            library_generation: 0,
+            album_groups: None,
+            selected_album: None,
+            browser: None,
+            browse_filter: None,
+            track_positions: None,
+            search_query: String::new(),
+            search_index: None,
+            search_results: None,
+            search_error: None,
+            table_rows: None,
+            missing_files: HashSet::new(),
+            relocate_root: String::new(),
+            relocate_result: None,
+            import_source: ImportSource::ITunes,
+            import_path: String::new(),
+            import_playlists_path: String::new(),
+            import_result: None,
+            export_settings: ExportSettings::default(),
+            export_result: None,
+            restore_error: None,
+            add_music_error: None,
+            library_upgrade: None,
+            relocation: None,
+            lyrics: None,
+            lyrics_line: None,
+            lyrics_editor: None,
+            lyrics_error: None,
+            playlist_rename: None,
+            playlist_file_path: String::new(),
+            playlist_export: 0,
+            playlist_export_relative: true,
+            playlist_import_result: None,
+            playlist_export_result: None,
+            profiles: ProfileList::default(),
+            profile_action: None,
+            new_profile: String::new(),
+            profile_rename: None,
+            profile_error: None,
         }
     }
 }
@@ -74,82 +279,2068 @@ impl TemplateApp {
 
         if let Some(storage) = cc.storage {
             println!("Storage is not None");
-            return eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default();
+            let profiles: ProfileList =
+                eframe::get_value(storage, PROFILES_KEY).unwrap_or_default();
+            let mut app = TemplateApp::load(storage, profiles.current, &cc.egui_ctx);
+            app.profiles = profiles;
+            app.audio_player = AudioHandler::new();
+            return app;
         } else {
             println!("Storage is None");
-            return Default::default();
+            return TemplateApp {
+                audio_player: AudioHandler::new(),
+                ..Default::default()
+            };
+        }
+    }
+
+    fn load(storage: &dyn eframe::Storage, profile: u32, ctx: &egui::Context) -> Self {
+        // the saved state of a profile, brought up to date with this version of the app
+        let mut app: TemplateApp =
+            eframe::get_value(storage, &ProfileList::storage_key(profile)).unwrap_or_default();
+        migrate_playlists(&mut app.music_library, &mut app.playlists, &mut app.ids);
+        assign_smart_playlist_ids(&mut app.smart_playlists, &mut app.ids);
+        app.resolve_tracks();
+        app.library_upgrade = LibraryUpgrade::start(&app.music_library, ctx);
+        app.column_layouts.add_missing_fields();
+        app
+    }
+
+    fn switch_profile(&mut self, ctx: &egui::Context, storage: &mut dyn eframe::Storage, id: u32) {
+        // -----------------------------------------------------------------------------------------------
+        // saves the open profile and loads another one in its place. playback stops, and the play that
+        // was in progress goes into the history of the profile it was played in. the audio device and
+        // the album art loader are handed over to the new profile rather than opened again
+        // -----------------------------------------------------------------------------------------------
+        self.audio_player.stop_playback();
+        self.track_listening();
+        let mut profiles = self.profiles.clone();
+        eframe::set_value(storage, &ProfileList::storage_key(profiles.current), self);
+        profiles.current = id;
+        eframe::set_value(storage, PROFILES_KEY, &profiles);
+
+        let mut app = TemplateApp::load(storage, id, ctx);
+        std::mem::swap(&mut app.audio_player, &mut self.audio_player);
+        std::mem::swap(&mut app.album_art, &mut self.album_art);
+        app.profiles = profiles;
+        *self = app;
+    }
+
+    fn delete_profile(&mut self, storage: &mut dyn eframe::Storage, id: u32) {
+        match self.profiles.remove(id) {
+            Ok(()) => {
+                // the storage can't remove a key, so the saved state is emptied instead
+                storage.set_string(&ProfileList::storage_key(id), String::new());
+                eframe::set_value(storage, PROFILES_KEY, &self.profiles);
+                self.profile_error = None;
+            }
+            Err(e) => self.profile_error = Some(e),
         }
     }
 }
 
-impl eframe::App for TemplateApp {
-    fn save(&mut self, storage: &mut dyn eframe::Storage) {
-        eframe::set_value(storage, eframe::APP_KEY, self);
+impl TemplateApp {
+    fn play_song(&mut self, song: &MusicFile) {
+        self.audio_player.stop_playback();
+        if let Err(e) = self.audio_player.load_file(&song.file_path) {
+            // nothing is playing now, so the queue moves on to the next song by itself
+            println!("couldn't play {:?}: {}", song.file_path, e);
+            if !song.file_path.exists() {
+                self.missing_files.insert(song.file_path.clone());
+            }
+            return;
+        }
+        self.current_song = if song.title.is_empty() {
+            song.name.clone()
+        } else {
+            song.title.clone()
+        };
+        self.current_song_path = Some(song.file_path.clone());
+        self.current_song_index = None;
     }
 
-    fn update(&mut self, mut ctx: &egui::Context, frame: &mut eframe::Frame) {
+    fn current_song_index(&mut self) -> Option<usize> {
+        // the library index of the playing song, looked up again only when the song or the library changes
+        let generation = self.library_generation;
+        match self.current_song_index {
+            Some((cached, index)) if cached == generation => index,
+            _ => {
+                let index = self
+                    .current_song_path
+                    .as_ref()
+                    .and_then(|path| self.music_library.iter().position(|m| &m.file_path == path));
+                self.current_song_index = Some((generation, index));
+                index
+            }
+        }
+    }
+
+    fn library_rows(&mut self) -> Vec<usize> {
         // -----------------------------------------------------------------------------------------------
-        // This is the main update loop for the GUI. It is called continuously while the app is running,
-        // roughly 60 times per second (possibly higher depending on monitor refresh rate). Keep in mind that blocking this loop will freeze the GUI until
-        // the blocking operation is complete.
+        // the library indices shown in the track grid: search results when there is a search query,
+        // otherwise the whole library, narrowed down by the browser selection. plain words go through
+        // the fuzzy search index (ranked), text using query syntax is parsed and evaluated as a query
+        // (library order). results are only recomputed when the query or the library changes
         // -----------------------------------------------------------------------------------------------
-        //
-        // 
-        //
+        let generation = self.library_generation;
+        let query = self.search_query.trim();
+
+        let mut rows: Vec<usize> = if query.is_empty() {
+            self.search_error = None;
+            (0..self.music_library.len()).collect()
+        } else {
+            let stale = match &self.search_results {
+                Some((results_generation, results_query, _)) => {
+                    *results_generation != generation || results_query != query
+                }
+                None => true,
+            };
+            if stale {
+                self.search_error = None;
+                let hits = if is_structured(query) {
+                    match Query::parse(query) {
+                        Ok(parsed) => parsed
+                            .filter(&self.music_library)
+                            .into_iter()
+                            .map(|index| SearchHit { index, score: 0.0 })
+                            .collect(),
+                        Err(e) => {
+                            self.search_error = Some(e);
+                            Vec::new()
+                        }
+                    }
+                } else {
+                    if self.search_index.as_ref().map(|(g, _)| *g) != Some(generation) {
+                        self.search_index =
+                            Some((generation, SearchIndex::build(&self.music_library)));
+                    }
+                    match &self.search_index {
+                        Some((_, index)) => index.search(query),
+                        None => Vec::new(),
+                    }
+                };
+                self.search_results = Some((generation, query.to_string(), hits));
+            }
+            match &self.search_results {
+                Some((_, _, hits)) => hits.iter().map(|hit| hit.index).collect(),
+                None => Vec::new(),
+            }
+        };
+
+        if let Some(filter) = &self.browse_filter {
+            rows.retain(|&i| filter.matches(&self.music_library[i]));
+        }
+        rows
+    }
+
+    fn library_view_selector(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.add(
+                TextEdit::singleline(&mut self.search_query)
+                    .hint_text("Search library")
+                    .desired_width(250.0),
+            );
+            ui.separator();
+            ui.selectable_value(&mut self.library_view, LibraryView::Tracks, "Tracks");
+            ui.selectable_value(&mut self.library_view, LibraryView::Albums, "Albums");
+            let generation = self.library_generation;
+            if self.track_positions.as_ref().map(|(g, _)| *g) != Some(generation) {
+                self.track_positions = Some((generation, track_positions(&self.music_library)));
+            }
+            if let (Some(filter), Some((_, positions))) =
+                (&self.browse_filter, &self.track_positions)
+            {
+                ui.separator();
+                ui.label(format!(
+                    "Showing: {}",
+                    filter.label(&self.music_library, positions)
+                ));
+                if ui.button("Clear").clicked() {
+                    self.browse_filter = None;
+                }
+            }
+        });
+        if let Some(error) = &self.search_error {
+            // point at the problem in the query with a caret under it
+            let leading =
+                self.search_query.chars().count() - self.search_query.trim_start().chars().count();
+            ui.colored_label(Color32::RED, error.to_string());
+            ui.label(
+                RichText::new(format!(
+                    "{}\n{}^",
+                    self.search_query,
+                    " ".repeat(leading + error.position)
+                ))
+                .monospace()
+                .color(Color32::RED),
+            );
+        }
+        ui.separator();
+    }
+
+    fn browser_panel(&mut self, ui: &mut Ui) {
+        // -----------------------------------------------------------------------------------------------
+        // Artist -> Album -> Track and Genre -> Artist -> Album -> Track trees for the left panel.
+        // Clicking a node filters the library grid, right clicking it enqueues everything under it.
+        // The tree is only rebuilt when the library changes
         // -----------------------------------------------------------------------------------------------
+        let generation = self.library_generation;
+        if self.browser.as_ref().map(|(g, _)| *g) != Some(generation) {
+            self.browser = Some((generation, LibraryBrowser::build(&self.music_library)));
+        }
+        let browser = match &self.browser {
+            Some((_, browser)) => browser,
+            None => return,
+        };
+        let library = &self.music_library;
+        let current = self.browse_filter.as_ref();
+        let mut actions: Vec<BrowserAction> = Vec::new();
+
+        egui::ScrollArea::vertical()
+            .id_source("library browser")
+            .show(ui, |ui| {
+                egui::CollapsingHeader::new("Artists").show(ui, |ui| {
+                    for artist in &browser.artists {
+                        let filter = BrowseFilter {
+                            artist: Some(artist.name.clone()),
+                            ..Default::default()
+                        };
+                        artist_node(ui, library, artist, filter, current, &mut actions);
+                    }
+                });
+                egui::CollapsingHeader::new("Genres").show(ui, |ui| {
+                    for genre in &browser.genres {
+                        let filter = BrowseFilter {
+                            genre: Some(genre.name.clone()),
+                            ..Default::default()
+                        };
+                        let label =
+                            node_label(&genre.name, "Unknown Genre", &genre.tracks, genre.duration);
+                        browser_node(
+                            ui,
+                            label,
+                            &filter,
+                            current,
+                            &genre.tracks,
+                            &mut actions,
+                            |ui, actions| {
+                                for artist in &genre.artists {
+                                    let artist_filter = BrowseFilter {
+                                        artist: Some(artist.name.clone()),
+                                        ..filter.clone()
+                                    };
+                                    artist_node(
+                                        ui,
+                                        library,
+                                        artist,
+                                        artist_filter,
+                                        current,
+                                        actions,
+                                    );
+                                }
+                            },
+                        );
+                    }
+                });
+            });
 
-        ctx.input(|input| {
-            if input.key_pressed(egui::Key::V) {
-                if !self.modal_is_open {
-                    if self.visualizer_parameters.is_active {
-                        self.visualizer_parameters.is_active = false
-                    } else {
-                        self.visualizer_parameters.is_active = true
+        for action in actions {
+            match action {
+                BrowserAction::Select(filter) => {
+                    self.set_view(None, None, false);
+                    self.library_view = LibraryView::Tracks;
+                    self.browse_filter = Some(filter);
+                }
+                BrowserAction::Enqueue(tracks) => self.enqueue_tracks(&tracks),
+                BrowserAction::Play(i) => {
+                    if let Some(track) = self.music_library.get(i).cloned() {
+                        self.play_song(&track);
                     }
                 }
             }
-            if input.key_pressed(egui::Key::Space) {
-                if self.audio_player.sink.is_paused() {
-                    self.audio_player.sink.play();
-                } else {
-                    self.audio_player.sink.pause();
+        }
+    }
+
+    fn album_view(&mut self, ui: &mut Ui) {
+        // -----------------------------------------------------------------------------------------------
+        // Shows the library as a grid of album covers, or the track list of the album that was clicked.
+        // The album grouping is only rebuilt when the library changes, and show_rows makes sure only the
+        // rows of covers that are actually on screen get laid out
+        // -----------------------------------------------------------------------------------------------
+        let generation = self.library_generation;
+        if self.album_groups.as_ref().map(|(g, _)| *g) != Some(generation) {
+            self.album_groups = Some((generation, group_albums(&self.music_library)));
+        }
+        let albums = match &self.album_groups {
+            Some((_, albums)) => albums,
+            None => return,
+        };
+
+        let selected = self
+            .selected_album
+            .as_ref()
+            .and_then(|key| albums.iter().find(|a| &a.key() == key).cloned());
+        if let Some(album) = selected {
+            self.album_tracks(ui, &album);
+            return;
+        }
+
+        let tile_size = 150.0;
+        let row_height = tile_size + 45.0;
+        let per_row =
+            ((ui.available_width() / (tile_size + ui.spacing().item_spacing.x)) as usize).max(1);
+        let rows = (albums.len() + per_row - 1) / per_row;
+        let mut clicked = None;
+
+        egui::ScrollArea::vertical()
+            .id_source("album grid")
+            .auto_shrink([false; 2])
+            .show_rows(ui, row_height, rows, |ui, row_range| {
+                for row in row_range {
+                    ui.horizontal(|ui| {
+                        for album in albums.iter().skip(row * per_row).take(per_row) {
+                            ui.allocate_ui(vec2(tile_size, row_height), |ui| {
+                                ui.vertical(|ui| {
+                                    let first_track = album
+                                        .tracks
+                                        .first()
+                                        .and_then(|&i| self.music_library.get(i));
+                                    let texture = first_track.and_then(|track| {
+                                        self.album_art.texture(ui.ctx(), &track.file_path)
+                                    });
+                                    let response = match texture {
+                                        Some(texture) => ui.add(ImageButton::new(
+                                            texture,
+                                            vec2(tile_size, tile_size),
+                                        )),
+                                        None => {
+                                            ui.add_sized([tile_size, tile_size], Button::new("♫"))
+                                        }
+                                    };
+                                    if response.clicked() {
+                                        clicked = Some(album.key());
+                                    }
+                                    let album_name = if album.album.is_empty() {
+                                        "Unknown Album"
+                                    } else {
+                                        &album.album
+                                    };
+                                    ui.add(
+                                        Label::new(RichText::new(album_name).strong()).wrap(false),
+                                    );
+                                    ui.add(Label::new(&album.album_artist).wrap(false));
+                                });
+                            });
+                        }
+                    });
                 }
+            });
+
+        if clicked.is_some() {
+            self.selected_album = clicked;
+        }
+    }
+
+    fn album_tracks(&mut self, ui: &mut Ui, album: &AlbumGroup) {
+        let mut play_album = false;
+
+        ui.horizontal(|ui| {
+            if ui.button("Back").clicked() {
+                self.selected_album = None;
+            }
+            ui.heading(&album.album);
+        });
+        ui.label(format!(
+            "{}  -  {} tracks  -  {}",
+            album.album_artist,
+            album.tracks.len(),
+            format_duration(album.duration(&self.music_library))
+        ));
+        ui.horizontal(|ui| {
+            if ui.button("Play album").clicked() {
+                play_album = true;
+            }
+            if ui.button("Queue album").clicked() {
+                self.enqueue_tracks(&album.tracks);
             }
         });
+        ui.separator();
+
+        let key = TableRowsKey {
+            view: format!("album {} {}", album.album_artist, album.album),
+            generation: self.library_generation,
+            len: album.tracks.len(),
+            query: String::new(),
+            filter: None,
+            sort: self.column_layouts.albums.sort.clone(),
+        };
+        let rows = match self.cached_rows(&key) {
+            Some(rows) => rows,
+            None => {
+                let mut rows = album.tracks.clone();
+                let library = &self.music_library;
+                self.column_layouts
+                    .albums
+                    .sort_rows(&mut rows, |&i| &library[i]);
+                rows
+            }
+        };
 
-        if self.audio_player.sink.empty() {
-            if !self.song_queue.is_empty() {
-                let song: MusicFile = self.song_queue.pop_front().unwrap();
-                self.audio_player.load_file(&song.file_path);
+        if play_album {
+            self.play_tracks(&rows);
+        }
+        let events = TrackTable::new(
+            "album tracks",
+            &self.music_library,
+            &rows,
+            &mut self.column_layouts.albums,
+        )
+        .default_layout(ColumnLayout::albums)
+        .missing(&self.missing_files)
+        .menu(&COLLECTION_MENU)
+        .show(ui);
+        self.apply_track_events(ui.ctx(), events, &rows, None);
+        self.table_rows = Some((key, rows));
+    }
+
+    fn smart_playlist_view(&mut self, ui: &mut Ui, view: SmartView) {
+        // -----------------------------------------------------------------------------------------------
+        // Shows the tracks of a smart playlist (or the built-in Favourites). The rules are only
+        // evaluated again when the library generation changed since the last time it was shown
+        // -----------------------------------------------------------------------------------------------
+        let saved = match view {
+            SmartView::Favourites => None,
+            SmartView::Saved(id) => match self.smart_playlist_index(id) {
+                Some(index) => Some(index),
+                None => {
+                    self.set_view(self.playlist_state, None, false);
+                    return;
+                }
+            },
+        };
+        let key = TableRowsKey {
+            view: format!("{:?}", view),
+            generation: self.library_generation,
+            len: self.music_library.len(),
+            query: String::new(),
+            filter: None,
+            sort: self.column_layouts.smart_playlists.sort.clone(),
+        };
+        let rows = match self.cached_rows(&key) {
+            Some(rows) => rows,
+            None => {
+                let smart_playlist = match saved {
+                    None => &mut self.favourites,
+                    Some(index) => &mut self.smart_playlists[index],
+                };
+                let mut rows = smart_playlist
+                    .tracks(&self.music_library, key.generation)
+                    .to_vec();
+                let library = &self.music_library;
+                self.column_layouts
+                    .smart_playlists
+                    .sort_rows(&mut rows, |&i| &library[i]);
+                rows
+            }
+        };
+
+        let (smart_playlist, icon) = match saved {
+            None => (&self.favourites, "\u{2665}"),
+            Some(index) => (&self.smart_playlists[index], "\u{2699}"),
+        };
+        ui.heading(format!("{} {}", icon, smart_playlist.name));
+        ui.label(format!(
+            "{}  -  {} tracks  -  {}",
+            smart_playlist.describe(),
+            rows.len(),
+            format_duration(rows.iter().map(|&i| self.music_library[i].duration).sum())
+        ));
+        ui.horizontal(|ui| {
+            if ui.button("Play").clicked() {
+                self.play_tracks(&rows);
+            }
+            if ui.button("Add to Queue").clicked() {
+                self.enqueue_tracks(&rows);
+            }
+        });
+        ui.separator();
+
+        let events = TrackTable::new(
+            "smart playlist tracks",
+            &self.music_library,
+            &rows,
+            &mut self.column_layouts.smart_playlists,
+        )
+        .missing(&self.missing_files)
+        .menu(&COLLECTION_MENU)
+        .show(ui);
+        self.apply_track_events(ui.ctx(), events, &rows, None);
+        self.table_rows = Some((key, rows));
+    }
+
+    fn library_table(&mut self, ui: &mut Ui) {
+        let key = TableRowsKey {
+            view: "library".to_string(),
+            generation: self.library_generation,
+            len: self.music_library.len(),
+            query: self.search_query.trim().to_string(),
+            filter: self.browse_filter.clone(),
+            sort: self.column_layouts.library.sort.clone(),
+        };
+        let rows = match self.cached_rows(&key) {
+            Some(rows) => rows,
+            None => {
+                let mut rows = self.library_rows();
+                let library = &self.music_library;
+                self.column_layouts
+                    .library
+                    .sort_rows(&mut rows, |&i| &library[i]);
+                rows
             }
+        };
+
+        // matches are only highlighted for plain searches, not queries
+        let highlight = if is_structured(&key.query) {
+            ""
+        } else {
+            key.query.as_str()
+        };
+        let events = TrackTable::new(
+            "library",
+            &self.music_library,
+            &rows,
+            &mut self.column_layouts.library,
+        )
+        .missing(&self.missing_files)
+        .selection(&self.selected_tracks)
+        .highlight(highlight)
+        .menu(&LIBRARY_MENU)
+        .show(ui);
+        self.apply_track_events(ui.ctx(), events, &rows, None);
+        self.table_rows = Some((key, rows));
+    }
+
+    fn playlist_table(&mut self, ui: &mut Ui, playlist: usize) {
+        let id = self.playlists[playlist].id;
+        let key = TableRowsKey {
+            view: format!("playlist {}", id),
+            generation: self.library_generation,
+            len: self.playlists[playlist].song_count(),
+            query: String::new(),
+            filter: None,
+            sort: self.column_layouts.playlists.sort.clone(),
+        };
+        // the playlist's tracks are looked up in the library again whenever the library changes
+        let up_to_date = match &self.playlist_tracks {
+            Some(cached) => cached.generation == self.library_generation && cached.id == id,
+            None => false,
+        };
+        if !up_to_date {
+            let (positions, tracks): (Vec<usize>, Vec<usize>) = self.playlists[playlist]
+                .entries(&self.music_library)
+                .into_iter()
+                .unzip();
+            let library = &self.music_library;
+            self.playlist_tracks = Some(PlaylistTracks {
+                generation: self.library_generation,
+                id,
+                positions,
+                stats: PlaylistStats::of(tracks.iter().map(|&i| &library[i])),
+                tracks,
+            });
         }
 
-        // 2400x1600
+        let rows = match self.cached_rows(&key) {
+            Some(rows) => rows,
+            None => {
+                let tracks = cached_tracks(&self.playlist_tracks);
+                let library = &self.music_library;
+                let mut rows: Vec<usize> = (0..tracks.len()).collect();
+                self.column_layouts
+                    .playlists
+                    .sort_rows(&mut rows, |&i| &library[tracks[i]]);
+                rows
+            }
+        };
+
+        if let Some(PlaylistTracks { stats, .. }) = &self.playlist_tracks {
+            let modified = match self.playlists[playlist].modified {
+                Some(modified) => format_date(modified),
+                None => "unknown".to_string(),
+            };
+            ui.heading(&self.playlists[playlist].name);
+            ui.label(format!(
+                "{} tracks  -  {}  -  {} artists  -  {} albums  -  last changed {}",
+                stats.tracks,
+                format_duration(stats.duration),
+                stats.artists,
+                stats.albums,
+                modified
+            ));
+        }
+        // the buttons go through the rows, so they play the tracks in the order the table shows them
+        let mut play: Option<bool> = None;
+        ui.horizontal(|ui| {
+            if ui.button("Play").clicked() {
+                play = Some(false);
+            }
+            if ui.button("Shuffle").clicked() {
+                play = Some(true);
+            }
+            if ui.button("Add to Queue").clicked() {
+                let tracks = self.playlist_rows(&rows);
+                self.enqueue_tracks(&tracks);
+            }
+        });
+        ui.separator();
+        if let Some(shuffle) = play {
+            let tracks = self.playlist_rows(&rows);
+            if shuffle {
+                self.play_tracks(&shuffled(&tracks));
+            } else {
+                self.play_tracks(&tracks);
+            }
+        }
 
-        // _frame.set_window_size(vec2(2400.0, 1600.0));
-        ctx.set_visuals(egui::Visuals::dark());
+        // dragging only makes sense in playlist order, not while the table is sorted by a column
+        let reorderable = self.column_layouts.playlists.sort.is_empty();
+        let events = TrackTable::new(
+            "playlist",
+            &self.music_library,
+            &rows,
+            &mut self.column_layouts.playlists,
+        )
+        .entries(cached_tracks(&self.playlist_tracks))
+        .selection(&self.selected_tracks)
+        .missing(&self.missing_files)
+        .menu(&PLAYLIST_MENU)
+        .reorderable(reorderable)
+        .show(ui);
+        self.apply_track_events(ui.ctx(), events, &rows, Some(playlist));
+
+        let delete = ui.input(|i| i.key_pressed(egui::Key::Delete));
+        if delete && !ui.ctx().wants_keyboard_input() && !self.selected_tracks.is_empty() {
+            let positions = self.playlist_positions(&self.selected_tracks);
+            self.remove_from_playlist(playlist, &positions);
+        }
+        self.table_rows = Some((key, rows));
+    }
 
-        let filepath_modal =
-            egui_modal::Modal::new(ctx, "filepath modal").with_close_on_outside_click(true);
-        filepath_modal.show(|ui| {
-            self.modal_is_open = true;
-            filepath_modal.title(ui, "Enter File Path");
+    fn cached_rows(&mut self, key: &TableRowsKey) -> Option<Vec<usize>> {
+        // -----------------------------------------------------------------------------------------------
+        // the sorted rows of the table on screen are kept between frames, so a big library isn't
+        // filtered and sorted again every frame. they are recomputed when anything in the key changes
+        // -----------------------------------------------------------------------------------------------
+        match self.table_rows.take() {
+            Some((cached_key, rows)) if &cached_key == key => Some(rows),
+            _ => None,
+        }
+    }
+
+    fn apply_track_events(
+        &mut self,
+        ctx: &Context,
+        events: Vec<TrackEvent>,
+        rows: &[usize],
+        playlist: Option<usize>,
+    ) {
+        // -----------------------------------------------------------------------------------------------
+        // applies what the user did in a TrackTable. indices are into the library, or into the
+        // collection of `playlist` when a playlist is shown
+        // -----------------------------------------------------------------------------------------------
+        for event in events {
+            match event {
+                TrackEvent::Select(i, modifiers) => update_selection(
+                    &mut self.selected_tracks,
+                    &mut self.selection_anchor,
+                    rows,
+                    i,
+                    modifiers,
+                ),
+                TrackEvent::Rate(i, rating) => {
+                    if let Some(i) = self.library_index(playlist, i) {
+                        self.set_rating(i, rating);
+                    }
+                }
+                TrackEvent::Favourite(i, favourite) => {
+                    if let Some(i) = self.library_index(playlist, i) {
+                        self.set_favourite(i, favourite);
+                    }
+                }
+                TrackEvent::Move(i, row) => {
+                    if let Some(p) = playlist {
+                        // the whole selection moves when the dragged track is part of it
+                        let to = match rows.get(row).and_then(|&row| self.playlist_entry(row)) {
+                            Some((position, _)) => position,
+                            None => self.playlists[p].song_count(),
+                        };
+                        let positions = self.playlist_positions(&self.selected_or(i));
+                        let moved = self.playlists[p].move_songs(&positions, to);
+                        // the moved tracks stay selected, as rows of the table the playlist is shown in
+                        let entries = self.playlists[p].entries(&self.music_library);
+                        self.selected_tracks = entries
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, (position, _))| moved.contains(position))
+                            .map(|(row, _)| row)
+                            .collect();
+                        self.selection_anchor = self.selected_tracks.iter().next().copied();
+                        self.library_generation += 1;
+                    }
+                }
+                TrackEvent::Menu(item, i) => {
+                    let track = match self.library_index(playlist, i) {
+                        Some(index) => self.music_library[index].clone(),
+                        None => continue,
+                    };
+                    match item {
+                        TrackMenuItem::Play => self.play_song(&track),
+                        TrackMenuItem::EditTags => {
+                            if !self.selected_tracks.contains(&i) {
+                                self.selected_tracks.clear();
+                                self.selected_tracks.insert(i);
+                            }
+                            // in the order the rows are shown, which is what auto-numbering uses
+                            let targets = rows
+                                .iter()
+                                .filter(|row| self.selected_tracks.contains(row))
+                                .filter_map(|&row| self.library_index(playlist, row))
+                                .collect();
+                            self.batch_editor =
+                                Some(BatchTagEditor::new(&self.music_library, targets));
+                            egui_modal::Modal::new(ctx, "batch edit modal").open();
+                        }
+                        TrackMenuItem::AddToPlaylist => {
+                            self.song_holder = Some(track);
+                            egui_modal::Modal::new(ctx, "playlist_add modal").open();
+                        }
+                        TrackMenuItem::AddToQueue => self.song_queue.push_back(track),
+                        TrackMenuItem::AddToQueueFront => self.song_queue.push_front(track),
+                        TrackMenuItem::RemoveFromPlaylist => {
+                            if let Some(p) = playlist {
+                                let positions = self.playlist_positions(&self.selected_or(i));
+                                self.remove_from_playlist(p, &positions);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn selected_or(&self, i: usize) -> BTreeSet<usize> {
+        // the selected tracks when `i` is one of them, otherwise just `i`
+        if self.selected_tracks.contains(&i) {
+            self.selected_tracks.clone()
+        } else {
+            BTreeSet::from([i])
+        }
+    }
+
+    fn remove_from_playlist(&mut self, playlist: usize, positions: &BTreeSet<usize>) {
+        self.playlists[playlist].remove_songs(positions);
+        self.selected_tracks.clear();
+        self.selection_anchor = None;
+        self.library_generation += 1;
+    }
+
+    fn delete_playlist(&mut self, id: u32) {
+        // the central panel goes back to the library when it was showing the deleted playlist
+        self.playlists.retain(|playlist| playlist.id != id);
+        if self.playlist_state == Some(id) {
+            self.set_view(None, self.smart_playlist_state, self.show_statistics);
+        }
+        self.playlist_rename = None;
+        self.library_generation += 1;
+    }
+
+    fn node_name(&self, node: PlaylistNode) -> &str {
+        let name = match node {
+            PlaylistNode::Playlist(id) => self
+                .playlists
+                .iter()
+                .find(|playlist| playlist.id == id)
+                .map(|playlist| &playlist.name),
+            PlaylistNode::Folder(id) => self.playlist_folders.get(id).map(|folder| &folder.name),
+        };
+        name.map(|name| name.as_str()).unwrap_or_default()
+    }
+
+    fn folder_tracks(&self, folder: u32) -> Vec<usize> {
+        // the library tracks of every playlist in a folder, in the order they play
+        let ids = self.playlist_folders.folder_tracks(folder, &self.playlists);
+        self.library_tracks(&ids)
+    }
+
+    fn playlist_rows(&self, rows: &[usize]) -> Vec<usize> {
+        // the library tracks of the given rows of the playlist on screen
+        rows.iter()
+            .filter_map(|&row| self.playlist_entry(row))
+            .map(|(_, i)| i)
+            .collect()
+    }
+
+    fn playlist_entry(&self, row: usize) -> Option<(usize, usize)> {
+        // -----------------------------------------------------------------------------------------------
+        // the position in the playlist and the index in the library of a row of the playlist on screen.
+        // they come from the same lookup the rows were built from, as the playlist can hold ids that
+        // aren't in the library and aren't shown
+        // -----------------------------------------------------------------------------------------------
+        let cached = self.playlist_tracks.as_ref()?;
+        Some((*cached.positions.get(row)?, *cached.tracks.get(row)?))
+    }
+
+    fn playlist_positions(&self, rows: &BTreeSet<usize>) -> BTreeSet<usize> {
+        rows.iter()
+            .filter_map(|&row| self.playlist_entry(row))
+            .map(|(position, _)| position)
+            .collect()
+    }
+
+    fn library_tracks(&self, ids: &[u32]) -> Vec<usize> {
+        let positions = track_positions(&self.music_library);
+        ids.iter()
+            .filter_map(|id| positions.get(id).copied())
+            .collect()
+    }
+
+    fn apply_playlist_actions(&mut self, ui: &Ui, actions: Vec<PlaylistAction>) {
+        for action in actions {
+            match action {
+                PlaylistAction::Show(id) => self.set_view(Some(id), None, false),
+                PlaylistAction::Play(folder) => {
+                    let tracks = self.folder_tracks(folder);
+                    self.play_tracks(&tracks);
+                }
+                PlaylistAction::Enqueue(folder) => {
+                    let tracks = self.folder_tracks(folder);
+                    self.enqueue_tracks(&tracks);
+                }
+                PlaylistAction::NewFolder(parent) => {
+                    // the new folder starts out being renamed, inside its parent opened up
+                    let id = self.playlist_folders.add("New Folder", parent);
+                    if let Some(parent) = parent {
+                        let header = folder_header_id(parent);
+                        let mut state =
+                            egui::collapsing_header::CollapsingState::load_with_default_open(
+                                ui.ctx(),
+                                header,
+                                false,
+                            );
+                        state.set_open(true);
+                        state.store(ui.ctx());
+                    }
+                    self.playlist_rename =
+                        Some((PlaylistNode::Folder(id), "New Folder".to_string()));
+                    ui.memory_mut(|m| m.request_focus(Id::new("playlist rename")));
+                }
+                PlaylistAction::Rename(node) => {
+                    self.playlist_rename = Some((node, self.node_name(node).to_string()));
+                    ui.memory_mut(|m| m.request_focus(Id::new("playlist rename")));
+                }
+                PlaylistAction::FinishRename(entered) => {
+                    // an empty name keeps the old one
+                    if let Some((node, name)) = self.playlist_rename.take() {
+                        if entered {
+                            let renamed = match node {
+                                PlaylistNode::Playlist(id) => self
+                                    .playlists
+                                    .iter_mut()
+                                    .find(|playlist| playlist.id == id)
+                                    .map(|playlist| playlist.rename(&name)),
+                                PlaylistNode::Folder(id) => {
+                                    Some(self.playlist_folders.rename(id, &name))
+                                }
+                            };
+                            if let Some(Ok(())) = renamed {
+                                self.library_generation += 1;
+                            }
+                        }
+                    }
+                }
+                PlaylistAction::Delete(PlaylistNode::Playlist(id)) => self.delete_playlist(id),
+                PlaylistAction::Delete(PlaylistNode::Folder(id)) => {
+                    self.playlist_folders.remove(id, &mut self.playlists);
+                    self.playlist_rename = None;
+                }
+                PlaylistAction::Move(PlaylistNode::Playlist(id), folder) => {
+                    if let Some(playlist) = self.playlists.iter_mut().find(|p| p.id == id) {
+                        self.playlist_folders.move_playlist(playlist, folder);
+                    }
+                }
+                PlaylistAction::Move(PlaylistNode::Folder(id), folder) => {
+                    // drop targets are never inside the dragged folder, so this can't fail
+                    let _ = self.playlist_folders.move_folder(id, folder);
+                }
+            }
+        }
+    }
+
+    fn set_view(&mut self, playlist: Option<u32>, smart: Option<SmartView>, statistics: bool) {
+        // -----------------------------------------------------------------------------------------------
+        // switches what the central panel shows. the selection holds rows of the list on screen, so it
+        // is cleared whenever that list changes
+        // -----------------------------------------------------------------------------------------------
+        let view = (
+            self.playlist_state,
+            self.smart_playlist_state,
+            self.show_statistics,
+        );
+        if view != (playlist, smart, statistics) {
+            self.selected_tracks.clear();
+            self.selection_anchor = None;
+        }
+        self.playlist_state = playlist;
+        self.smart_playlist_state = smart;
+        self.show_statistics = statistics;
+    }
+
+    fn smart_playlist_index(&self, id: u32) -> Option<usize> {
+        self.smart_playlists.iter().position(|p| p.id == id)
+    }
+
+    fn shown_playlist(&self) -> Option<usize> {
+        // where the playlist on screen is in the list of playlists
+        let id = self.playlist_state?;
+        self.playlists.iter().position(|playlist| playlist.id == id)
+    }
+
+    fn library_index(&self, playlist: Option<usize>, i: usize) -> Option<usize> {
+        // rows of a playlist are looked up through the ids the table was built from
+        match playlist {
+            Some(_) => self.playlist_entry(i).map(|(_, index)| index),
+            None => Some(i),
+        }
+    }
+
+    fn set_rating(&mut self, i: usize, rating: Option<u8>) {
+        self.music_library[i].rating = rating;
+        if self.write_ratings_to_tags {
+            if let Err(e) = write_rating(&self.music_library[i].file_path, rating) {
+                println!(
+                    "couldn't save the rating of {:?}: {}",
+                    self.music_library[i].file_path, e
+                );
+            }
+        }
+        self.track_changed();
+    }
+
+    fn set_favourite(&mut self, i: usize, favourite: bool) {
+        self.music_library[i].favourite = favourite;
+        self.track_changed();
+    }
+
+    fn track_changed(&mut self) {
+        // lets cached views (playlists among them) know to refresh
+        self.library_generation += 1;
+    }
+
+    fn track_listening(&mut self) {
+        // -----------------------------------------------------------------------------------------------
+        // keeps the listening history up to date. runs every frame and logs a play whenever the player
+        // moves on to another file or stops
+        // -----------------------------------------------------------------------------------------------
+        self.play_tracker.tick(self.audio_player.sink.is_paused());
+        let load = if self.audio_player.sink.empty() {
+            None
+        } else {
+            Some(self.audio_player.loads)
+        };
+        if self.play_tracker.load() == load {
+            return;
+        }
+
+        if let Some(play) = self.play_tracker.finish() {
+            if self.history.log(play, &mut self.music_library).is_some() {
+                self.track_changed();
+            }
+        }
+        if let (Some(load), Some(path)) = (load, &self.current_song_path) {
+            // tracks played from the queue might not be in the library anymore
+            let track = self
+                .music_library
+                .iter()
+                .chain(self.song_queue.iter())
+                .find(|m| &m.file_path == path)
+                .cloned()
+                .unwrap_or_else(|| MusicFile {
+                    file_path: path.clone(),
+                    title: self.current_song.clone(),
+                    ..Default::default()
+                });
+            self.play_tracker.start(&track, load);
+        }
+    }
+
+    fn statistics_view(&mut self, ui: &mut Ui) {
+        // -----------------------------------------------------------------------------------------------
+        // Listening statistics for the selected time range. They're only counted again when the range
+        // changes or a play is added to the history
+        // -----------------------------------------------------------------------------------------------
+        ui.heading("Listening Statistics");
+        ui.horizontal(|ui| {
+            for range in TimeRange::ALL {
+                ui.selectable_value(&mut self.statistics_range, range, range.label());
+            }
+        });
+        ui.separator();
+
+        let up_to_date = match &self.statistics {
+            Some((range, plays, _)) => {
+                *range == self.statistics_range && *plays == self.history.plays.len()
+            }
+            None => false,
+        };
+        if !up_to_date {
+            self.statistics = Some((
+                self.statistics_range,
+                self.history.plays.len(),
+                self.history.stats(self.statistics_range),
+            ));
+        }
+        let stats = match &self.statistics {
+            Some((_, _, stats)) => stats,
+            None => return,
+        };
+
+        ui.label(format!(
+            "Listening time: {}  -  {} plays  -  {} skipped",
+            format_listening_time(stats.total_time),
+            stats.plays,
+            stats.skips
+        ));
+        ui.add_space(10.0);
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            ui.columns(3, |columns| {
+                top_list(&mut columns[0], "Top Artists", &stats.top_artists);
+                top_list(&mut columns[1], "Top Albums", &stats.top_albums);
+                top_list(&mut columns[2], "Top Tracks", &stats.top_tracks);
+            });
+            ui.add_space(10.0);
+            ui.strong("Listening by hour and weekday");
+            listening_heatmap(ui, &stats.heatmap);
+        });
+    }
+
+    fn current_view_tracks(&mut self) -> Vec<usize> {
+        // -----------------------------------------------------------------------------------------------
+        // the library tracks of the list on screen, filtered and sorted the same way as the table shows
+        // them. the album grid and the statistics count as the library view
+        // -----------------------------------------------------------------------------------------------
+        if let Some(view) = self.smart_playlist_state {
+            let smart_playlist = match view {
+                SmartView::Favourites => &mut self.favourites,
+                SmartView::Saved(id) => match self.smart_playlist_index(id) {
+                    Some(index) => &mut self.smart_playlists[index],
+                    None => return Vec::new(),
+                },
+            };
+            let mut rows = smart_playlist
+                .tracks(&self.music_library, self.library_generation)
+                .to_vec();
+            let library = &self.music_library;
+            self.column_layouts
+                .smart_playlists
+                .sort_rows(&mut rows, |&i| &library[i]);
+            return rows;
+        }
+        if let Some(p) = self.shown_playlist() {
+            let library = &self.music_library;
+            let mut rows: Vec<usize> = self.playlists[p]
+                .entries(library)
+                .into_iter()
+                .map(|(_, i)| i)
+                .collect();
+            self.column_layouts
+                .playlists
+                .sort_rows(&mut rows, |&i| &library[i]);
+            return rows;
+        }
+        let mut rows = self.library_rows();
+        let library = &self.music_library;
+        self.column_layouts
+            .library
+            .sort_rows(&mut rows, |&i| &library[i]);
+        rows
+    }
+
+    fn export(&mut self) -> Result<String, String> {
+        // writes the export chosen in the export dialog, returns what was written
+        if self.export_settings.format == ExportFormat::Json && self.export_settings.full_backup {
+            export_backup(
+                &self.music_library,
+                &self.playlists,
+                &self.smart_playlists,
+                &self.playlist_folders,
+                &self.library_roots,
+                &self.export_settings.path,
+            )?;
+            return Ok(format!(
+                "Saved {} tracks, {} playlists and {} smart playlists.",
+                self.music_library.len(),
+                self.playlists.len(),
+                self.smart_playlists.len()
+            ));
+        }
+        let tracks: Vec<&MusicFile> = if self.export_settings.current_view {
+            let rows = self.current_view_tracks();
+            rows.iter().map(|&i| &self.music_library[i]).collect()
+        } else {
+            self.music_library.iter().collect()
+        };
+        export_tracks(&tracks, &self.export_settings)?;
+        Ok(format!("Exported {} tracks.", tracks.len()))
+    }
+
+    fn apply_relocations(&mut self, report: RelocationReport) {
+        // points the moved tracks at where the relocation search found them
+        let relocations = &report.relocations;
+        relocate_tracks(
+            self.music_library
+                .iter_mut()
+                .chain(self.song_queue.iter_mut()),
+            relocations,
+        );
+        self.resolve_tracks();
+        // keep the listening history pointing at the same tracks
+        for play in &mut self.history.plays {
+            if let Some(new_path) = relocations.get(&play.file_path) {
+                play.file_path = new_path.clone();
+            }
+        }
+        let mut result = format!(
+            "Found {} of {} missing tracks.",
+            relocations.len(),
+            self.missing_files.len()
+        );
+        if report.ambiguous > 0 {
+            result.push_str(&format!(
+                " {} matched more than one file and were left alone.",
+                report.ambiguous
+            ));
+        }
+        if !report.unreadable.is_empty() {
+            result.push_str(&format!(
+                " Couldn't search {}.",
+                report.unreadable.join(", ")
+            ));
+        }
+        self.relocate_result = Some(result);
+        self.missing_files
+            .retain(|path| !relocations.contains_key(path));
+        self.library_generation += 1;
+    }
+
+    fn restore_backup(&mut self, ctx: &egui::Context, backup: LibraryBackup) {
+        // -----------------------------------------------------------------------------------------------
+        // replaces the library and playlists with the ones from a JSON backup. everything that holds
+        // library indices or ids from before (the browser filter, the selection, open editors and the
+        // cached rows) is dropped, the old indices mean other tracks in the restored library
+        // -----------------------------------------------------------------------------------------------
+        self.music_library = backup.library;
+        self.playlists = backup.playlists;
+        self.playlist_folders = backup.folders;
+        // backups made before library roots or smart playlists were in them keep the current ones
+        if !backup.roots.roots.is_empty() {
+            self.library_roots = backup.roots;
+        }
+        if let Some(smart_playlists) = backup.smart_playlists {
+            self.smart_playlists = smart_playlists;
+        }
+        migrate_playlists(&mut self.music_library, &mut self.playlists, &mut self.ids);
+        assign_smart_playlist_ids(&mut self.smart_playlists, &mut self.ids);
+        self.resolve_tracks();
+        self.library_upgrade = LibraryUpgrade::start(&self.music_library, ctx);
+        self.set_view(None, None, false);
+        // the selection is cleared even when the view stays the same, the rows it pointed at are gone
+        self.selected_tracks.clear();
+        self.selection_anchor = None;
+        self.browse_filter = None;
+        self.track_positions = None;
+        self.batch_editor = None;
+        self.smart_playlist_editor = None;
+        self.playlist_tracks = None;
+        self.table_rows = None;
+        self.browser = None;
+        self.album_groups = None;
+        self.search_index = None;
+        self.search_results = None;
+        self.current_song_index = None;
+        self.missing_files.clear();
+        self.library_generation += 1;
+    }
+
+    fn resolve_tracks(&mut self) {
+        // points every track in the library and the queue at its library root
+        self.library_roots.resolve_tracks(
+            self.music_library
+                .iter_mut()
+                .chain(self.song_queue.iter_mut()),
+        );
+    }
+
+    fn remap_root(&mut self, id: u32, path: &Path) -> String {
+        // -----------------------------------------------------------------------------------------------
+        // moves a library root to a new location. every track under it follows, and so do the listening
+        // history and the playing track, which store absolute paths
+        // -----------------------------------------------------------------------------------------------
+        let old = match self.library_roots.remap(id, path) {
+            Some(old) => old,
+            None => return "That library folder doesn't exist anymore.".to_string(),
+        };
+        self.resolve_tracks();
+        for play in &mut self.history.plays {
+            if let Some(new_path) = remap_path(&play.file_path, &old, path) {
+                play.file_path = new_path;
+            }
+        }
+        if let Some(current) = &self.current_song_path {
+            if let Some(new_path) = remap_path(current, &old, path) {
+                self.current_song_path = Some(new_path);
+            }
+        }
+        self.missing_files.clear();
+        self.library_generation += 1;
+
+        let tracks: Vec<&MusicFile> = self
+            .music_library
+            .iter()
+            .filter(|m| m.root == Some(id))
+            .collect();
+        let missing = tracks.iter().filter(|m| !m.file_path.exists()).count();
+        let mut result = format!("Moved {} tracks to {}.", tracks.len(), path.display());
+        if missing > 0 {
+            result.push_str(&format!(" {} of them can't be found there.", missing));
+        }
+        result
+    }
+
+    fn lyrics_panel(&mut self, ui: &mut Ui) {
+        // -----------------------------------------------------------------------------------------------
+        // Lyrics of the playing track. Synced lyrics follow the playback position: the current line is
+        // highlighted and scrolled to whenever it changes (so the list can still be scrolled by hand in
+        // between), and clicking a line jumps there in the track. Edit opens the lyrics as text (LRC
+        // when synced) and saves them back to the sidecar file or the tags they came from
+        // -----------------------------------------------------------------------------------------------
+        let path = match &self.current_song_path {
+            Some(path) if !self.audio_player.sink.empty() => path.clone(),
+            _ => {
+                ui.heading("Lyrics");
+                ui.separator();
+                ui.label("Nothing is playing.");
+                return;
+            }
+        };
+        if self.lyrics.as_ref().map(|(p, _)| p) != Some(&path) {
+            self.lyrics = Some((path.clone(), load_lyrics(&path)));
+            self.lyrics_line = None;
+            self.lyrics_error = None;
+        }
+        if self.lyrics_editor.as_ref().map(|(p, _)| p) != Some(&path) {
+            self.lyrics_editor = None;
+        }
+
+        ui.horizontal(|ui| {
+            ui.heading("Lyrics");
+            if self.lyrics_editor.is_none() {
+                ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                    if ui.button("Edit").clicked() {
+                        let text = match &self.lyrics {
+                            Some((_, Some(lyrics))) => lyrics.to_text(),
+                            _ => String::new(),
+                        };
+                        self.lyrics_editor = Some((path.clone(), text));
+                        self.lyrics_error = None;
+                    }
+                });
+            }
+        });
+        ui.separator();
+        if let Some(error) = &self.lyrics_error {
+            ui.colored_label(Color32::RED, error);
+        }
+
+        if let Some((_, text)) = &mut self.lyrics_editor {
+            let mut save = false;
+            let mut cancel = false;
+            ui.horizontal(|ui| {
+                save = ui.button("Save").clicked();
+                cancel = ui.button("Cancel").clicked();
+            });
+            ui.label("Put [mm:ss.xx] in front of a line to sync it.");
+            egui::ScrollArea::vertical()
+                .auto_shrink([false; 2])
+                .show(ui, |ui| {
+                    ui.add(
+                        egui::TextEdit::multiline(text)
+                            .desired_width(f32::INFINITY)
+                            .desired_rows(20),
+                    );
+                });
+            if save {
+                let source = match &self.lyrics {
+                    Some((_, Some(lyrics))) => lyrics.source.clone(),
+                    _ => LyricsSource::Embedded,
+                };
+                match save_lyrics(&path, &source, text) {
+                    Ok(()) => {
+                        self.lyrics_editor = None;
+                        self.lyrics = None;
+                        self.lyrics_error = None;
+                    }
+                    Err(e) => self.lyrics_error = Some(format!("Couldn't save the lyrics: {}", e)),
+                }
+            } else if cancel {
+                self.lyrics_editor = None;
+                self.lyrics_error = None;
+            }
+            return;
+        }
+
+        let lyrics = match &self.lyrics {
+            Some((_, Some(lyrics))) => lyrics,
+            _ => {
+                ui.label(
+                    "No lyrics found. Put a .lrc or .txt file with the same name next to the track, \
+                     or add lyrics to its tags with Edit.",
+                );
+                return;
+            }
+        };
+
+        let current = lyrics.current_line(self.audio_player.position());
+        let scroll = current != self.lyrics_line;
+        self.lyrics_line = current;
+        let mut seek = None;
+        egui::ScrollArea::vertical()
+            .auto_shrink([false; 2])
+            .show(ui, |ui| {
+                for (i, line) in lyrics.lines.iter().enumerate() {
+                    let text = if Some(i) == current {
+                        RichText::new(&line.text)
+                            .strong()
+                            .color(Color32::LIGHT_BLUE)
+                    } else {
+                        RichText::new(&line.text)
+                    };
+                    let response = match line.time {
+                        Some(time) => {
+                            let response = ui
+                                .add(Label::new(text).sense(Sense::click()))
+                                .on_hover_text(format_duration(time));
+                            if response.clicked() {
+                                seek = Some(time);
+                            }
+                            response
+                        }
+                        None => ui.label(text),
+                    };
+                    if scroll && Some(i) == current {
+                        response.scroll_to_me(Some(Align::Center));
+                    }
+                }
+            });
+        if lyrics.is_synced() {
+            ui.ctx().request_repaint();
+        }
+
+        if let Some(time) = seek {
+            if let Err(e) = self.audio_player.seek(&path, time) {
+                println!("couldn't seek in {:?}: {}", path, e);
+            }
+        }
+    }
+
+    fn play_tracks(&mut self, tracks: &[usize]) {
+        // plays the first of the given library tracks and queues the rest in their place
+        self.song_queue.clear();
+        self.enqueue_tracks(tracks.get(1..).unwrap_or_default());
+        if let Some(track) = tracks
+            .first()
+            .and_then(|&i| self.music_library.get(i))
+            .cloned()
+        {
+            self.play_song(&track);
+        }
+    }
+
+    fn enqueue_tracks(&mut self, tracks: &[usize]) {
+        for &i in tracks {
+            if let Some(track) = self.music_library.get(i) {
+                self.song_queue.push_back(track.clone());
+            }
+        }
+    }
+}
+
+impl eframe::App for TemplateApp {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(
+            storage,
+            &ProfileList::storage_key(self.profiles.current),
+            self,
+        );
+        eframe::set_value(storage, PROFILES_KEY, &self.profiles);
+    }
+
+    fn update(&mut self, mut ctx: &egui::Context, frame: &mut eframe::Frame) {
+        // -----------------------------------------------------------------------------------------------
+        // This is the main update loop for the GUI. It is called continuously while the app is running,
+        // roughly 60 times per second (possibly higher depending on monitor refresh rate). Keep in mind that blocking this loop will freeze the GUI until
+        // the blocking operation is complete.
+        // -----------------------------------------------------------------------------------------------
+        //
+        // 
+        //
+        // -----------------------------------------------------------------------------------------------
+
+        if let Some(action) = self.profile_action.take() {
+            match (action, frame.storage_mut()) {
+                (ProfileAction::Switch(id), Some(storage)) => self.switch_profile(ctx, storage, id),
+                (ProfileAction::Delete(id), Some(storage)) => self.delete_profile(storage, id),
+                (_, None) => {
+                    self.profile_error = Some("profiles need the app's storage".to_string())
+                }
+            }
+        }
+
+        if let Some(upgrade) = &mut self.library_upgrade {
+            if upgrade.receive(&mut self.music_library) {
+                self.library_generation += 1;
+            }
+            if upgrade.finished {
+                self.library_upgrade = None;
+            }
+        }
+        if let Some(search) = &mut self.relocation {
+            if let Some(report) = search.receive() {
+                self.relocation = None;
+                self.apply_relocations(report);
+            }
+        }
+
+        // keyboard shortcuts are ignored while a text field (e.g. the search box) has focus
+        let typing = ctx.wants_keyboard_input();
+        ctx.input(|input| {
+            if typing {
+                return;
+            }
+            if input.key_pressed(egui::Key::V) {
+                if !self.modal_is_open {
+                    if self.visualizer_parameters.is_active {
+                        self.visualizer_parameters.is_active = false
+                    } else {
+                        self.visualizer_parameters.is_active = true
+                    }
+                }
+            }
+            if input.key_pressed(egui::Key::Space) {
+                if self.audio_player.sink.is_paused() {
+                    self.audio_player.sink.play();
+                } else {
+                    self.audio_player.sink.pause();
+                }
+            }
+        });
+
+        if self.audio_player.sink.empty() {
+            if !self.song_queue.is_empty() {
+                let song: MusicFile = self.song_queue.pop_front().unwrap();
+                self.play_song(&song);
+            }
+        }
+        self.track_listening();
+
+        // 2400x1600
+
+        // _frame.set_window_size(vec2(2400.0, 1600.0));
+        ctx.set_visuals(egui::Visuals::dark());
+
+        let filepath_modal =
+            egui_modal::Modal::new(ctx, "filepath modal").with_close_on_outside_click(true);
+        filepath_modal.show(|ui| {
+            self.modal_is_open = true;
+            filepath_modal.title(ui, "Enter File Path");
+
+            filepath_modal.frame(ui, |ui| {
+                let fp_edit =
+                    ui.add(TextEdit::singleline(&mut self.fp).hint_text("Enter filepath"));
+                if fp_edit.changed() {}
+                if let Some(error) = &self.add_music_error {
+                    ui.colored_label(Color32::RED, error);
+                }
+            });
+            filepath_modal.buttons(ui, |ui| {
+                if ui.button("Add to Library").clicked() {
+                    let new_music_files = match get_from_path(&self.fp) {
+                        Ok(new_music_files) => new_music_files,
+                        Err(e) => {
+                            self.add_music_error = Some(e);
+                            return;
+                        }
+                    };
+                    self.add_music_error = None;
+                    for x in new_music_files {
+                        self.music_library.push(x);
+                    }
+                    assign_track_ids(&mut self.music_library, &mut self.ids);
+                    // a folder that isn't inside a library root becomes one
+                    let folder = Path::new(self.fp.trim());
+                    let in_root = self
+                        .library_roots
+                        .roots
+                        .iter()
+                        .any(|root| folder.starts_with(&root.path));
+                    if folder.is_dir() && !in_root {
+                        self.library_roots.add(folder);
+                    }
+                    self.resolve_tracks();
+                    self.library_generation += 1;
+                    filepath_modal.close();
+                    self.modal_is_open = false;
+                    self.fp = "".to_owned();
+                }
+            });
+        });
+
+        let missing_files_modal =
+            egui_modal::Modal::new(ctx, "missing files modal").with_close_on_outside_click(true);
+        missing_files_modal.show(|ui| {
+            self.modal_is_open = true;
+            missing_files_modal.title(ui, "Missing Files");
+            missing_files_modal.frame(ui, |ui| {
+                if self.missing_files.is_empty() {
+                    ui.label("Every track in the library was found.");
+                } else {
+                    ui.label(format!(
+                        "{} tracks can't be found where the library expects them:",
+                        self.missing_files.len()
+                    ));
+                    egui::ScrollArea::vertical()
+                        .max_height(200.0)
+                        .show(ui, |ui| {
+                            for path in &self.missing_files {
+                                ui.label(path.to_string_lossy());
+                            }
+                        });
+                    ui.separator();
+                    ui.label("Search a folder for the moved files:");
+                    ui.add(
+                        TextEdit::singleline(&mut self.relocate_root)
+                            .hint_text("Enter folder path"),
+                    );
+                }
+                if let Some(search) = &self.relocation {
+                    let (done, total) = search.progress;
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label(format!(
+                            "Looked for {} of {} missing tracks...",
+                            done, total
+                        ));
+                    });
+                } else if let Some(result) = &self.relocate_result {
+                    ui.label(result);
+                }
+            });
+            missing_files_modal.buttons(ui, |ui| {
+                if missing_files_modal.button(ui, "Close").clicked() {
+                    self.modal_is_open = false;
+                    self.relocate_result = None;
+                }
+                let searching = self.relocation.is_some();
+                if !self.missing_files.is_empty()
+                    && ui
+                        .add_enabled(!searching, Button::new("Relocate"))
+                        .clicked()
+                {
+                    self.relocate_result = None;
+                    self.relocation = Some(RelocationSearch::start(
+                        &self.music_library,
+                        &self.missing_files,
+                        Path::new(self.relocate_root.trim()),
+                        ui.ctx(),
+                    ));
+                }
+            });
+        });
+
+        let roots_modal =
+            egui_modal::Modal::new(ctx, "library roots modal").with_close_on_outside_click(true);
+        roots_modal.show(|ui| {
+            self.modal_is_open = true;
+            roots_modal.title(ui, "Library Folders");
+            roots_modal.frame(ui, |ui| {
+                ui.label(
+                    "Tracks in a library folder are saved relative to it. When the music moves to \
+                     another drive or mount point, change the folder's location to move every track \
+                     in it at once.",
+                );
+                ui.separator();
+                if self.library_roots.roots.is_empty() {
+                    ui.label("There are no library folders yet.");
+                }
+                let mut remap = None;
+                let mut remove = None;
+                egui::Grid::new("library roots")
+                    .num_columns(3)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for root in &self.library_roots.roots {
+                            let tracks = self
+                                .music_library
+                                .iter()
+                                .filter(|m| m.root == Some(root.id))
+                                .count();
+                            ui.label(root.path.to_string_lossy());
+                            ui.label(format!("{} tracks", tracks));
+                            ui.horizontal(|ui| match &mut self.root_editor {
+                                Some((id, path)) if *id == root.id => {
+                                    ui.add(
+                                        TextEdit::singleline(path).hint_text("New location"),
+                                    );
+                                    if ui.button("Apply").clicked() {
+                                        remap = Some((root.id, PathBuf::from(path.trim())));
+                                    }
+                                }
+                                _ => {
+                                    if ui.button("Change location").clicked() {
+                                        self.root_editor =
+                                            Some((root.id, root.path.to_string_lossy().to_string()));
+                                    }
+                                    if ui.button("Remove").clicked() {
+                                        remove = Some(root.id);
+                                    }
+                                }
+                            });
+                            ui.end_row();
+                        }
+                    });
+                if let Some((id, path)) = remap {
+                    self.roots_result = Some(self.remap_root(id, &path));
+                    self.root_editor = None;
+                }
+                if let Some(id) = remove {
+                    // the tracks keep their absolute paths
+                    self.library_roots.remove(id);
+                    self.resolve_tracks();
+                    self.roots_result = None;
+                }
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.add(TextEdit::singleline(&mut self.new_root).hint_text("Folder path"));
+                    if ui.button("Add folder").clicked() {
+                        let folder = PathBuf::from(self.new_root.trim());
+                        if folder.is_dir() {
+                            self.library_roots.add(&folder);
+                            self.resolve_tracks();
+                            self.new_root.clear();
+                            self.roots_result = None;
+                        } else {
+                            self.roots_result =
+                                Some(format!("{} isn't a folder.", folder.display()));
+                        }
+                    }
+                });
+                if let Some(result) = &self.roots_result {
+                    ui.label(result);
+                }
+            });
+            roots_modal.buttons(ui, |ui| {
+                if roots_modal.button(ui, "Close").clicked() {
+                    self.modal_is_open = false;
+                    self.root_editor = None;
+                    self.roots_result = None;
+                }
+            });
+        });
+
+        let profiles_modal =
+            egui_modal::Modal::new(ctx, "profiles modal").with_close_on_outside_click(true);
+        profiles_modal.show(|ui| {
+            self.modal_is_open = true;
+            profiles_modal.title(ui, "Profiles");
+            profiles_modal.frame(ui, |ui| {
+                ui.label("Each profile has its own library, playlists, queue and settings.");
+                ui.separator();
+                egui::Grid::new("profiles")
+                    .num_columns(2)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for profile in &self.profiles.profiles {
+                            let open = profile.id == self.profiles.current;
+                            match &mut self.profile_rename {
+                                Some((id, name)) if *id == profile.id => {
+                                    ui.add(TextEdit::singleline(name));
+                                }
+                                _ if open => {
+                                    ui.strong(format!("{} (open)", profile.name));
+                                }
+                                _ => {
+                                    ui.label(&profile.name);
+                                }
+                            }
+                            ui.horizontal(|ui| {
+                                if !open && ui.button("Open").clicked() {
+                                    self.profile_action = Some(ProfileAction::Switch(profile.id));
+                                }
+                                if ui.button("Rename").clicked() {
+                                    self.profile_rename = Some((profile.id, profile.name.clone()));
+                                }
+                                if !open && ui.button("Delete").clicked() {
+                                    self.profile_action = Some(ProfileAction::Delete(profile.id));
+                                }
+                            });
+                            ui.end_row();
+                        }
+                    });
+                if let Some((id, name)) = self.profile_rename.clone() {
+                    ui.horizontal(|ui| {
+                        if ui.button("Save name").clicked() {
+                            match self.profiles.rename(id, &name) {
+                                Ok(()) => {
+                                    self.profile_rename = None;
+                                    self.profile_error = None;
+                                }
+                                Err(e) => self.profile_error = Some(e),
+                            }
+                        }
+                        if ui.button("Cancel").clicked() {
+                            self.profile_rename = None;
+                            self.profile_error = None;
+                        }
+                    });
+                }
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.add(TextEdit::singleline(&mut self.new_profile).hint_text("Profile name"));
+                    if ui.button("Create and open").clicked() {
+                        match self.profiles.add(&self.new_profile) {
+                            Ok(id) => {
+                                self.profile_action = Some(ProfileAction::Switch(id));
+                                self.new_profile.clear();
+                                self.profile_error = None;
+                            }
+                            Err(e) => self.profile_error = Some(e),
+                        }
+                    }
+                });
+                if let Some(error) = &self.profile_error {
+                    ui.colored_label(Color32::RED, error);
+                }
+            });
+            profiles_modal.buttons(ui, |ui| {
+                if profiles_modal.button(ui, "Close").clicked() {
+                    self.modal_is_open = false;
+                    self.profile_rename = None;
+                    self.profile_error = None;
+                }
+            });
+        });
+
+        let import_modal =
+            egui_modal::Modal::new(ctx, "import modal").with_close_on_outside_click(true);
+        import_modal.show(|ui| {
+            self.modal_is_open = true;
+            import_modal.title(ui, "Import Library");
+            import_modal.frame(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("From");
+                    ui.selectable_value(
+                        &mut self.import_source,
+                        ImportSource::ITunes,
+                        "iTunes / Music",
+                    );
+                    if ui
+                        .selectable_value(
+                            &mut self.import_source,
+                            ImportSource::Rhythmbox,
+                            "Rhythmbox",
+                        )
+                        .clicked()
+                        && self.import_path.is_empty()
+                    {
+                        if let Some(folder) = rhythmbox_folder() {
+                            self.import_path = folder.join("rhythmdb.xml").display().to_string();
+                            self.import_playlists_path =
+                                folder.join("playlists.xml").display().to_string();
+                        }
+                    }
+                });
+                match self.import_source {
+                    ImportSource::ITunes => {
+                        ui.add(
+                            TextEdit::singleline(&mut self.import_path)
+                                .hint_text("Path to Library.xml"),
+                        );
+                    }
+                    ImportSource::Rhythmbox => {
+                        ui.add(
+                            TextEdit::singleline(&mut self.import_path)
+                                .hint_text("Path to rhythmdb.xml"),
+                        );
+                        ui.add(
+                            TextEdit::singleline(&mut self.import_playlists_path)
+                                .hint_text("Path to playlists.xml (optional)"),
+                        );
+                    }
+                }
+                match &self.import_result {
+                    Some(Ok(report)) => {
+                        ui.label(report.summary());
+                        if !report.unresolved.is_empty() {
+                            egui::ScrollArea::vertical()
+                                .max_height(200.0)
+                                .show(ui, |ui| {
+                                    for entry in &report.unresolved {
+                                        ui.label(entry);
+                                    }
+                                });
+                        }
+                    }
+                    Some(Err(error)) => {
+                        ui.colored_label(Color32::RED, error);
+                    }
+                    None => {}
+                }
+            });
+            import_modal.buttons(ui, |ui| {
+                if import_modal.button(ui, "Close").clicked() {
+                    self.modal_is_open = false;
+                    self.import_result = None;
+                }
+                if ui.button("Import").clicked() {
+                    let path = Path::new(self.import_path.trim());
+                    let imported = match self.import_source {
+                        ImportSource::ITunes => read_itunes_library(path),
+                        ImportSource::Rhythmbox => {
+                            let playlists = self.import_playlists_path.trim();
+                            read_rhythmbox_library(
+                                path,
+                                Some(Path::new(playlists)).filter(|_| !playlists.is_empty()),
+                            )
+                        }
+                    };
+                    self.import_result = Some(imported.map(|imported| {
+                        imported.merge_into(
+                            &mut self.music_library,
+                            &mut self.playlists,
+                            &mut self.ids,
+                        )
+                    }));
+                    self.resolve_tracks();
+                    // the files that were added get their tags read in the background
+                    self.library_upgrade = LibraryUpgrade::start(&self.music_library, ui.ctx());
+                    self.library_generation += 1;
+                }
+            });
+        });
+
+        let playlist_files_modal =
+            egui_modal::Modal::new(ctx, "playlist files modal").with_close_on_outside_click(true);
+        playlist_files_modal.show(|ui| {
+            self.modal_is_open = true;
+            playlist_files_modal.title(ui, "Playlist Files");
+            playlist_files_modal.frame(ui, |ui| {
+                ui.add(
+                    TextEdit::singleline(&mut self.playlist_file_path)
+                        .hint_text("Path to playlist file (.m3u, .m3u8, .pls, .xspf)"),
+                );
+                ui.separator();
+                ui.strong("Export");
+                if self.playlists.is_empty() {
+                    ui.label("There are no playlists to export.");
+                } else {
+                    if !self.playlists.iter().any(|p| p.id == self.playlist_export) {
+                        self.playlist_export = self.playlists[0].id;
+                    }
+                    let selected = self
+                        .playlists
+                        .iter()
+                        .find(|p| p.id == self.playlist_export)
+                        .map(|p| p.name.as_str())
+                        .unwrap_or_default();
+                    egui::ComboBox::from_id_source("export playlist")
+                        .selected_text(selected)
+                        .show_ui(ui, |ui| {
+                            for playlist in &self.playlists {
+                                ui.selectable_value(
+                                    &mut self.playlist_export,
+                                    playlist.id,
+                                    &playlist.name,
+                                );
+                            }
+                        });
+                    ui.checkbox(
+                        &mut self.playlist_export_relative,
+                        "Paths relative to the playlist file",
+                    );
+                }
+                match &self.playlist_export_result {
+                    Some(Ok(result)) => {
+                        ui.label(result);
+                    }
+                    Some(Err(error)) => {
+                        ui.colored_label(Color32::RED, error);
+                    }
+                    None => {}
+                }
+                match &self.playlist_import_result {
+                    Some(Ok(report)) => {
+                        ui.label(report.summary());
+                        if !report.unresolved.is_empty() {
+                            egui::ScrollArea::vertical()
+                                .max_height(200.0)
+                                .show(ui, |ui| {
+                                    for entry in &report.unresolved {
+                                        ui.label(entry);
+                                    }
+                                });
+                        }
+                    }
+                    Some(Err(error)) => {
+                        ui.colored_label(Color32::RED, error);
+                    }
+                    None => {}
+                }
+            });
+            playlist_files_modal.buttons(ui, |ui| {
+                if playlist_files_modal.button(ui, "Close").clicked() {
+                    self.modal_is_open = false;
+                    self.playlist_import_result = None;
+                    self.playlist_export_result = None;
+                }
+                if ui.button("Import").clicked() {
+                    self.playlist_export_result = None;
+                    self.playlist_import_result = Some(import_playlist(
+                        Path::new(self.playlist_file_path.trim()),
+                        &mut self.music_library,
+                        &mut self.playlists,
+                        &mut self.ids,
+                    ));
+                    self.resolve_tracks();
+                    // the files that were added get their tags read in the background
+                    self.library_upgrade = LibraryUpgrade::start(&self.music_library, ui.ctx());
+                    self.library_generation += 1;
+                }
+                let export = self.playlists.iter().find(|p| p.id == self.playlist_export);
+                if let Some(playlist) = export {
+                    if ui.button("Export").clicked() {
+                        self.playlist_import_result = None;
+                        self.playlist_export_result = Some(
+                            export_playlist(
+                                playlist,
+                                &self.music_library,
+                                Path::new(self.playlist_file_path.trim()),
+                                self.playlist_export_relative,
+                            )
+                            .map(|()| {
+                                format!(
+                                    "Exported \"{}\" with {} tracks.",
+                                    playlist.name,
+                                    playlist.song_count()
+                                )
+                            }),
+                        );
+                    }
+                }
+            });
+        });
+
+        let export_modal =
+            egui_modal::Modal::new(ctx, "export modal").with_close_on_outside_click(true);
+        export_modal.show(|ui| {
+            self.modal_is_open = true;
+            export_modal.title(ui, "Export Library");
+            export_modal.frame(ui, |ui| {
+                let settings = &mut self.export_settings;
+                ui.add(TextEdit::singleline(&mut settings.path).hint_text("Export to file"));
+                ui.horizontal(|ui| {
+                    ui.label("Format");
+                    ui.selectable_value(&mut settings.format, ExportFormat::Csv, "CSV");
+                    ui.selectable_value(&mut settings.format, ExportFormat::Json, "JSON");
+                });
+                if settings.format == ExportFormat::Json {
+                    ui.checkbox(
+                        &mut settings.full_backup,
+                        "Full backup (library, playlists and smart playlists, can be restored later)",
+                    );
+                }
+                let full_backup = settings.format == ExportFormat::Json && settings.full_backup;
+                ui.add_enabled_ui(!full_backup, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Tracks");
+                        ui.selectable_value(&mut settings.current_view, false, "Whole library");
+                        ui.selectable_value(&mut settings.current_view, true, "Current view");
+                    });
+                    ui.label("Fields");
+                    ui.horizontal_wrapped(|ui| {
+                        for field in Field::ALL {
+                            let mut selected = settings.fields.contains(&field);
+                            if ui.checkbox(&mut selected, column_header(field)).changed() {
+                                settings.toggle_field(field, selected);
+                            }
+                        }
+                    });
+                });
+                match &self.export_result {
+                    Some(Ok(message)) => {
+                        ui.label(message);
+                    }
+                    Some(Err(error)) => {
+                        ui.colored_label(Color32::RED, error);
+                    }
+                    None => {}
+                }
+            });
+            export_modal.buttons(ui, |ui| {
+                if export_modal.button(ui, "Close").clicked() {
+                    self.modal_is_open = false;
+                    self.export_result = None;
+                }
+                if ui.button("Export").clicked() {
+                    self.export_result = Some(self.export());
+                }
+            });
+        });
 
-            filepath_modal.frame(ui, |ui| {
-                let fp_edit =
-                    ui.add(TextEdit::singleline(&mut self.fp).hint_text("Enter filepath"));
-                if fp_edit.changed() {}
+        let restore_modal =
+            egui_modal::Modal::new(ctx, "restore modal").with_close_on_outside_click(true);
+        restore_modal.show(|ui| {
+            self.modal_is_open = true;
+            restore_modal.title(ui, "Restore from Backup");
+            restore_modal.frame(ui, |ui| {
+                ui.label(
+                    "This replaces the library and all playlists with the ones in the backup. \
+                     Smart playlists are only replaced when the backup has them.",
+                );
+                ui.add(TextEdit::singleline(&mut self.fp).hint_text("Path to JSON backup"));
+                if let Some(error) = &self.restore_error {
+                    ui.colored_label(Color32::RED, error);
+                }
             });
-            filepath_modal.buttons(ui, |ui| {
-                if filepath_modal.button(ui, "Add to Library").clicked() {
-                    let new_music_files = get_from_path(&self.fp);
-                    for x in new_music_files {
-                        self.music_library.push(x);
-                    }
-                    filepath_modal.close();
+            restore_modal.buttons(ui, |ui| {
+                if restore_modal.button(ui, "Cancel").clicked() {
                     self.modal_is_open = false;
+                    self.restore_error = None;
                     self.fp = "".to_owned();
                 }
+                if ui.button("Restore").clicked() {
+                    match read_backup(&self.fp) {
+                        Ok(backup) => {
+                            self.restore_backup(ctx, backup);
+                            self.restore_error = None;
+                            self.fp = "".to_owned();
+                            restore_modal.close();
+                            self.modal_is_open = false;
+                        }
+                        Err(e) => self.restore_error = Some(e),
+                    }
+                }
             });
         });
 
@@ -166,7 +2357,7 @@ impl eframe::App for TemplateApp {
             playlist_modal.buttons(ui, |ui| {
                 if playlist_modal.button(ui, "Create").clicked() {
                     let playlist =
-                        MusicCollection::new(String::from(&self.fp), self.playlists.len() as i32);
+                        MusicCollection::new(String::from(&self.fp), self.ids.next_playlist_id());
                     self.playlists.push(playlist);
                     playlist_modal.close();
                     self.modal_is_open = false;
@@ -175,23 +2366,298 @@ impl eframe::App for TemplateApp {
             });
         });
 
+        let smart_playlist_modal =
+            egui_modal::Modal::new(ctx, "smart playlist modal").with_close_on_outside_click(true);
+        smart_playlist_modal.show(|ui| {
+            self.modal_is_open = true;
+            smart_playlist_modal.title(ui, "Smart Playlist");
+            if let Some(editor) = &mut self.smart_playlist_editor {
+                smart_playlist_modal.frame(ui, |ui| {
+                    ui.add(TextEdit::singleline(&mut editor.name).hint_text("Enter Playlist Name"));
+                    ui.horizontal(|ui| {
+                        ui.label("Match");
+                        ui.selectable_value(&mut editor.match_mode, MatchMode::All, "all");
+                        ui.selectable_value(&mut editor.match_mode, MatchMode::Any, "any");
+                        ui.label("of the following rules:");
+                    });
+
+                    let mut remove: Option<usize> = None;
+                    for (i, rule) in editor.rules.iter_mut().enumerate() {
+                        ui.horizontal(|ui| {
+                            query_field_combo_box(ui, ("rule field", i), &mut rule.field);
+                            egui::ComboBox::from_id_source(("rule op", i))
+                                .width(50.0)
+                                .selected_text(rule.op.symbol())
+                                .show_ui(ui, |ui| {
+                                    for op in Op::ALL {
+                                        ui.selectable_value(&mut rule.op, op, op.symbol());
+                                    }
+                                });
+                            ui.add(TextEdit::singleline(&mut rule.value).desired_width(150.0));
+                            if ui.button("-").clicked() {
+                                remove = Some(i);
+                            }
+                        });
+                    }
+                    if let Some(i) = remove {
+                        editor.rules.remove(i);
+                    }
+                    if ui.button("+ Add rule").clicked() {
+                        editor.rules.push(RuleEdit {
+                            field: Field::Artist,
+                            op: Op::Contains,
+                            value: String::new(),
+                        });
+                    }
+                    ui.separator();
+
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut editor.sort_enabled, "Sort by");
+                        query_field_combo_box(ui, "sort field", &mut editor.sort.field);
+                        ui.checkbox(&mut editor.sort.descending, "Descending");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Limit to");
+                        egui::ComboBox::from_id_source("limit kind")
+                            .selected_text(match editor.limit_kind {
+                                LimitKind::None => "no limit",
+                                LimitKind::Tracks => "tracks",
+                                LimitKind::Minutes => "minutes",
+                            })
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(
+                                    &mut editor.limit_kind,
+                                    LimitKind::None,
+                                    "no limit",
+                                );
+                                ui.selectable_value(
+                                    &mut editor.limit_kind,
+                                    LimitKind::Tracks,
+                                    "tracks",
+                                );
+                                ui.selectable_value(
+                                    &mut editor.limit_kind,
+                                    LimitKind::Minutes,
+                                    "minutes",
+                                );
+                            });
+                        if editor.limit_kind != LimitKind::None {
+                            ui.add(
+                                DragValue::new(&mut editor.limit_value).clamp_range(1.0..=100000.0),
+                            );
+                        }
+                    });
+                    if let Some(error) = &editor.error {
+                        ui.colored_label(Color32::RED, error);
+                    }
+                });
+                smart_playlist_modal.buttons(ui, |ui| {
+                    if smart_playlist_modal.button(ui, "Cancel").clicked() {
+                        smart_playlist_modal.close();
+                        self.modal_is_open = false;
+                    }
+                    if ui.button("Save").clicked() {
+                        match editor.build() {
+                            Ok(mut playlist) => {
+                                // a playlist deleted while its rules were being edited comes back
+                                match self
+                                    .smart_playlists
+                                    .iter()
+                                    .position(|p| p.id == playlist.id)
+                                {
+                                    Some(i) => self.smart_playlists[i] = playlist,
+                                    None => {
+                                        if playlist.id == 0 {
+                                            playlist.id = self.ids.next_smart_playlist_id();
+                                        }
+                                        self.smart_playlists.push(playlist);
+                                    }
+                                }
+                                self.table_rows = None;
+                                smart_playlist_modal.close();
+                                self.modal_is_open = false;
+                            }
+                            Err(e) => editor.error = Some(e),
+                        }
+                    }
+                });
+            }
+        });
+
+        let playlistadd_modal =
+            egui_modal::Modal::new(ctx, "playlist_add modal").with_close_on_outside_click(true);
+        playlistadd_modal.show(|ui| {
+            playlistadd_modal.title(ui, "Select Playlist");
+
+            playlistadd_modal.frame(ui, |ui| {
+                for mut x in &mut self.playlists {
+                    if ui.add(Label::new(&x.name).sense(Sense::click())).clicked() {
+                        let song = self.song_holder.clone();
+                        x.add_song(song.unwrap().id);
+                        self.library_generation += 1;
+                        playlistadd_modal.close();
+                    }
+                }
+            });
+            playlistadd_modal.buttons(ui, |ui| {
+                if playlistadd_modal.button(ui, "close").clicked() {
+                    playlistadd_modal.close();
+                }
+            });
+        });
+
+        let batch_edit_modal =
+            egui_modal::Modal::new(ctx, "batch edit modal").with_close_on_outside_click(true);
+        batch_edit_modal.show(|ui| {
+            self.modal_is_open = true;
+            batch_edit_modal.title(ui, "Edit Tags");
+            if let Some(editor) = &mut self.batch_editor {
+                batch_edit_modal.frame(ui, |ui| {
+                    ui.label(format!("{} tracks selected", editor.targets.len()));
+                    egui::Grid::new("batch edit grid")
+                        .num_columns(2)
+                        .show(ui, |ui| {
+                            for field in TagField::ALL {
+                                ui.label(field.label());
+                                let edit = editor.field_mut(field);
+                                let hint = if edit.is_mixed() {
+                                    "(multiple values)"
+                                } else {
+                                    ""
+                                };
+                                ui.add(TextEdit::singleline(&mut edit.value).hint_text(hint));
+                                ui.end_row();
+                            }
+                        });
+                    ui.separator();
+                    ui.label("Find and replace:");
+                    ui.horizontal(|ui| {
+                        tag_field_combo_box(ui, "find field", &mut editor.find_field);
+                        ui.add(TextEdit::singleline(&mut editor.find).hint_text("Find"));
+                        ui.add(TextEdit::singleline(&mut editor.replace).hint_text("Replace with"));
+                    });
+                    ui.label("Change case:");
+                    ui.horizontal(|ui| {
+                        tag_field_combo_box(ui, "case field", &mut editor.case_field);
+                        egui::ComboBox::from_id_source("case conversion")
+                            .selected_text(editor.case_conversion.label())
+                            .show_ui(ui, |ui| {
+                                for conversion in CaseConversion::ALL {
+                                    ui.selectable_value(
+                                        &mut editor.case_conversion,
+                                        conversion,
+                                        conversion.label(),
+                                    );
+                                }
+                            });
+                    });
+                    ui.checkbox(
+                        &mut editor.auto_number,
+                        "Number tracks in the current order",
+                    );
+                    for error in &editor.errors {
+                        ui.colored_label(Color32::RED, error);
+                    }
+                });
+                batch_edit_modal.buttons(ui, |ui| {
+                    if batch_edit_modal.button(ui, "Cancel").clicked() {
+                        batch_edit_modal.close();
+                        self.modal_is_open = false;
+                    }
+                    if ui.button("Apply").clicked() {
+                        editor.apply(&mut self.music_library);
+                        self.library_generation += 1;
+                        if editor.errors.is_empty() {
+                            batch_edit_modal.close();
+                            self.modal_is_open = false;
+                        }
+                    }
+                });
+            }
+        });
+
+        // the playlist tree in the left panel and the Edit menu both change the playlists
+        let mut playlist_actions: Vec<PlaylistAction> = Vec::new();
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             egui::menu::bar(ui, |ui| {
                 ui.menu_button("File", |ui| {
+                    ui.menu_button(format!("Profile: {}", self.profiles.current_name()), |ui| {
+                        for profile in &self.profiles.profiles {
+                            let open = profile.id == self.profiles.current;
+                            if ui.radio(open, &profile.name).clicked() && !open {
+                                self.profile_action = Some(ProfileAction::Switch(profile.id));
+                                ui.close_menu();
+                            }
+                        }
+                        ui.separator();
+                        if ui.button("Manage profiles").clicked() {
+                            self.profile_error = None;
+                            profiles_modal.open();
+                            ui.close_menu();
+                        }
+                    });
+                    ui.separator();
                     if ui.button("Add music file").clicked() {
+                        self.add_music_error = None;
                         filepath_modal.open();
                         ui.close_menu();
                     }
                     if ui.button("Add music folder").clicked() {
+                        self.add_music_error = None;
                         filepath_modal.open();
                         ui.close_menu();
                     }
+                    if ui.button("Import from iTunes / Rhythmbox").clicked() {
+                        self.import_result = None;
+                        import_modal.open();
+                        ui.close_menu();
+                    }
+                    if ui.button("Import / export playlist file").clicked() {
+                        // the playlist on screen is the one to export
+                        if let Some(id) = self.playlist_state {
+                            self.playlist_export = id;
+                        }
+                        self.playlist_import_result = None;
+                        self.playlist_export_result = None;
+                        playlist_files_modal.open();
+                        ui.close_menu();
+                    }
+                    if ui.button("Export library").clicked() {
+                        self.export_result = None;
+                        export_modal.open();
+                        ui.close_menu();
+                    }
+                    if ui.button("Restore from JSON backup").clicked() {
+                        self.restore_error = None;
+                        restore_modal.open();
+                        ui.close_menu();
+                    }
+                    if ui.button("Library folders").clicked() {
+                        roots_modal.open();
+                        ui.close_menu();
+                    }
+                    if ui.button("Check for missing files").clicked() {
+                        self.missing_files = find_missing(&self.music_library);
+                        self.relocate_result = None;
+                        missing_files_modal.open();
+                        ui.close_menu();
+                    }
                 });
                 ui.menu_button("Edit", |ui| {
                     if ui.button("Create new Playlist").clicked() {
                         playlist_modal.open();
                         ui.close_menu();
                     }
+                    if ui.button("Create new Playlist Folder").clicked() {
+                        playlist_actions.push(PlaylistAction::NewFolder(None));
+                        ui.close_menu();
+                    }
+                    if ui.button("Create new Smart Playlist").clicked() {
+                        self.smart_playlist_editor = Some(SmartPlaylistEditor::new());
+                        smart_playlist_modal.open();
+                        ui.close_menu();
+                    }
+                    ui.checkbox(&mut self.write_ratings_to_tags, "Save ratings to file tags");
                 });
                 ui.menu_button("View", |ui| {
                     if ui.button("Visualizer").clicked() {
@@ -212,19 +2678,39 @@ impl eframe::App for TemplateApp {
                         }
                         ui.close_menu();
                     }
+                    ui.checkbox(&mut self.show_lyrics, "Lyrics");
                 });
             });
             ui.style_mut().spacing.slider_width = 100.0;
             ui.vertical_centered(|ui| {
                 if !self.audio_player.sink.empty() {
+                    if let Some(path) = &self.current_song_path {
+                        if let Some(texture) = self.album_art.texture(ui.ctx(), path) {
+                            let size = texture.size_vec2() * (96.0 / texture.size_vec2().y);
+                            ui.image(texture, size);
+                        }
+                    }
                     ui.label(format!("Now playing: {}", self.current_song));
+                    if let Some(i) = self.current_song_index() {
+                        let (rating, favourite) = (
+                            self.music_library[i].rating,
+                            self.music_library[i].favourite,
+                        );
+                        ui.horizontal(|ui| {
+                            if let Some(favourite) = favourite_toggle(ui, favourite) {
+                                self.set_favourite(i, favourite);
+                            }
+                            if let Some(rating) = rating_stars(ui, rating) {
+                                self.set_rating(i, rating);
+                            }
+                        });
+                    }
                 }
                 if self.audio_player.sink.empty() {
                     if ui.button("PLAY").clicked() {
                         if !self.song_queue.is_empty() {
                             let song = self.song_queue.pop_front().unwrap();
-                            self.current_song = song.name.to_string();
-                            self.audio_player.load_file(&song.file_path);
+                            self.play_song(&song);
                         }
                         // nothing
                     }
@@ -241,14 +2727,10 @@ impl eframe::App for TemplateApp {
                         }
                         if !self.song_queue.is_empty() {
                             if ui.button("Next").clicked() {
-                                self.audio_player.stop_playback();
-
                                 // Check if there are songs in the queue
                                 if let Some(next_song) = self.song_queue.pop_front() {
                                     // Load and play the next song
-                                    self.current_song = next_song.name.to_string();
-                                    self.audio_player.load_file(next_song.file_path.as_path());
-                                    // Add this line
+                                    self.play_song(&next_song);
                                 }
                             }
                         }
@@ -297,17 +2779,94 @@ impl eframe::App for TemplateApp {
                     .add(Label::new("Library").sense(Sense::click()))
                     .clicked()
                 {
-                    self.playlist_state = 0;
+                    self.set_view(None, None, false);
+                    self.browse_filter = None;
+                };
+                if ui
+                    .add(Label::new("Statistics").sense(Sense::click()))
+                    .clicked()
+                {
+                    self.set_view(None, None, true);
                 };
 
-                egui::CollapsingHeader::new("Playlists").show(ui, |ui| {
-                    let mut i: usize = 1;
-                    for x in &self.playlists {
-                        if ui.add(Label::new(&x.name).sense(Sense::click())).clicked() {
-                            self.playlist_state = (x.index + 1) as usize
+                let drag_id = Id::new("dragged playlist");
+                let dragged: Option<PlaylistNode> = ui.data(|d| d.get_temp(drag_id));
+                let playlists_header = egui::CollapsingHeader::new("Playlists").show(ui, |ui| {
+                    if ui
+                        .add(Label::new("\u{2665} Favourites").sense(Sense::click()))
+                        .clicked()
+                    {
+                        self.set_view(None, Some(SmartView::Favourites), false);
+                    }
+
+                    let tree = PlaylistTree {
+                        folders: &self.playlist_folders,
+                        playlists: &self.playlists,
+                        dragged,
+                        drag_id,
+                    };
+                    tree.show(ui, None, &mut self.playlist_rename, &mut playlist_actions);
+
+                    let mut show: Option<u32> = None;
+                    let mut delete: Option<u32> = None;
+                    for smart_playlist in &self.smart_playlists {
+                        let response = ui
+                            .add(
+                                Label::new(format!("\u{2699} {}", smart_playlist.name))
+                                    .sense(Sense::click()),
+                            )
+                            .on_hover_text(smart_playlist.describe());
+                        if response.clicked() {
+                            show = Some(smart_playlist.id);
+                        }
+                        response.context_menu(|ui| {
+                            if ui.button("Edit Rules").clicked() {
+                                self.smart_playlist_editor =
+                                    Some(SmartPlaylistEditor::edit(smart_playlist));
+                                smart_playlist_modal.open();
+                                ui.close_menu();
+                            }
+                            if ui.button("Delete").clicked() {
+                                delete = Some(smart_playlist.id);
+                                ui.close_menu();
+                            }
+                        });
+                    }
+                    if let Some(id) = show {
+                        self.set_view(None, Some(SmartView::Saved(id)), false);
+                    }
+                    if let Some(id) = delete {
+                        self.smart_playlists.retain(|p| p.id != id);
+                        if self.smart_playlist_state == Some(SmartView::Saved(id)) {
+                            self.set_view(self.playlist_state, None, self.show_statistics);
                         }
                     }
                 });
+                // dropping on the heading moves a playlist or folder out to the top level
+                drop_target(
+                    ui,
+                    &playlists_header.header_response,
+                    None,
+                    dragged,
+                    &mut playlist_actions,
+                );
+                playlists_header.header_response.context_menu(|ui| {
+                    if ui.button("New Folder").clicked() {
+                        playlist_actions.push(PlaylistAction::NewFolder(None));
+                        ui.close_menu();
+                    }
+                });
+                if let Some(node) = dragged {
+                    egui::show_tooltip_at_pointer(ui.ctx(), drag_id.with("tooltip"), |ui| {
+                        ui.label(self.node_name(node));
+                    });
+                    if !ui.input(|i| i.pointer.any_down()) {
+                        ui.data_mut(|d| d.remove::<PlaylistNode>(drag_id));
+                    }
+                }
+                self.apply_playlist_actions(ui, playlist_actions);
+
+                self.browser_panel(ui);
             });
 
         egui::SidePanel::right("right panel")
@@ -334,6 +2893,15 @@ impl eframe::App for TemplateApp {
                 });
             });
 
+        if self.show_lyrics {
+            egui::SidePanel::right("lyrics panel")
+                .resizable(true)
+                .default_width(300.0)
+                .show(ctx, |ui| {
+                    self.lyrics_panel(ui);
+                });
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
             if self.visualizer_parameters.is_active {
                 if ui.add(Label::new("EXIT").sense(Sense::click())).clicked() {
@@ -629,211 +3197,510 @@ impl eframe::App for TemplateApp {
                     }
                     ui.painter().extend(shapes);
                 }
+            } else if self.show_statistics {
+                self.statistics_view(ui);
+            } else if let Some(view) = self.smart_playlist_state {
+                self.smart_playlist_view(ui, view);
+            } else if let Some(playlist) = self.shown_playlist() {
+                self.playlist_table(ui, playlist);
+            } else if self.library_view == LibraryView::Albums {
+                self.library_view_selector(ui);
+                self.album_view(ui);
             } else {
-                let spacing = (ui.available_width() - 500.0) / 4.0;
-                egui::ScrollArea::vertical().show(ui, |ui| {
-                    egui::Grid::new("some_unique_id")
-                        .striped(true)
-                        .min_col_width(spacing)
-                        .min_row_height(35.0)
-                        .show(ui, |ui| {
-                            ui.label("Title:");
-                            ui.label("Artist:");
-                            ui.label("Album:");
-                            ui.label("Duration:");
-                            ui.label("");
-                            ui.end_row();
-                            if self.playlist_state == 0 {
-                                let playlistadd_modal =
-                                    egui_modal::Modal::new(ctx, "playlist_add modal")
-                                        .with_close_on_outside_click(true);
-                                playlistadd_modal.show(|ui| {
-                                    playlistadd_modal.title(ui, "Select Playlist");
-
-                                    playlistadd_modal.frame(ui, |ui| {
-                                        for mut x in &mut self.playlists {
-                                            if ui
-                                                .add(Label::new(&x.name).sense(Sense::click()))
-                                                .clicked()
-                                            {
-                                                let song = self.song_holder.clone();
-                                                x.add_song(song.unwrap());
-                                                playlistadd_modal.close();
-                                            }
-                                        }
-                                    });
-                                    playlistadd_modal.buttons(ui, |ui| {
-                                        if playlistadd_modal.button(ui, "close").clicked() {
-                                            playlistadd_modal.close();
-                                        }
-                                    });
-                                });
+                self.library_view_selector(ui);
+                self.library_table(ui);
+            }
+        });
+    }
+}
 
-                                for z in &self.music_library {
-                                    if &z.title == "" {
-                                        let response =
-                                            ui.add(Label::new(&z.name).sense(Sense::click()));
-
-                                        if response.double_clicked() {
-                                            self.audio_player.stop_playback();
-                                            println!("double click executed456");
-                                            let file_path = &z.file_path;
-                                            self.audio_player.load_file(file_path.as_path());
-                                            self.current_song = String::from(&z.name);
-                                            print!("abcc, {}", self.current_song);
-                                        }
+//-------------------------------------------------------------------------------------------------
+// Selection helpers for the library table.
+// Click selects a single track, ctrl/cmd + click toggles a track and shift + click selects the
+// range between the last clicked track and this one, in the order the rows are shown
+// ------------------------------------------------------------------------------------------------
+fn update_selection(
+    selected: &mut BTreeSet<usize>,
+    anchor: &mut Option<usize>,
+    rows: &[usize],
+    index: usize,
+    modifiers: Modifiers,
+) {
+    if modifiers.shift {
+        let position = |track: usize| rows.iter().position(|&i| i == track);
+        if let (Some(start), Some(end)) = (anchor.and_then(position), position(index)) {
+            selected.clear();
+            selected.extend(&rows[start.min(end)..=start.max(end)]);
+            return;
+        }
+    }
 
-                                        response.context_menu(|ui| {
-                                            if ui.button("Play File").clicked() {
-                                                self.audio_player.stop_playback();
-                                                println!("double click executed456");
-                                                let file_path = &z.file_path;
-                                                self.audio_player.load_file(file_path.as_path());
-                                                ui.close_menu();
-                                                self.current_song = String::from(&z.name);
-                                                print!("abcc, {}", self.current_song);
-                                                ui.close_menu()
-                                            }
-
-                                            if ui.button("Add to Playlist").clicked() {
-                                                self.song_holder = Some(z.clone());
-                                                playlistadd_modal.open();
-                                                ui.close_menu();
-                                            }
-
-                                            if ui.button("Add to Queue").clicked() {
-                                                self.song_queue.push_back(z.clone());
-                                                ui.close_menu();
-                                            }
-
-                                            if ui.button("Add to beginning of Queue").clicked() {
-                                                self.song_queue.push_front(z.clone());
-                                                ui.close_menu();
-                                            }
-                                        });
-                                    } else {
-                                        let response =
-                                            ui.add(Label::new(&z.title).sense(Sense::click()));
-
-                                        if response.double_clicked() {
-                                            self.audio_player.stop_playback();
-                                            println!("double click executed456");
-                                            let file_path = &z.file_path;
-                                            self.audio_player.load_file(file_path.as_path());
-                                            self.current_song = String::from(&z.title);
-                                            print!("abcc, {}", self.current_song);
-                                        }
+    if modifiers.command {
+        if !selected.remove(&index) {
+            selected.insert(index);
+        }
+    } else {
+        selected.clear();
+        selected.insert(index);
+    }
+    *anchor = Some(index);
+}
 
-                                        response.context_menu(|ui| {
-                                            if ui.button("Play File").clicked() {
-                                                self.audio_player.stop_playback();
-                                                println!("double click executed123");
-                                                let file_path = &z.file_path;
-                                                self.audio_player.load_file(file_path.as_path());
-                                                self.current_song = String::from(&z.title);
-                                                ui.close_menu()
-                                            }
-                                            if ui.button("Add to Playlist").clicked() {
-                                                self.song_holder = Some(z.clone());
-                                                playlistadd_modal.open();
-                                                ui.close_menu();
-                                            }
-
-                                            if ui.button("Add to Queue").clicked() {
-                                                self.song_queue.push_back(z.clone());
-                                                ui.close_menu()
-                                            }
-                                            if ui.button("Add to beginning of Queue").clicked() {
-                                                self.song_queue.push_front(z.clone());
-                                                ui.close_menu()
-                                            }
-                                        });
-                                    }
-                                    ui.label(&z.artist);
-                                    ui.label(&z.album);
-                                    ui.label(&z.duration.to_string());
-                                    ui.end_row();
-                                }
-                            } else {
-                                let playlist = &self.playlists[self.playlist_state - 1];
-                                for z in &playlist.collection {
-                                    if &z.title == "" {
-                                        let response =
-                                            ui.add(Label::new(&z.name).sense(Sense::click()));
-
-                                        if response.double_clicked() {
-                                            self.audio_player.stop_playback();
-                                            println!("double click executed456");
-                                            let file_path = &z.file_path;
-                                            self.audio_player.load_file(file_path.as_path());
-                                            self.current_song = String::from(&z.name);
-                                            print!("abcc, {}", self.current_song);
-                                        }
+fn cached_tracks(cache: &Option<PlaylistTracks>) -> &[usize] {
+    // the library tracks of the playlist on screen, a free function so only the cache is borrowed
+    match cache {
+        Some(cached) => &cached.tracks,
+        None => &[],
+    }
+}
 
-                                        response.context_menu(|ui| {
-                                            if ui.button("Play File").clicked() {
-                                                self.audio_player.stop_playback();
-                                                println!("double click executed456");
-                                                let file_path = &z.file_path;
-                                                self.audio_player.load_file(file_path.as_path());
-                                                ui.close_menu();
-                                                self.current_song = String::from(&z.name);
-                                                print!("abcc, {}", self.current_song);
-                                                ui.close_menu();
-                                            }
-                                            if ui.button("Add to Queue").clicked() {
-                                                self.song_queue.push_back(z.clone());
-                                                ui.close_menu();
-                                            }
-
-                                            if ui.button("Add to beginning of Queue").clicked() {
-                                                self.song_queue.push_front(z.clone());
-                                                ui.close_menu();
-                                            }
-                                        });
-                                    } else {
-                                        let response =
-                                            ui.add(Label::new(&z.title).sense(Sense::click()));
-
-                                        if response.double_clicked() {
-                                            self.audio_player.stop_playback();
-                                            println!("double click executed456");
-                                            let file_path = &z.file_path;
-                                            self.audio_player.load_file(file_path.as_path());
-                                            self.current_song = String::from(&z.title);
-                                            print!("abcc, {}", self.current_song);
-                                        }
+fn top_list(ui: &mut Ui, title: &str, ranked: &[Ranked]) {
+    ui.strong(title);
+    if ranked.is_empty() {
+        ui.weak("Nothing played yet");
+    }
+    for (i, entry) in ranked.iter().enumerate() {
+        ui.label(format!("{}. {}", i + 1, entry.name))
+            .on_hover_text(format!(
+                "{} plays, {}",
+                entry.plays,
+                format_listening_time(entry.listened)
+            ));
+    }
+}
 
-                                        response.context_menu(|ui| {
-                                            if ui.button("Play File").clicked() {
-                                                self.audio_player.stop_playback();
-                                                println!("double click executed123");
-                                                let file_path = &z.file_path;
-                                                self.audio_player.load_file(file_path.as_path());
-                                                self.current_song = String::from(&z.title);
-                                                ui.close_menu();
-                                            }
-                                            if ui.button("Add to Queue").clicked() {
-                                                self.song_queue.push_back(z.clone());
-                                                ui.close_menu();
-                                            }
-                                            if ui.button("Add to beginning of Queue").clicked() {
-                                                self.song_queue.push_front(z.clone());
-                                                ui.close_menu();
-                                            }
-                                        });
-                                    }
-                                    ui.label(&z.artist);
-                                    ui.label(&z.album);
-                                    ui.label(&z.duration.to_string());
-                                    ui.end_row();
-                                }
-                            }
-                        });
-                });
+fn listening_heatmap(ui: &mut Ui, heatmap: &[[f64; 24]; 7]) {
+    // -----------------------------------------------------------------------------------------------
+    // a 7 x 24 grid, one row per weekday and one cell per hour. the more was listened in that hour,
+    // the brighter the cell
+    // -----------------------------------------------------------------------------------------------
+    const DAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+    let label_width = 40.0;
+    let cell = ((ui.available_width() - label_width) / 24.0).clamp(8.0, 30.0);
+    let max = heatmap
+        .iter()
+        .flat_map(|day| day.iter())
+        .fold(0.0_f64, |a, &b| a.max(b));
+
+    let (rect, response) = ui.allocate_exact_size(
+        vec2(label_width + cell * 24.0, cell * 7.0 + 20.0),
+        Sense::hover(),
+    );
+    let painter = ui.painter_at(rect);
+    let text_color = ui.visuals().text_color();
+    let font = FontId::proportional(12.0);
+    for (day, hours) in heatmap.iter().enumerate() {
+        let y = rect.top() + day as f32 * cell;
+        painter.text(
+            pos2(rect.left(), y + cell / 2.0),
+            Align2::LEFT_CENTER,
+            DAYS[day],
+            font.clone(),
+            text_color,
+        );
+        for (hour, &seconds) in hours.iter().enumerate() {
+            let intensity = if max > 0.0 {
+                (seconds / max) as f32
+            } else {
+                0.0
+            };
+            let cell_rect = Rect::from_min_size(
+                pos2(rect.left() + label_width + hour as f32 * cell, y),
+                vec2(cell, cell),
+            )
+            .shrink(1.0);
+            // from a dark grey for no listening up to light blue for the busiest hour
+            let mix =
+                |from: u8, to: u8| (from as f32 + (to as f32 - from as f32) * intensity) as u8;
+            painter.rect_filled(
+                cell_rect,
+                2.0,
+                Color32::from_rgb(mix(30, 140), mix(30, 180), mix(40, 255)),
+            );
+        }
+    }
+    for hour in (0..24).step_by(3) {
+        painter.text(
+            pos2(
+                rect.left() + label_width + hour as f32 * cell,
+                rect.top() + cell * 7.0 + 10.0,
+            ),
+            Align2::LEFT_CENTER,
+            format!("{}:00", hour),
+            font.clone(),
+            text_color,
+        );
+    }
+
+    // show the exact time for the cell under the mouse
+    if let Some(pos) = response.hover_pos() {
+        let hour = ((pos.x - rect.left() - label_width) / cell).floor();
+        let day = ((pos.y - rect.top()) / cell).floor();
+        if (0.0..24.0).contains(&hour) && (0.0..7.0).contains(&day) {
+            let seconds = heatmap[day as usize][hour as usize];
+            response.on_hover_text(format!(
+                "{} {}:00 - {}",
+                DAYS[day as usize],
+                hour,
+                format_listening_time(seconds)
+            ));
+        }
+    }
+}
+
+fn tag_field_combo_box(ui: &mut Ui, id: &str, field: &mut TagField) {
+    egui::ComboBox::from_id_source(id)
+        .selected_text(field.label())
+        .show_ui(ui, |ui| {
+            for option in TagField::ALL {
+                ui.selectable_value(field, option, option.label());
+            }
+        });
+}
+
+fn query_field_combo_box(ui: &mut Ui, id: impl std::hash::Hash, field: &mut Field) {
+    egui::ComboBox::from_id_source(id)
+        .selected_text(field.name())
+        .show_ui(ui, |ui| {
+            for option in Field::ALL {
+                ui.selectable_value(field, option, option.name());
             }
         });
+}
+
+//-------------------------------------------------------------------------------------------------
+// Library browser helpers.
+// The browser tree is drawn first and the clicks are collected as actions, which are applied once
+// the tree is done borrowing the library
+// ------------------------------------------------------------------------------------------------
+enum BrowserAction {
+    Select(BrowseFilter),
+    Enqueue(Vec<usize>),
+    Play(usize),
+}
+
+// profile changes that need the storage, which the app only has at the start of update()
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ProfileAction {
+    Switch(u32),
+    Delete(u32),
+}
+
+//-------------------------------------------------------------------------------------------------
+// Playlist tree helpers.
+// Playlists and their folders are drawn the same way as the library browser: the clicks, renames
+// and drops are collected as actions and applied once the tree is done borrowing the playlists.
+// Playlists and folders are dragged by their name and dropped on a folder, or on the Playlists
+// heading for the top level. What is being dragged is kept in egui's memory under drag_id
+// ------------------------------------------------------------------------------------------------
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum PlaylistNode {
+    Playlist(u32),
+    Folder(u32),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum PlaylistAction {
+    Show(u32),
+    Play(u32),
+    Enqueue(u32),
+    NewFolder(Option<u32>),
+    Rename(PlaylistNode),
+    FinishRename(bool), // whether enter was pressed to keep the new name
+    Delete(PlaylistNode),
+    Move(PlaylistNode, Option<u32>),
+}
+
+struct PlaylistTree<'a> {
+    folders: &'a PlaylistFolders,
+    playlists: &'a [MusicCollection],
+    dragged: Option<PlaylistNode>,
+    drag_id: Id,
+}
+
+impl PlaylistTree<'_> {
+    fn show(
+        &self,
+        ui: &mut Ui,
+        parent: Option<u32>,
+        rename: &mut Option<(PlaylistNode, String)>,
+        actions: &mut Vec<PlaylistAction>,
+    ) {
+        for folder in self.folders.subfolders(parent) {
+            let node = PlaylistNode::Folder(folder.id);
+            egui::collapsing_header::CollapsingState::load_with_default_open(
+                ui.ctx(),
+                folder_header_id(folder.id),
+                false,
+            )
+            .show_header(ui, |ui| {
+                let label = format!("\u{1F4C1} {}", folder.name);
+                let response = match self.name_label(ui, node, &label, rename, actions) {
+                    Some(response) => response,
+                    None => return,
+                };
+                // a folder can't go inside itself or one of its own subfolders
+                let dragged = self.dragged.filter(|dragged| match dragged {
+                    PlaylistNode::Folder(id) => !self.folders.is_inside(folder.id, *id),
+                    PlaylistNode::Playlist(_) => true,
+                });
+                drop_target(ui, &response, Some(folder.id), dragged, actions);
+                response.context_menu(|ui| {
+                    let mut action = None;
+                    if ui.button("Play").clicked() {
+                        action = Some(PlaylistAction::Play(folder.id));
+                    }
+                    if ui.button("Add to Queue").clicked() {
+                        action = Some(PlaylistAction::Enqueue(folder.id));
+                    }
+                    if ui.button("New Folder").clicked() {
+                        action = Some(PlaylistAction::NewFolder(Some(folder.id)));
+                    }
+                    if ui.button("Rename").clicked() {
+                        action = Some(PlaylistAction::Rename(node));
+                    }
+                    if ui.button("Delete").clicked() {
+                        action = Some(PlaylistAction::Delete(node));
+                    }
+                    if let Some(action) = action {
+                        actions.push(action);
+                        ui.close_menu();
+                    }
+                });
+            })
+            .body(|ui| self.show(ui, Some(folder.id), rename, actions));
+        }
+
+        for playlist in self.folders.playlists_in(parent, self.playlists) {
+            let node = PlaylistNode::Playlist(playlist.id);
+            let response = match self.name_label(ui, node, &playlist.name, rename, actions) {
+                Some(response) => response,
+                None => continue,
+            };
+            if response.clicked() {
+                actions.push(PlaylistAction::Show(playlist.id));
+            }
+            response.context_menu(|ui| {
+                if ui.button("Rename").clicked() {
+                    actions.push(PlaylistAction::Rename(node));
+                    ui.close_menu();
+                }
+                if ui.button("Delete").clicked() {
+                    actions.push(PlaylistAction::Delete(node));
+                    ui.close_menu();
+                }
+            });
+        }
+    }
+
+    fn name_label(
+        &self,
+        ui: &mut Ui,
+        node: PlaylistNode,
+        label: &str,
+        rename: &mut Option<(PlaylistNode, String)>,
+        actions: &mut Vec<PlaylistAction>,
+    ) -> Option<Response> {
+        // -----------------------------------------------------------------------------------------------
+        // the name of a playlist or folder, which can be clicked and dragged. while it is being renamed
+        // it is a text field instead: enter keeps the new name, clicking elsewhere or escape drops it
+        // -----------------------------------------------------------------------------------------------
+        if let Some((renaming, name)) = rename {
+            if *renaming == node {
+                let response = ui.add(TextEdit::singleline(name).id(Id::new("playlist rename")));
+                if response.lost_focus() {
+                    let entered = ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    actions.push(PlaylistAction::FinishRename(entered));
+                }
+                return None;
+            }
+        }
+        let response = ui.add(Label::new(label).sense(Sense::click_and_drag()));
+        if response.drag_started() {
+            ui.data_mut(|d| d.insert_temp(self.drag_id, node));
+        }
+        Some(response)
+    }
+}
+
+fn folder_header_id(folder: u32) -> Id {
+    Id::new(("playlist folder", folder))
+}
+
+fn drop_target(
+    ui: &Ui,
+    response: &Response,
+    folder: Option<u32>,
+    dragged: Option<PlaylistNode>,
+    actions: &mut Vec<PlaylistAction>,
+) {
+    // outlines the folder the dragged playlist or folder is over, and moves it there when it is let go
+    let node = match dragged {
+        Some(node) => node,
+        None => return,
+    };
+    if !ui.rect_contains_pointer(response.rect) {
+        return;
     }
+    ui.painter().rect_stroke(
+        response.rect.expand(2.0),
+        2.0,
+        ui.visuals().selection.stroke,
+    );
+    if ui.input(|i| i.pointer.any_released()) {
+        actions.push(PlaylistAction::Move(node, folder));
+    }
+}
+
+fn node_label(name: &str, fallback: &str, tracks: &[usize], duration: f64) -> String {
+    format!(
+        "{}  ({} tracks, {})",
+        if name.is_empty() { fallback } else { name },
+        tracks.len(),
+        format_duration(duration)
+    )
+}
+
+fn enqueue_menu(response: Response, tracks: &[usize], actions: &mut Vec<BrowserAction>) {
+    response.context_menu(|ui| {
+        if ui.button("Add to Queue").clicked() {
+            actions.push(BrowserAction::Enqueue(tracks.to_vec()));
+            ui.close_menu();
+        }
+    });
+}
+
+fn browser_node(
+    ui: &mut Ui,
+    label: String,
+    filter: &BrowseFilter,
+    current: Option<&BrowseFilter>,
+    tracks: &[usize],
+    actions: &mut Vec<BrowserAction>,
+    add_children: impl FnOnce(&mut Ui, &mut Vec<BrowserAction>),
+) {
+    let id = ui.make_persistent_id(filter);
+    egui::collapsing_header::CollapsingState::load_with_default_open(ui.ctx(), id, false)
+        .show_header(ui, |ui| {
+            let response = ui.selectable_label(current == Some(filter), label);
+            if response.clicked() {
+                actions.push(BrowserAction::Select(filter.clone()));
+            }
+            enqueue_menu(response, tracks, actions);
+        })
+        .body(|ui| add_children(ui, actions));
+}
+
+fn artist_node(
+    ui: &mut Ui,
+    library: &[MusicFile],
+    artist: &ArtistNode,
+    filter: BrowseFilter,
+    current: Option<&BrowseFilter>,
+    actions: &mut Vec<BrowserAction>,
+) {
+    let label = node_label(
+        &artist.name,
+        "Unknown Artist",
+        &artist.tracks,
+        artist.duration,
+    );
+    browser_node(
+        ui,
+        label,
+        &filter,
+        current,
+        &artist.tracks,
+        actions,
+        |ui, actions| {
+            for album in &artist.albums {
+                let album_filter = BrowseFilter {
+                    album: Some(album.name.clone()),
+                    ..filter.clone()
+                };
+                let label = node_label(&album.name, "Unknown Album", &album.tracks, album.duration);
+                browser_node(
+                    ui,
+                    label,
+                    &album_filter,
+                    current,
+                    &album.tracks,
+                    actions,
+                    |ui, actions| {
+                        for &i in &album.tracks {
+                            let track = &library[i];
+                            let track_filter = BrowseFilter {
+                                track: Some(track.id),
+                                ..album_filter.clone()
+                            };
+                            let title = if track.title.is_empty() {
+                                &track.name
+                            } else {
+                                &track.title
+                            };
+                            let response = ui.selectable_label(
+                                current == Some(&track_filter),
+                                format!("{}  ({})", title, format_duration(track.duration)),
+                            );
+                            if response.clicked() {
+                                actions.push(BrowserAction::Select(track_filter));
+                            }
+                            if response.double_clicked() {
+                                actions.push(BrowserAction::Play(i));
+                            }
+                            enqueue_menu(response, &[i], actions);
+                        }
+                    },
+                );
+            }
+        },
+    );
+}
+
+//-------------------------------------------------------------------------------------------------
+// LibraryView enum
+// Which layout the central panel uses when showing the library
+// ------------------------------------------------------------------------------------------------
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LibraryView {
+    Tracks,
+    Albums,
+}
+
+// which rule based playlist the central panel is showing
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SmartView {
+    Favourites,
+    Saved(u32), // the id of a saved smart playlist
+}
+
+//-------------------------------------------------------------------------------------------------
+// PlaylistTracks struct
+// The tracks of the playlist on screen, looked up in the library for the library generation they
+// were made for. tracks holds the library index of each entry the table shows and positions where
+// that entry is in the playlist, rows of the table are turned back into both through them
+// ------------------------------------------------------------------------------------------------
+struct PlaylistTracks {
+    generation: u64,
+    id: u32,
+    positions: Vec<usize>,
+    tracks: Vec<usize>,
+    stats: PlaylistStats,
+}
+
+//-------------------------------------------------------------------------------------------------
+// TableRowsKey struct
+// Everything the rows of the track table on screen depend on. The sorted rows are cached together
+// with their key and reused for as long as the key stays the same
+// ------------------------------------------------------------------------------------------------
+#[derive(Clone, PartialEq, Debug)]
+struct TableRowsKey {
+    view: String,
+    generation: u64,
+    len: usize,
+    query: String,
+    filter: Option<BrowseFilter>,
+    sort: Vec<SortRule>,
 }
 
 //-------------------------------------------------------------------------------------------------
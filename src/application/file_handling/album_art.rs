@@ -0,0 +1,281 @@
+use audiotags::Tag;
+use egui::{ColorImage, Context, TextureHandle, TextureOptions};
+use image::DynamicImage;
+#[cfg(not(target_arch = "wasm32"))]
+use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::mpsc::{channel, Receiver, Sender};
+#[cfg(not(target_arch = "wasm32"))]
+use std::thread;
+use std::time::UNIX_EPOCH;
+
+// size (in pixels) of the longest side of a cached thumbnail
+pub const THUMBNAIL_SIZE: u32 = 256;
+
+// how many textures the loader keeps before dropping the least recently shown ones
+pub const TEXTURE_CACHE_SIZE: usize = 512;
+
+// sidecar image names we look for next to a track, in order of preference
+const SIDECAR_NAMES: [&str; 6] = [
+    "folder.jpg",
+    "folder.png",
+    "cover.jpg",
+    "cover.png",
+    "front.jpg",
+    "front.png",
+];
+
+pub fn read_embedded_art(path: &Path) -> Option<Vec<u8>> {
+    let tag = Tag::new().read_from_path(path).ok()?;
+    tag.album_cover().map(|picture| picture.data.to_vec())
+}
+
+pub fn find_sidecar_art(path: &Path) -> Option<PathBuf> {
+    // -----------------------------------------------------------------------------------------------
+    // looks for folder.jpg / cover.png etc. in the same directory as the track. file names are
+    // compared case-insensitively since "Folder.jpg" and "COVER.PNG" are both common
+    // -----------------------------------------------------------------------------------------------
+    let entries: Vec<PathBuf> = fs::read_dir(path.parent()?)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .collect();
+
+    SIDECAR_NAMES.iter().find_map(|name| {
+        entries
+            .iter()
+            .find(|p| {
+                p.file_name()
+                    .map(|n| n.to_string_lossy().to_lowercase() == *name)
+                    .unwrap_or(false)
+            })
+            .cloned()
+    })
+}
+
+pub fn default_cache_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("eguiRustAudio")
+        .join("thumbnails")
+}
+
+fn cache_file_for(cache_dir: &Path, track_path: &Path) -> PathBuf {
+    // -----------------------------------------------------------------------------------------------
+    // the modification time is part of the key so re-tagged files get a fresh thumbnail. the name is
+    // an FNV-1a hash, which (unlike std's DefaultHasher) stays the same between Rust releases, so
+    // the thumbnails cached by an older build are still found
+    // -----------------------------------------------------------------------------------------------
+    let mut key = track_path.to_string_lossy().into_owned();
+    if let Ok(modified) = fs::metadata(track_path).and_then(|m| m.modified()) {
+        if let Ok(since_epoch) = modified.duration_since(UNIX_EPOCH) {
+            key.push_str(&format!("\n{}", since_epoch.as_secs()));
+        }
+    }
+    cache_dir.join(format!("{:016x}.png", fnv1a(key.as_bytes())))
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+fn decode_art(track_path: &Path) -> Option<DynamicImage> {
+    let data = read_embedded_art(track_path)
+        .or_else(|| find_sidecar_art(track_path).and_then(|p| fs::read(p).ok()))?;
+    let image = image::load_from_memory(&data).ok()?;
+    Some(image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE))
+}
+
+pub fn load_thumbnail(cache_dir: &Path, track_path: &Path) -> Option<ColorImage> {
+    // -----------------------------------------------------------------------------------------------
+    // returns the downscaled cover art for a track, decoding and caching it on disk the first time.
+    // this does file IO and image decoding, so it should never be called from the UI thread
+    // -----------------------------------------------------------------------------------------------
+    let cache_file = cache_file_for(cache_dir, track_path);
+    let image = match image::open(&cache_file) {
+        Ok(image) => image,
+        Err(_) => {
+            let image = decode_art(track_path)?;
+            if fs::create_dir_all(cache_dir).is_ok() {
+                if let Err(e) = image.save(&cache_file) {
+                    eprintln!("could not cache thumbnail {:?}: {}", cache_file, e);
+                }
+            }
+            image
+        }
+    };
+    Some(color_image(&image))
+}
+
+fn color_image(image: &DynamicImage) -> ColorImage {
+    let rgba = image.to_rgba8();
+    ColorImage::from_rgba_unmultiplied(
+        [rgba.width() as usize, rgba.height() as usize],
+        rgba.as_raw(),
+    )
+}
+
+//---------------------------------------------------------------------------------------------------
+// AlbumArtLoader struct
+// Loads cover art on a background thread and keeps the resulting textures around. The UI asks for
+// a texture every frame, the first request queues the track on the worker thread and returns None
+// until the thumbnail has been decoded. Tracks without any art are remembered as None as well, so
+// they are only looked up once. At most TEXTURE_CACHE_SIZE textures are kept, the ones that haven't
+// been asked for the longest are dropped first. The worker thread is only started by the first
+// request, so a loader that is never used costs nothing. The web build has no threads (or disk
+// cache), so there the art is decoded right away when it is first asked for.
+// --------------------------------------------------------------------------------------------------
+pub struct AlbumArtLoader {
+    #[cfg(not(target_arch = "wasm32"))]
+    worker: Option<ArtWorker>,
+    #[cfg(not(target_arch = "wasm32"))]
+    pending: HashSet<PathBuf>,
+    // each texture with the time it was last asked for, and the same times in order so the least
+    // recently shown texture is found without going through all of them
+    textures: HashMap<PathBuf, (Option<TextureHandle>, u64)>,
+    last_used: BTreeMap<u64, PathBuf>,
+    clock: u64,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+struct ArtWorker {
+    requests: Sender<(PathBuf, Context)>,
+    results: Receiver<(PathBuf, Option<ColorImage>)>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ArtWorker {
+    fn start() -> ArtWorker {
+        let (request_sender, request_receiver) = channel::<(PathBuf, Context)>();
+        let (result_sender, result_receiver) = channel();
+        let cache_dir = default_cache_dir();
+
+        thread::spawn(move || {
+            for (track_path, ctx) in request_receiver {
+                let thumbnail = load_thumbnail(&cache_dir, &track_path);
+                if result_sender.send((track_path, thumbnail)).is_err() {
+                    break;
+                }
+                ctx.request_repaint();
+            }
+        });
+
+        ArtWorker {
+            requests: request_sender,
+            results: result_receiver,
+        }
+    }
+}
+
+impl AlbumArtLoader {
+    pub fn new() -> AlbumArtLoader {
+        AlbumArtLoader {
+            #[cfg(not(target_arch = "wasm32"))]
+            worker: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            pending: HashSet::new(),
+            textures: HashMap::new(),
+            last_used: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn receive(&mut self, ctx: &Context) {
+        let results: Vec<(PathBuf, Option<ColorImage>)> = match &self.worker {
+            Some(worker) => worker.results.try_iter().collect(),
+            None => return,
+        };
+        for (track_path, thumbnail) in results {
+            self.pending.remove(&track_path);
+            self.store(ctx, track_path, thumbnail);
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn request(&mut self, ctx: &Context, track_path: &Path) {
+        if self.pending.insert(track_path.to_path_buf()) {
+            let worker = self.worker.get_or_insert_with(ArtWorker::start);
+            let _ = worker
+                .requests
+                .send((track_path.to_path_buf(), ctx.clone()));
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn request(&mut self, ctx: &Context, track_path: &Path) {
+        let thumbnail = decode_art(track_path).map(|image| color_image(&image));
+        self.store(ctx, track_path.to_path_buf(), thumbnail);
+    }
+
+    fn store(&mut self, ctx: &Context, track_path: PathBuf, thumbnail: Option<ColorImage>) {
+        let texture = thumbnail.map(|image| {
+            ctx.load_texture(track_path.to_string_lossy(), image, TextureOptions::LINEAR)
+        });
+        // every texture gets a time of its own, several can come in between two requests
+        self.clock += 1;
+        self.last_used.insert(self.clock, track_path.clone());
+        if let Some((_, replaced)) = self.textures.insert(track_path, (texture, self.clock)) {
+            self.last_used.remove(&replaced);
+        }
+
+        while self.textures.len() > TEXTURE_CACHE_SIZE {
+            let oldest = match self.last_used.keys().next() {
+                Some(&oldest) => oldest,
+                None => break,
+            };
+            if let Some(path) = self.last_used.remove(&oldest) {
+                self.textures.remove(&path);
+            }
+        }
+    }
+
+    pub fn texture(&mut self, ctx: &Context, track_path: &Path) -> Option<&TextureHandle> {
+        self.clock += 1;
+        #[cfg(not(target_arch = "wasm32"))]
+        self.receive(ctx);
+
+        match self.textures.get_mut(track_path) {
+            Some((_, last_used)) => {
+                let path = self.last_used.remove(last_used);
+                *last_used = self.clock;
+                if let Some(path) = path {
+                    self.last_used.insert(self.clock, path);
+                }
+            }
+            None => self.request(ctx, track_path),
+        }
+
+        self.textures.get(track_path).and_then(|(t, _)| t.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fnv1a_matches_the_reference_values() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn cache_file_names_are_stable() {
+        let cache_dir = Path::new("/cache");
+        let track = Path::new("/no/such/dir/track.mp3");
+        assert_eq!(
+            cache_file_for(cache_dir, track),
+            cache_dir.join(format!("{:016x}.png", fnv1a(b"/no/such/dir/track.mp3")))
+        );
+    }
+}
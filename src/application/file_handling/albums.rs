@@ -0,0 +1,77 @@
+use super::file_handling::MusicFile;
+use std::collections::BTreeMap;
+
+//---------------------------------------------------------------------------------------------------
+// AlbumGroup struct
+// A single album in the library, identified by (album artist, album). tracks holds indices into
+// the music library, already sorted in disc/track order.
+// --------------------------------------------------------------------------------------------------
+#[derive(Clone, Debug)]
+pub struct AlbumGroup {
+    pub album_artist: String,
+    pub album: String,
+    pub tracks: Vec<usize>,
+}
+
+impl AlbumGroup {
+    pub fn key(&self) -> (String, String) {
+        (self.album_artist.clone(), self.album.clone())
+    }
+
+    pub fn duration(&self, library: &[MusicFile]) -> f64 {
+        self.tracks
+            .iter()
+            .filter_map(|&i| library.get(i))
+            .map(|m| m.duration)
+            .sum()
+    }
+}
+
+// falls back to the track artist when a file has no album artist tag
+pub fn album_artist_of(music_file: &MusicFile) -> &str {
+    if music_file.album_artist.is_empty() {
+        &music_file.artist
+    } else {
+        &music_file.album_artist
+    }
+}
+
+pub fn group_albums(library: &[MusicFile]) -> Vec<AlbumGroup> {
+    // -----------------------------------------------------------------------------------------------
+    // groups the library by (album artist, album). grouping is case-insensitive so "The Album" and
+    // "the album" end up together, the spelling of the first track is the one that gets displayed
+    // -----------------------------------------------------------------------------------------------
+    let mut groups: BTreeMap<(String, String), AlbumGroup> = BTreeMap::new();
+
+    for (i, music_file) in library.iter().enumerate() {
+        let album_artist = album_artist_of(music_file);
+        let key = (album_artist.to_lowercase(), music_file.album.to_lowercase());
+        groups
+            .entry(key)
+            .or_insert_with(|| AlbumGroup {
+                album_artist: album_artist.to_string(),
+                album: music_file.album.clone(),
+                tracks: Vec::new(),
+            })
+            .tracks
+            .push(i);
+    }
+
+    let mut albums: Vec<AlbumGroup> = groups.into_values().collect();
+    for album in &mut albums {
+        album.tracks.sort_by(|&a, &b| {
+            let (a, b) = (&library[a], &library[b]);
+            (
+                a.disc_number.unwrap_or(1),
+                a.track_number.unwrap_or(u16::MAX),
+                &a.name,
+            )
+                .cmp(&(
+                    b.disc_number.unwrap_or(1),
+                    b.track_number.unwrap_or(u16::MAX),
+                    &b.name,
+                ))
+        });
+    }
+    albums
+}
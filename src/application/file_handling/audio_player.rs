@@ -11,12 +11,15 @@ use rodio::{source::Source, Decoder, OutputStream, Sample, Sink};
 
 // this is the audio handler, it is responsible for handling all audio related tasks
 pub struct AudioHandler {
-    pub sink: Sink,           // controls audio playback to the OS
-    pub stream: OutputStream, // output stream for audio
+    pub sink: Sink,                   // controls audio playback to the OS
+    pub stream: Option<OutputStream>, // output stream for audio [None when idle]
     // pub samples_for_viz: Vec<f32>,      // Samples for visualization [no longer used]]
     pub sample_index: Arc<AtomicUsize>, // Atomic iterator/index [for playback position tracking]
     pub duration: u64,                  // total number of samples
     pub circular_buffer: Arc<Mutex<CircularBuffer<2048, f32>>>, // reference to cicrular buffer for audio data [used for the visualizer]
+    pub loads: usize, // how many files have been loaded [lets the listening history tell plays apart]
+    pub sample_rate: u32, // of the loaded file [to turn the sample index into a time]
+    pub channels: u16,
 }
 
 impl AudioHandler {
@@ -24,31 +27,86 @@ impl AudioHandler {
         let (stream, stream_handle) = OutputStream::try_default().unwrap();
         AudioHandler {
             sink: Sink::try_new(&stream_handle).unwrap(),
-            stream,
+            stream: Some(stream),
+            ..AudioHandler::idle()
+        }
+    }
+
+    pub fn idle() -> AudioHandler {
+        // -----------------------------------------------------------------------------------------------
+        // a handler that isn't connected to an output device and plays nothing. it stands in for the
+        // real one while saved state is loaded, so loading a profile doesn't open the device again
+        // -----------------------------------------------------------------------------------------------
+        let (sink, _) = Sink::new_idle();
+        AudioHandler {
+            sink,
+            stream: None,
             sample_index: Arc::new(AtomicUsize::new(0)),
             duration: 0,
             circular_buffer: Arc::new(Mutex::new(CircularBuffer::<2048, f32>::new())),
+            loads: 0,
+            sample_rate: 0,
+            channels: 0,
         }
     }
 
-    pub fn load_file(&mut self, path: &Path) {
+    pub fn load_file(&mut self, path: &Path) -> Result<(), String> {
         // -----------------------------------------------------------------------------------------------
         // load a music fine and append it to the sink
         // Path should be fetch from a music file object
+        // fails if the file has been moved or deleted, or can't be decoded
+        // -----------------------------------------------------------------------------------------------
+        self.append_file(path, Duration::ZERO)?;
+        self.loads += 1;
+        Ok(())
+    }
+
+    pub fn seek(&mut self, path: &Path, seconds: f64) -> Result<(), String> {
         // -----------------------------------------------------------------------------------------------
-        let file_for_playback = File::open(path).unwrap();
-        let source_for_playback = Decoder::new(BufReader::new(file_for_playback)).unwrap();
+        // rodio can't jump around in a source that is already playing, so the file is loaded again and
+        // decoded up to the new position. it's still the same play, so loads isn't counted up
+        // -----------------------------------------------------------------------------------------------
+        let paused = self.sink.is_paused();
+        self.sink.stop();
+        self.append_file(path, Duration::from_secs_f64(seconds.max(0.0)))?;
+        if paused {
+            self.sink.pause();
+        }
+        Ok(())
+    }
+
+    pub fn position(&self) -> f64 {
+        // seconds into the current file
+        let samples_per_second = self.sample_rate as f64 * self.channels as f64;
+        if samples_per_second == 0.0 {
+            return 0.0;
+        }
+        self.sample_index.load(Ordering::Relaxed) as f64 / samples_per_second
+    }
+
+    fn append_file(&mut self, path: &Path, start: Duration) -> Result<(), String> {
+        let file_for_playback = File::open(path).map_err(|e| e.to_string())?;
+        let source_for_playback =
+            Decoder::new(BufReader::new(file_for_playback)).map_err(|e| e.to_string())?;
+        self.sample_rate = source_for_playback.sample_rate();
+        self.channels = source_for_playback.channels();
         let buffer = self.circular_buffer.clone();
-        let converted_samples = source_for_playback.convert_samples::<f32>();
+        let converted_samples = source_for_playback
+            .convert_samples::<f32>()
+            .skip_duration(start);
         // -----------------------------------------------------------------------------------------------
         // i may have to do some kind of hack here to get the duration of the song,
         // involving extracting a raw vector of all the sample data, getting the length then slapping
         // it back into a decoder object and so i can throw it back into an indexed source
         // -----------------------------------------------------------------------------------------------
         let (indexed_source, sample_index) = IndexedSource::new(converted_samples, buffer);
+        // count the skipped samples too, so the index is still the position in the whole file
+        let skipped = start.as_secs_f64() * self.sample_rate as f64 * self.channels as f64;
+        sample_index.store(skipped as usize, Ordering::Relaxed);
         self.sample_index = sample_index.clone();
         self.sink.append(indexed_source);
         self.sink.play();
+        Ok(())
     }
 
     pub fn pause_playback(&mut self) {
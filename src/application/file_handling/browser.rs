@@ -0,0 +1,165 @@
+use super::albums::album_artist_of;
+use super::file_handling::MusicFile;
+use std::collections::{BTreeMap, HashMap};
+
+//---------------------------------------------------------------------------------------------------
+// BrowseFilter struct
+// The node that is selected in the left panel browser. Every level of the tree adds one more field,
+// e.g. selecting an album under an artist gives { artist, album }, and under a genre
+// { genre, artist, album }. A track matches when it matches every field that is set. A selected
+// track is kept by its id, so the filter still means the same track after the library changes.
+// --------------------------------------------------------------------------------------------------
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BrowseFilter {
+    pub genre: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub track: Option<u32>, // track id
+}
+
+impl BrowseFilter {
+    pub fn matches(&self, music_file: &MusicFile) -> bool {
+        let same = |filter: &Option<String>, value: &str| match filter {
+            Some(filter) => filter.to_lowercase() == value.to_lowercase(),
+            None => true,
+        };
+        same(&self.genre, &music_file.genre)
+            && same(&self.artist, album_artist_of(music_file))
+            && same(&self.album, &music_file.album)
+            && self.track.map_or(true, |track| track == music_file.id)
+    }
+
+    pub fn label(&self, library: &[MusicFile], positions: &HashMap<u32, usize>) -> String {
+        let mut parts: Vec<String> = Vec::new();
+        if let Some(genre) = &self.genre {
+            parts.push(genre.clone());
+        }
+        if let Some(artist) = &self.artist {
+            parts.push(artist.clone());
+        }
+        if let Some(album) = &self.album {
+            parts.push(album.clone());
+        }
+        let track = self.track.and_then(|id| positions.get(&id));
+        if let Some(track) = track.and_then(|&i| library.get(i)) {
+            parts.push(if track.title.is_empty() {
+                track.name.clone()
+            } else {
+                track.title.clone()
+            });
+        }
+        parts.join(" > ")
+    }
+}
+
+//---------------------------------------------------------------------------------------------------
+// Browser tree nodes
+// Each node keeps the library indices of every track under it, so counts, durations and "Add to
+// Queue" don't have to walk the tree again
+// --------------------------------------------------------------------------------------------------
+#[derive(Clone, Debug)]
+pub struct AlbumNode {
+    pub name: String,
+    pub tracks: Vec<usize>,
+    pub duration: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct ArtistNode {
+    pub name: String,
+    pub albums: Vec<AlbumNode>,
+    pub tracks: Vec<usize>,
+    pub duration: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct GenreNode {
+    pub name: String,
+    pub artists: Vec<ArtistNode>,
+    pub tracks: Vec<usize>,
+    pub duration: f64,
+}
+
+pub struct LibraryBrowser {
+    pub artists: Vec<ArtistNode>,
+    pub genres: Vec<GenreNode>,
+}
+
+impl LibraryBrowser {
+    pub fn build(library: &[MusicFile]) -> LibraryBrowser {
+        let all: Vec<usize> = (0..library.len()).collect();
+
+        let mut genres: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+        for (i, music_file) in library.iter().enumerate() {
+            genres
+                .entry(music_file.genre.to_lowercase())
+                .or_default()
+                .push(i);
+        }
+
+        LibraryBrowser {
+            artists: build_artists(library, &all),
+            genres: genres
+                .into_values()
+                .map(|tracks| GenreNode {
+                    name: library[tracks[0]].genre.clone(),
+                    artists: build_artists(library, &tracks),
+                    duration: total_duration(library, &tracks),
+                    tracks,
+                })
+                .collect(),
+        }
+    }
+}
+
+fn total_duration(library: &[MusicFile], tracks: &[usize]) -> f64 {
+    tracks.iter().map(|&i| library[i].duration).sum()
+}
+
+fn build_artists(library: &[MusicFile], tracks: &[usize]) -> Vec<ArtistNode> {
+    // -----------------------------------------------------------------------------------------------
+    // groups the given tracks into artist -> album -> track, case-insensitively and sorted by name.
+    // tracks inside an album are kept in disc/track order
+    // -----------------------------------------------------------------------------------------------
+    let mut artists: BTreeMap<String, BTreeMap<String, Vec<usize>>> = BTreeMap::new();
+    for &i in tracks {
+        let music_file = &library[i];
+        artists
+            .entry(album_artist_of(music_file).to_lowercase())
+            .or_default()
+            .entry(music_file.album.to_lowercase())
+            .or_default()
+            .push(i);
+    }
+
+    artists
+        .into_values()
+        .map(|albums| {
+            let albums: Vec<AlbumNode> = albums
+                .into_values()
+                .map(|mut tracks| {
+                    tracks.sort_by_key(|&i| {
+                        let m = &library[i];
+                        (
+                            m.disc_number.unwrap_or(1),
+                            m.track_number.unwrap_or(u16::MAX),
+                            m.name.clone(),
+                        )
+                    });
+                    AlbumNode {
+                        name: library[tracks[0]].album.clone(),
+                        duration: total_duration(library, &tracks),
+                        tracks,
+                    }
+                })
+                .collect();
+            let tracks: Vec<usize> = albums.iter().flat_map(|a| a.tracks.clone()).collect();
+            ArtistNode {
+                name: album_artist_of(&library[tracks[0]]).to_string(),
+                duration: total_duration(library, &tracks),
+                albums,
+                tracks,
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,385 @@
+use super::file_handling::{format_duration, MusicFile};
+use super::history::format_date;
+use super::query::{Field, FieldKind};
+use super::smart_playlist::SortRule;
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+// how many previously clicked columns are kept as tie-breakers for the current sort
+const MAX_SORT_KEYS: usize = 3;
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct ColumnSetting {
+    pub field: Field,
+    pub width: f32,
+    pub visible: bool,
+}
+
+//---------------------------------------------------------------------------------------------------
+// ColumnLayout struct
+// The columns of a track table: their order, width and whether they are shown, plus the current
+// sort. Every field is always in the list (hidden ones included), so showing a column again puts it
+// back where it was.
+//
+// sort holds the most recently clicked columns, newest first. The first one is the sort the user
+// sees, the others break ties, so sorting by album after sorting by track number keeps each album
+// in track order. Rows that are equal on every key keep their original order.
+// --------------------------------------------------------------------------------------------------
+#[derive(serde::Serialize, serde::Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct ColumnLayout {
+    pub columns: Vec<ColumnSetting>,
+    pub sort: Vec<SortRule>,
+}
+
+impl Default for ColumnLayout {
+    fn default() -> Self {
+        ColumnLayout::new(&[
+            Field::Favourite,
+            Field::Title,
+            Field::Artist,
+            Field::Album,
+            Field::Duration,
+            Field::Rating,
+        ])
+    }
+}
+
+fn column(field: Field, width: f32, visible: bool) -> ColumnSetting {
+    ColumnSetting {
+        field,
+        width,
+        visible,
+    }
+}
+
+impl ColumnLayout {
+    pub fn new(visible: &[Field]) -> ColumnLayout {
+        let mut layout = ColumnLayout {
+            columns: visible
+                .iter()
+                .map(|&field| column(field, default_width(field), true))
+                .collect(),
+            sort: Vec::new(),
+        };
+        layout.add_missing_fields();
+        layout
+    }
+
+    pub fn add_missing_fields(&mut self) {
+        // fields added after the layout was saved show up as hidden columns at the end
+        for field in Field::ALL {
+            if !self.columns.iter().any(|c| c.field == field) {
+                self.columns
+                    .push(column(field, default_width(field), false));
+            }
+        }
+    }
+
+    pub fn visible(&self) -> Vec<ColumnSetting> {
+        self.columns.iter().filter(|c| c.visible).copied().collect()
+    }
+
+    pub fn id(&self) -> u64 {
+        // changes whenever the visible columns or their order change, so the table widget doesn't
+        // reuse the widths it remembered for a different set of columns
+        let mut hasher = DefaultHasher::new();
+        for column in self.columns.iter().filter(|c| c.visible) {
+            column.field.hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    pub fn set_visible(&mut self, field: Field, visible: bool) {
+        if let Some(column) = self.columns.iter_mut().find(|c| c.field == field) {
+            column.visible = visible;
+        }
+    }
+
+    pub fn set_width(&mut self, field: Field, width: f32) {
+        if let Some(column) = self.columns.iter_mut().find(|c| c.field == field) {
+            column.width = width;
+        }
+    }
+
+    pub fn move_column(&mut self, field: Field, left: bool) {
+        // swaps the column with its nearest visible neighbour, hidden columns are skipped
+        let from = match self.columns.iter().position(|c| c.field == field) {
+            Some(from) => from,
+            None => return,
+        };
+        let to = if left {
+            self.columns[..from].iter().rposition(|c| c.visible)
+        } else {
+            self.columns[from + 1..]
+                .iter()
+                .position(|c| c.visible)
+                .map(|i| from + 1 + i)
+        };
+        if let Some(to) = to {
+            self.columns.swap(from, to);
+        }
+    }
+
+    pub fn sort_direction(&self, field: Field) -> Option<bool> {
+        // Some(descending) when the table is currently sorted by this field
+        self.sort
+            .first()
+            .filter(|rule| rule.field == field)
+            .map(|rule| rule.descending)
+    }
+
+    pub fn click_header(&mut self, field: Field) {
+        // cycles ascending -> descending -> unsorted
+        match self.sort_direction(field) {
+            Some(false) => self.sort[0].descending = true,
+            Some(true) => self.sort.clear(),
+            None => {
+                self.sort.retain(|rule| rule.field != field);
+                self.sort.insert(
+                    0,
+                    SortRule {
+                        field,
+                        descending: false,
+                    },
+                );
+                self.sort.truncate(MAX_SORT_KEYS);
+            }
+        }
+    }
+
+    pub fn sort_rows<'a, T: Copy>(&self, rows: &mut [T], track: impl Fn(&T) -> &'a MusicFile) {
+        // the keys are worked out once per row rather than on every comparison
+        if self.sort.is_empty() {
+            return;
+        }
+        let mut keyed: Vec<(Vec<SortKey>, T)> = rows
+            .iter()
+            .map(|row| {
+                let music_file = track(row);
+                let keys = self
+                    .sort
+                    .iter()
+                    .map(|rule| SortKey::of(rule.field, music_file))
+                    .collect();
+                (keys, *row)
+            })
+            .collect();
+        keyed.sort_by(|(a, _), (b, _)| {
+            for ((rule, a), b) in self.sort.iter().zip(a).zip(b) {
+                let ordering = a.cmp(b);
+                let ordering = if rule.descending {
+                    ordering.reverse()
+                } else {
+                    ordering
+                };
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            Ordering::Equal
+        });
+        for (row, (_, sorted)) in rows.iter_mut().zip(keyed) {
+            *row = sorted;
+        }
+    }
+
+    pub fn albums() -> ColumnLayout {
+        ColumnLayout::new(&[
+            Field::Disc,
+            Field::Track,
+            Field::Title,
+            Field::Artist,
+            Field::Duration,
+        ])
+    }
+}
+
+// one column's value of a row, compared the same way as compare_field does for smart playlists
+enum SortKey {
+    Text(String),
+    Number(Option<f64>),
+    Date(Option<u64>),
+}
+
+impl SortKey {
+    fn of(field: Field, music_file: &MusicFile) -> SortKey {
+        match field {
+            // untitled tracks show their file name in the title column
+            Field::Title => SortKey::Text(display_title(music_file).to_lowercase()),
+            // by date rather than by how many days ago
+            Field::LastPlayed => SortKey::Date(music_file.last_played),
+            field => match field.kind() {
+                FieldKind::Text => SortKey::Text(field.text(music_file).to_lowercase()),
+                _ => SortKey::Number(field.number(music_file)),
+            },
+        }
+    }
+
+    fn cmp(&self, other: &SortKey) -> Ordering {
+        match (self, other) {
+            (SortKey::Text(a), SortKey::Text(b)) => a.cmp(b),
+            (SortKey::Number(a), SortKey::Number(b)) => a.partial_cmp(b).unwrap_or(Ordering::Equal),
+            (SortKey::Date(a), SortKey::Date(b)) => a.cmp(b),
+            _ => Ordering::Equal,
+        }
+    }
+}
+
+//---------------------------------------------------------------------------------------------------
+// The column layouts that are saved, one per kind of view
+// --------------------------------------------------------------------------------------------------
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ColumnLayouts {
+    pub library: ColumnLayout,
+    pub playlists: ColumnLayout,
+    pub smart_playlists: ColumnLayout,
+    pub albums: ColumnLayout,
+}
+
+impl Default for ColumnLayouts {
+    fn default() -> Self {
+        ColumnLayouts {
+            library: ColumnLayout::default(),
+            playlists: ColumnLayout::default(),
+            smart_playlists: ColumnLayout::default(),
+            albums: ColumnLayout::albums(),
+        }
+    }
+}
+
+impl ColumnLayouts {
+    pub fn add_missing_fields(&mut self) {
+        self.library.add_missing_fields();
+        self.playlists.add_missing_fields();
+        self.smart_playlists.add_missing_fields();
+        self.albums.add_missing_fields();
+    }
+}
+
+fn default_width(field: Field) -> f32 {
+    match field {
+        Field::Title | Field::Path => 250.0,
+        Field::Artist | Field::Album | Field::AlbumArtist | Field::Name => 200.0,
+        Field::Genre | Field::Composer | Field::Comment => 150.0,
+        Field::Track | Field::Disc => 50.0,
+        Field::Favourite => 40.0,
+        Field::LastPlayed => 100.0,
+        Field::Rating => 100.0,
+        _ => 80.0,
+    }
+}
+
+pub fn display_title(music_file: &MusicFile) -> &str {
+    if music_file.title.is_empty() {
+        &music_file.name
+    } else {
+        &music_file.title
+    }
+}
+
+pub fn column_header(field: Field) -> &'static str {
+    match field {
+        Field::Title => "Title",
+        Field::Artist => "Artist",
+        Field::Album => "Album",
+        Field::AlbumArtist => "Album Artist",
+        Field::Genre => "Genre",
+        Field::Composer => "Composer",
+        Field::Comment => "Comment",
+        Field::Name => "File Name",
+        Field::Path => "Location",
+        Field::Codec => "Format",
+        Field::Year => "Year",
+        Field::Track => "Track",
+        Field::Disc => "Disc",
+        Field::Duration => "Duration",
+        Field::Bitrate => "Bitrate",
+        Field::SampleRate => "Sample Rate",
+        Field::Channels => "Channels",
+        Field::Size => "Size",
+        Field::Rating => "Rating",
+        Field::Favourite => "\u{2665}",
+        Field::Plays => "Plays",
+        Field::LastPlayed => "Last Played",
+    }
+}
+
+pub fn cell_text(field: Field, music_file: &MusicFile) -> String {
+    match field {
+        Field::Title => display_title(music_file).to_string(),
+        Field::Duration => format_duration(music_file.duration),
+        Field::Size => format!("{:.1} MB", music_file.file_size as f64 / 1_000_000.0),
+        Field::Bitrate => music_file
+            .bitrate
+            .map(|b| format!("{} kbps", b))
+            .unwrap_or_default(),
+        Field::SampleRate => music_file
+            .sample_rate
+            .map(|r| format!("{:.1} kHz", r as f64 / 1000.0))
+            .unwrap_or_default(),
+        Field::Rating => stars(music_file.rating),
+        Field::Favourite if music_file.favourite => "\u{2665}".to_string(),
+        Field::Favourite => String::new(),
+        Field::LastPlayed => music_file.last_played.map(format_date).unwrap_or_default(),
+        field => field.text(music_file),
+    }
+}
+
+pub fn stars(rating: Option<u8>) -> String {
+    let rating = rating.unwrap_or(0).min(5) as usize;
+    "\u{2605}".repeat(rating) + &"\u{2606}".repeat(5 - rating)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(title: &str, name: &str, album: &str, track_number: Option<u16>) -> MusicFile {
+        MusicFile {
+            title: title.to_string(),
+            name: name.to_string(),
+            album: album.to_string(),
+            track_number,
+            ..Default::default()
+        }
+    }
+
+    fn sorted(layout: &ColumnLayout, tracks: &[MusicFile]) -> Vec<usize> {
+        let mut rows: Vec<usize> = (0..tracks.len()).collect();
+        layout.sort_rows(&mut rows, |&i| &tracks[i]);
+        rows
+    }
+
+    #[test]
+    fn title_sort_ignores_case_and_uses_the_file_name() {
+        let tracks = vec![
+            track("beta", "", "", None),
+            track("", "Alpha.mp3", "", None),
+            track("Gamma", "", "", None),
+        ];
+        let mut layout = ColumnLayout::default();
+        layout.click_header(Field::Title);
+        assert_eq!(sorted(&layout, &tracks), vec![1, 0, 2]);
+        layout.click_header(Field::Title);
+        assert_eq!(sorted(&layout, &tracks), vec![2, 0, 1]);
+    }
+
+    #[test]
+    fn older_sorts_break_ties_and_equal_rows_keep_their_order() {
+        let tracks = vec![
+            track("a", "", "B", Some(2)),
+            track("b", "", "A", Some(2)),
+            track("c", "", "B", Some(1)),
+            track("d", "", "A", None),
+            track("e", "", "A", Some(2)),
+        ];
+        let mut layout = ColumnLayout::default();
+        layout.click_header(Field::Track);
+        layout.click_header(Field::Album);
+        // tracks without a number come first, like in smart playlists
+        assert_eq!(sorted(&layout, &tracks), vec![3, 1, 4, 2, 0]);
+    }
+}
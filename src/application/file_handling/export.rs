@@ -0,0 +1,220 @@
+use super::file_handling::{MusicCollection, MusicFile};
+use super::history::format_date;
+use super::playlist_folders::PlaylistFolders;
+use super::query::{Field, FieldKind};
+use super::roots::LibraryRoots;
+use super::smart_playlist::SmartPlaylist;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+//---------------------------------------------------------------------------------------------------
+// ExportSettings struct
+// What the export dialog is set to. Tracks are written with the chosen fields, one row (CSV) or one
+// object (JSON) per track. A full backup ignores the fields and writes the whole library, every
+// playlist, smart playlist and the library roots as JSON using their serde derives, so it can be
+// restored with read_backup(). Backups made before smart playlists were saved in them have None.
+// --------------------------------------------------------------------------------------------------
+pub struct ExportSettings {
+    pub path: String,
+    pub format: ExportFormat,
+    pub fields: Vec<Field>,
+    pub current_view: bool, // only the tracks in the view on screen, in the order they are shown
+    pub full_backup: bool,
+}
+
+impl Default for ExportSettings {
+    fn default() -> Self {
+        ExportSettings {
+            path: String::new(),
+            format: ExportFormat::Csv,
+            fields: vec![
+                Field::Title,
+                Field::Artist,
+                Field::Album,
+                Field::Year,
+                Field::Genre,
+                Field::Duration,
+                Field::Rating,
+                Field::Plays,
+                Field::Path,
+            ],
+            current_view: false,
+            full_backup: false,
+        }
+    }
+}
+
+impl ExportSettings {
+    pub fn toggle_field(&mut self, field: Field, selected: bool) {
+        // keeps the fields in the order of Field::ALL, so the columns come out the same every time
+        self.fields.retain(|f| *f != field);
+        if selected {
+            self.fields.push(field);
+        }
+        self.fields
+            .sort_by_key(|f| Field::ALL.iter().position(|a| a == f));
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct LibraryBackup {
+    pub library: Vec<MusicFile>,
+    pub playlists: Vec<MusicCollection>,
+    pub folders: PlaylistFolders,
+    pub roots: LibraryRoots,
+    pub smart_playlists: Option<Vec<SmartPlaylist>>,
+}
+
+pub fn export_tracks(tracks: &[&MusicFile], settings: &ExportSettings) -> Result<(), String> {
+    let text = match settings.format {
+        ExportFormat::Csv => tracks_to_csv(tracks, &settings.fields),
+        ExportFormat::Json => {
+            let rows: Vec<Value> = tracks
+                .iter()
+                .map(|track| {
+                    Value::Object(
+                        settings
+                            .fields
+                            .iter()
+                            .map(|&field| (field.name().to_string(), export_value(field, track)))
+                            .collect(),
+                    )
+                })
+                .collect();
+            serde_json::to_string_pretty(&rows).map_err(|e| e.to_string())?
+        }
+    };
+    write(&settings.path, text)
+}
+
+pub fn export_backup(
+    library: &[MusicFile],
+    playlists: &[MusicCollection],
+    smart_playlists: &[SmartPlaylist],
+    folders: &PlaylistFolders,
+    roots: &LibraryRoots,
+    path: &str,
+) -> Result<(), String> {
+    let backup = LibraryBackup {
+        library: library.to_vec(),
+        playlists: playlists.to_vec(),
+        folders: folders.clone(),
+        roots: roots.clone(),
+        smart_playlists: Some(smart_playlists.to_vec()),
+    };
+    write(
+        path,
+        serde_json::to_string_pretty(&backup).map_err(|e| e.to_string())?,
+    )
+}
+
+pub fn read_backup(path: &str) -> Result<LibraryBackup, String> {
+    let text = fs::read_to_string(path.trim()).map_err(|e| e.to_string())?;
+    serde_json::from_str(&text).map_err(|e| format!("not a library backup: {}", e))
+}
+
+fn write(path: &str, text: String) -> Result<(), String> {
+    let path = Path::new(path.trim());
+    if path.as_os_str().is_empty() {
+        return Err("enter a file to export to".to_string());
+    }
+    fs::write(path, text).map_err(|e| format!("couldn't write {}: {}", path.display(), e))
+}
+
+fn export_value(field: Field, music_file: &MusicFile) -> Value {
+    // -----------------------------------------------------------------------------------------------
+    // raw values rather than what the grid shows, so spreadsheets can do maths on them: durations in
+    // seconds, sizes in megabytes, no units. dates are written as YYYY-MM-DD
+    // -----------------------------------------------------------------------------------------------
+    match field {
+        Field::Favourite => Value::Bool(music_file.favourite),
+        Field::LastPlayed => match music_file.last_played {
+            Some(time) => Value::String(format_date(time)),
+            None => Value::Null,
+        },
+        field if field.kind() == FieldKind::Text => Value::String(field.text(music_file)),
+        field => match field.number(music_file) {
+            Some(number) if number.fract() == 0.0 => Value::from(number as i64),
+            Some(number) => Value::from(number),
+            None => Value::Null,
+        },
+    }
+}
+
+fn tracks_to_csv(tracks: &[&MusicFile], fields: &[Field]) -> String {
+    let mut csv = String::new();
+    let header: Vec<String> = fields.iter().map(|f| csv_cell(f.name())).collect();
+    csv.push_str(&header.join(","));
+    csv.push_str("\r\n");
+    for track in tracks {
+        let row: Vec<String> = fields
+            .iter()
+            .map(|&field| match export_value(field, track) {
+                Value::String(text) => csv_cell(&text),
+                Value::Null => String::new(),
+                value => value.to_string(),
+            })
+            .collect();
+        csv.push_str(&row.join(","));
+        csv.push_str("\r\n");
+    }
+    csv
+}
+
+fn csv_cell(text: &str) -> String {
+    // quotes the cell when it has a comma, quote or line break in it (RFC 4180)
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_cells_are_quoted_when_needed() {
+        assert_eq!(csv_cell("plain text"), "plain text");
+        assert_eq!(csv_cell(""), "");
+        assert_eq!(csv_cell("a, b"), "\"a, b\"");
+        assert_eq!(csv_cell("12\" single"), "\"12\"\" single\"");
+        assert_eq!(csv_cell("two\nlines"), "\"two\nlines\"");
+        assert_eq!(csv_cell("two\r\nlines"), "\"two\r\nlines\"");
+    }
+
+    #[test]
+    fn csv_rows_end_with_crlf() {
+        let track = MusicFile {
+            title: "Hello, \"World\"".to_string(),
+            year: Some(1999),
+            ..Default::default()
+        };
+        let csv = tracks_to_csv(&[&track], &[Field::Title, Field::Year, Field::Track]);
+        assert_eq!(
+            csv,
+            format!(
+                "{},{},{}\r\n\"Hello, \"\"World\"\"\",1999,\r\n",
+                Field::Title.name(),
+                Field::Year.name(),
+                Field::Track.name()
+            )
+        );
+    }
+
+    #[test]
+    fn backups_without_smart_playlists_still_load() {
+        let backup: LibraryBackup =
+            serde_json::from_str(r#"{"library": [], "playlists": []}"#).unwrap();
+        assert!(backup.smart_playlists.is_none());
+    }
+}
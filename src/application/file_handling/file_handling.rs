@@ -1,66 +1,267 @@
+use super::history::now_secs;
+use super::ratings::read_rating;
+use super::smart_playlist::SmartPlaylist;
 use audiotags::Tag;
+use rodio::{Decoder, Source};
 use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
 use serde::ser::{Serialize, SerializeMap, SerializeSeq, SerializeStruct, Serializer};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fmt;
 use std::fs;
+use std::fs::File;
 use std::io;
-use std::path::PathBuf;
+use std::io::BufReader;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+#[cfg(not(target_arch = "wasm32"))]
+use std::thread;
+use std::time::UNIX_EPOCH;
 
 //---------------------------------------------------------------------------------------------------
 // MusicFile struct
 // This struct is used to store the metadata of a music file, as well as the location of the file.
+//
+// version is the schema version the entry was created with. Libraries saved before the field
+// existed load as version 0, every field added since then falls back to its default, and
+// LibraryUpgrade re-reads the tags of any outdated entry to fill them in.
+//
+// id identifies the track for as long as it is in the library, playlists refer to tracks by it. New
+// entries come in with id 0 and get theirs from assign_track_ids() once they are in the library.
+// Ids come from IdCounters and are never handed out twice, not even after the track is removed.
 // --------------------------------------------------------------------------------------------------
-#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub const MUSIC_FILE_VERSION: u32 = 2;
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+#[serde(default)]
 pub struct MusicFile {
+    pub version: u32,
+    pub id: u32,
     pub name: String,
     pub file_path: PathBuf,
+    pub root: Option<u32>, // the library root the file is under, see roots.rs
+    pub relative_path: String, // the path under that root, / separated
     pub title: String,
     pub artist: String,
     pub duration: f64,
     pub album: String,
+    pub track_number: Option<u16>,
+    pub disc_number: Option<u16>,
+    pub year: Option<i32>,
+    pub genre: String,
+    pub album_artist: String,
+    pub composer: String,
+    pub comment: String,
+    pub bitrate: Option<u32>, // kbps
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u16>,
+    pub codec: String,
+    pub file_size: u64,
+    pub modified: Option<u64>, // seconds since the unix epoch
+    pub rating: Option<u8>,    // 1-5 stars
+    pub favourite: bool,
+    pub play_count: u32,
+    pub last_played: Option<u64>, // seconds since the unix epoch
 }
 
-pub fn get_from_path(path_string: &str) -> Vec<MusicFile> {
+pub fn read_music_file(path: &Path) -> audiotags::Result<MusicFile> {
+    // -----------------------------------------------------------------------------------------------
+    // reads the tags of a single file, along with the audio properties (sample rate, channels) from
+    // the decoder and the size/modification time from the file system
+    // -----------------------------------------------------------------------------------------------
+    let tag = Tag::new().read_from_path(path)?;
+    let metadata = fs::metadata(path).ok();
+    let file_size = metadata.as_ref().map(|m| m.len()).unwrap_or_default();
+    let modified = metadata
+        .and_then(|m| m.modified().ok())
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs());
+
+    let decoder = File::open(path)
+        .ok()
+        .and_then(|file| Decoder::new(BufReader::new(file)).ok());
+    let sample_rate = decoder.as_ref().map(|d| d.sample_rate());
+    let channels = decoder.as_ref().map(|d| d.channels());
+    let duration = tag
+        .duration()
+        .or_else(|| decoder.as_ref()?.total_duration().map(|d| d.as_secs_f64()))
+        .unwrap_or_default();
+    let bitrate = if duration > 0.0 {
+        Some((file_size as f64 * 8.0 / duration / 1000.0).round() as u32)
+    } else {
+        None
+    };
+
+    Ok(MusicFile {
+        version: MUSIC_FILE_VERSION,
+        id: 0,
+        name: path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        file_path: path.to_path_buf(),
+        root: None,
+        relative_path: String::new(),
+        title: tag.title().map(|s| s.to_string()).unwrap_or_default(),
+        artist: tag
+            .artists()
+            .map(|artists| artists.join(", "))
+            .unwrap_or_default(),
+        duration,
+        album: tag.album_title().map(|s| s.to_string()).unwrap_or_default(),
+        track_number: tag.track_number(),
+        disc_number: tag.disc_number(),
+        year: tag.year(),
+        genre: tag.genre().map(|s| s.to_string()).unwrap_or_default(),
+        album_artist: tag
+            .album_artist()
+            .map(|s| s.to_string())
+            .unwrap_or_default(),
+        composer: tag.composer().map(|s| s.to_string()).unwrap_or_default(),
+        comment: tag.comment().map(|s| s.to_string()).unwrap_or_default(),
+        bitrate,
+        sample_rate,
+        channels,
+        codec: path
+            .extension()
+            .map(|e| e.to_string_lossy().to_uppercase())
+            .unwrap_or_default(),
+        file_size,
+        modified,
+        rating: read_rating(path),
+        favourite: false,
+        play_count: 0,
+        last_played: None,
+    })
+}
+
+pub fn unread_music_file(path: &Path) -> MusicFile {
+    // an entry for a file whose tags haven't been read yet. it is outdated (version 0), so it shows
+    // up under its file name until LibraryUpgrade has read it in the background
+    MusicFile {
+        version: 0,
+        name: path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        file_path: path.to_path_buf(),
+        ..Default::default()
+    }
+}
+
+pub fn get_from_path(path_string: &str) -> Result<Vec<MusicFile>, String> {
     // -----------------------------------------------------------------------------------------------
     // ** might need to change the way this works. currently, trying to add more music to the library
     // after this function has already been called will overwrite the existing music lbrary. this needs
     // to be changed so a Vec<MusicFile> is created outside this function and is passed in by reference
     // and then we push the new music files to the existing Vec<MusicFile> **
+    // a single file is read on its own, and a folder that can't be read is an error for the caller to
+    // show. files in the folder that aren't music are skipped
     // -----------------------------------------------------------------------------------------------
+    let path = Path::new(path_string.trim());
+    if path.is_file() {
+        return read_music_file(path)
+            .map(|music| vec![music])
+            .map_err(|e| format!("could not read {}: {}", path.display(), e));
+    }
+
     let mut music_files: Vec<MusicFile> = Vec::new();
 
-    let paths = fs::read_dir(path_string).unwrap();
+    let paths =
+        fs::read_dir(path).map_err(|e| format!("could not open {}: {}", path.display(), e))?;
 
-    for p in paths {
-        let dir_entry = p.unwrap();
-        let tag = Tag::new().read_from_path(dir_entry.path().clone()).unwrap();
+    for dir_entry in paths.filter_map(|p| p.ok()) {
+        if let Ok(music) = read_music_file(&dir_entry.path()) {
+            music_files.push(music);
+        }
+    }
 
-        let song_title = tag.title().map(|s| s.to_string()).unwrap_or_default();
-        let song_artist = tag
-            .artists()
-            .map(|artists| artists.join(", "))
-            .unwrap_or_default();
-        let song_duration = tag.duration().unwrap_or_default();
-        let song_album = tag.album_title().map(|s| s.to_string()).unwrap_or_default();
-
-        let music = MusicFile {
-            name: dir_entry
-                .path()
-                .file_name()
-                .unwrap()
-                .to_string_lossy()
-                .to_string(),
-            file_path: dir_entry.path().clone(),
-            title: song_title,
-            duration: song_duration,
-            artist: song_artist,
-            album: song_album,
+    Ok(music_files)
+}
+
+//---------------------------------------------------------------------------------------------------
+// LibraryUpgrade struct
+// Brings entries saved with an older MusicFile schema up to date by reading their tags again. That
+// means decoding every outdated file, so it happens on a background thread and the tracks are
+// updated as they come in. Files that can't be read anymore keep whatever was saved for them, and
+// things that only live in the library (the id, favourites, ratings that aren't in the tags, play
+// counts, the library root) are carried over. The web build has no threads, so there the files are
+// read before start() returns.
+// --------------------------------------------------------------------------------------------------
+pub struct LibraryUpgrade {
+    results: Receiver<(u32, Option<MusicFile>)>,
+    pub finished: bool,
+}
+
+impl LibraryUpgrade {
+    pub fn start(library: &[MusicFile], ctx: &egui::Context) -> Option<LibraryUpgrade> {
+        let outdated: Vec<(u32, PathBuf)> = library
+            .iter()
+            .filter(|music_file| music_file.version < MUSIC_FILE_VERSION)
+            .map(|music_file| (music_file.id, music_file.file_path.clone()))
+            .collect();
+        if outdated.is_empty() {
+            return None;
+        }
+
+        let (sender, receiver) = channel();
+        let ctx = ctx.clone();
+        let work = move || {
+            for (id, path) in outdated {
+                if sender.send((id, read_music_file(&path).ok())).is_err() {
+                    break;
+                }
+                ctx.request_repaint();
+            }
         };
+        #[cfg(not(target_arch = "wasm32"))]
+        thread::spawn(work);
+        #[cfg(target_arch = "wasm32")]
+        work();
 
-        music_files.push(music);
+        Some(LibraryUpgrade {
+            results: receiver,
+            finished: false,
+        })
     }
 
-    music_files
+    pub fn receive(&mut self, library: &mut [MusicFile]) -> bool {
+        // updates the tracks that were read since the last call, returns whether any of them changed
+        let mut changed = false;
+        loop {
+            let (id, upgraded) = match self.results.try_recv() {
+                Ok(result) => result,
+                Err(TryRecvError::Empty) => return changed,
+                Err(TryRecvError::Disconnected) => {
+                    self.finished = true;
+                    return changed;
+                }
+            };
+            let music_file = match library.iter_mut().find(|m| m.id == id) {
+                Some(music_file) if music_file.version < MUSIC_FILE_VERSION => music_file,
+                _ => continue,
+            };
+            match upgraded {
+                Some(mut upgraded) => {
+                    upgraded.favourite = music_file.favourite;
+                    upgraded.rating = upgraded.rating.or(music_file.rating);
+                    upgraded.id = music_file.id;
+                    upgraded.play_count = music_file.play_count;
+                    upgraded.last_played = music_file.last_played;
+                    upgraded.root = music_file.root;
+                    upgraded.relative_path = music_file.relative_path.clone();
+                    *music_file = upgraded;
+                }
+                None => music_file.version = MUSIC_FILE_VERSION,
+            }
+            changed = true;
+        }
+    }
+}
+
+pub fn format_duration(seconds: f64) -> String {
+    let total = seconds.max(0.0).round() as u64;
+    format!("{}:{:02}", total / 60, total % 60)
 }
 
 pub fn new_library() -> Vec<MusicFile> {
@@ -68,32 +269,338 @@ pub fn new_library() -> Vec<MusicFile> {
     library
 }
 
+//---------------------------------------------------------------------------------------------------
+// IdCounters struct
+// The last track, playlist and smart playlist ids that were handed out. They are saved with the rest of the state so
+// an id isn't given to something new after whatever had it was deleted, which would make anything
+// still holding the old id (the queue, the history, an open view) point at the wrong track or
+// playlist. State saved before the counters existed starts them from the highest id in use.
+// --------------------------------------------------------------------------------------------------
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct IdCounters {
+    last_track: u32,
+    last_playlist: u32,
+    last_smart_playlist: u32,
+}
+
+impl IdCounters {
+    pub fn next_track_id(&mut self) -> u32 {
+        self.last_track += 1;
+        self.last_track
+    }
+
+    pub fn next_playlist_id(&mut self) -> u32 {
+        self.last_playlist += 1;
+        self.last_playlist
+    }
+
+    pub fn next_smart_playlist_id(&mut self) -> u32 {
+        self.last_smart_playlist += 1;
+        self.last_smart_playlist
+    }
+
+    pub fn catch_up(&mut self, library: &[MusicFile], playlists: &[MusicCollection]) {
+        // makes sure no id that is already in use gets handed out again
+        let last_track = library.iter().map(|m| m.id).max().unwrap_or(0);
+        let last_playlist = playlists.iter().map(|p| p.id).max().unwrap_or(0);
+        self.last_track = self.last_track.max(last_track);
+        self.last_playlist = self.last_playlist.max(last_playlist);
+    }
+}
+
+pub fn assign_track_ids(library: &mut [MusicFile], ids: &mut IdCounters) {
+    // gives every track that was just added to the library (the ones with id 0) an id of its own
+    for music_file in library.iter_mut().filter(|m| m.id == 0) {
+        music_file.id = ids.next_track_id();
+    }
+}
+
+pub fn assign_smart_playlist_ids(smart_playlists: &mut [SmartPlaylist], ids: &mut IdCounters) {
+    // smart playlists saved before they had ids get one, after every id that is already in use
+    let last = smart_playlists.iter().map(|p| p.id).max().unwrap_or(0);
+    ids.last_smart_playlist = ids.last_smart_playlist.max(last);
+    for smart_playlist in smart_playlists.iter_mut().filter(|p| p.id == 0) {
+        smart_playlist.id = ids.next_smart_playlist_id();
+    }
+}
+
+pub fn track_positions(library: &[MusicFile]) -> HashMap<u32, usize> {
+    // where each track id is in the library
+    library.iter().enumerate().map(|(i, m)| (m.id, i)).collect()
+}
+
 //---------------------------------------------------------------------------------------------------
 // MusicCollection struct
-// This struct is used to store a collection [aka playlists] of music files, as well as the name of the collection
-// and the number of songs in the collection.
+// This struct is used to store a collection [aka playlists] of music files, as well as the name of the collection.
+// A playlist holds the ids of its tracks in playlist order rather than copies of them, so edits to a
+// track in the library show up in every playlist it is in, and the playlist itself is known by its
+// own id, which stays the same when playlists before it are deleted.
+//
+// Playlists used to hold a copy of each track, saved as "collection", and be numbered by position.
+// Those copies are still read so migrate_playlists() can turn them into track ids.
 // --------------------------------------------------------------------------------------------------
-#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+#[serde(default)]
 pub struct MusicCollection {
+    pub id: u32,
     pub name: String,
-    pub collection: Vec<MusicFile>,
-    pub song_count: i32,
-    pub index: i32,
+    pub tracks: Vec<u32>,
+    pub folder: Option<u32>, // the playlist folder it is in, see playlist_folders.rs
+    pub modified: Option<u64>, // when its tracks or name last changed, seconds since the unix epoch
+    #[serde(rename = "collection", skip_serializing)]
+    saved_copies: Vec<MusicFile>,
 }
 
 impl MusicCollection {
-    pub fn new(s: String, i: i32) -> MusicCollection {
-        let collection = MusicCollection {
-            name: s,
-            collection: Vec::new(),
-            song_count: 0,
-            index: i,
-        };
-        collection
+    pub fn new(name: String, id: u32) -> MusicCollection {
+        MusicCollection {
+            id,
+            name,
+            modified: Some(now_secs()),
+            ..Default::default()
+        }
     }
 
-    pub fn add_song(&mut self, music_file: MusicFile) {
-        self.collection.push(music_file);
-        self.song_count += 1;
+    pub fn song_count(&self) -> usize {
+        self.tracks.len()
+    }
+
+    pub fn add_song(&mut self, id: u32) {
+        self.tracks.push(id);
+        self.modified = Some(now_secs());
+    }
+
+    pub fn songs<'a>(&self, library: &'a [MusicFile]) -> Vec<&'a MusicFile> {
+        // the tracks of the playlist, in playlist order
+        self.entries(library)
+            .into_iter()
+            .map(|(_, i)| &library[i])
+            .collect()
+    }
+
+    pub fn entries(&self, library: &[MusicFile]) -> Vec<(usize, usize)> {
+        // -----------------------------------------------------------------------------------------------
+        // the position in the playlist and the index in the library of every track songs() returns.
+        // ids that aren't in the library are skipped, so these are what the rows of songs() refer to
+        // -----------------------------------------------------------------------------------------------
+        let positions = track_positions(library);
+        self.tracks
+            .iter()
+            .enumerate()
+            .filter_map(|(position, id)| Some((position, *positions.get(id)?)))
+            .collect()
+    }
+
+    pub fn rename(&mut self, name: &str) -> Result<(), String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("a playlist needs a name".to_string());
+        }
+        self.name = name.to_string();
+        self.modified = Some(now_secs());
+        Ok(())
+    }
+
+    pub fn remove_songs(&mut self, positions: &BTreeSet<usize>) {
+        let mut position = 0;
+        self.tracks.retain(|_| {
+            position += 1;
+            !positions.contains(&(position - 1))
+        });
+        self.modified = Some(now_secs());
+    }
+
+    pub fn move_songs(&mut self, positions: &BTreeSet<usize>, to: usize) -> Range<usize> {
+        // -----------------------------------------------------------------------------------------------
+        // moves the songs at `positions` (in their current order) so they end up together in front of
+        // the song that is at `to` now, or at the end when `to` is past the last song. returns where
+        // the moved songs are afterwards
+        // -----------------------------------------------------------------------------------------------
+        let to = to.min(self.tracks.len());
+        let mut moved = Vec::new();
+        let mut kept = Vec::new();
+        for (i, id) in self.tracks.drain(..).enumerate() {
+            if positions.contains(&i) {
+                moved.push(id);
+            } else {
+                kept.push(id);
+            }
+        }
+        let insert_at = to - positions.range(..to).count();
+        let range = insert_at..insert_at + moved.len();
+        kept.splice(insert_at..insert_at, moved);
+        self.tracks = kept;
+        self.modified = Some(now_secs());
+        range
+    }
+}
+
+pub fn migrate_playlists(
+    library: &mut Vec<MusicFile>,
+    playlists: &mut [MusicCollection],
+    ids: &mut IdCounters,
+) {
+    // -----------------------------------------------------------------------------------------------
+    // brings playlists saved before tracks and playlists had ids up to date: the library gets its
+    // track ids, the copies a playlist held are matched to library tracks by path (copies of files
+    // that aren't in the library are added to it), and every playlist gets an id. ids of tracks that
+    // aren't in the library are dropped, so a playlist's positions always line up with songs()
+    // -----------------------------------------------------------------------------------------------
+    ids.catch_up(library, playlists);
+    assign_track_ids(library, ids);
+    let mut by_path: HashMap<PathBuf, u32> = library
+        .iter()
+        .map(|m| (m.file_path.clone(), m.id))
+        .collect();
+    for playlist in playlists.iter_mut() {
+        if playlist.id == 0 {
+            playlist.id = ids.next_playlist_id();
+        }
+        for mut copy in std::mem::take(&mut playlist.saved_copies) {
+            let id = match by_path.get(&copy.file_path) {
+                Some(&id) => id,
+                None => {
+                    copy.id = ids.next_track_id();
+                    by_path.insert(copy.file_path.clone(), copy.id);
+                    library.push(copy);
+                    library[library.len() - 1].id
+                }
+            };
+            playlist.tracks.push(id);
+        }
+    }
+
+    let in_library: HashSet<u32> = library.iter().map(|m| m.id).collect();
+    for playlist in playlists.iter_mut() {
+        playlist.tracks.retain(|id| in_library.contains(id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(id: u32, path: &str) -> MusicFile {
+        MusicFile {
+            id,
+            file_path: PathBuf::from(path),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn ids_are_not_handed_out_twice() {
+        let mut ids = IdCounters::default();
+        let mut library = vec![track(0, "/music/a.mp3"), track(0, "/music/b.mp3")];
+        assign_track_ids(&mut library, &mut ids);
+        assert_eq!(library[0].id, 1);
+        assert_eq!(library[1].id, 2);
+
+        // the newest track is removed, the next one still gets a new id
+        library.pop();
+        library.push(track(0, "/music/c.mp3"));
+        assign_track_ids(&mut library, &mut ids);
+        assert_eq!(library[1].id, 3);
+
+        let first = ids.next_playlist_id();
+        assert_eq!(ids.next_playlist_id(), first + 1);
+    }
+
+    #[test]
+    fn counters_catch_up_with_saved_ids() {
+        // state saved before the counters existed
+        let mut ids = IdCounters::default();
+        let mut library = vec![track(7, "/music/a.mp3"), track(0, "/music/b.mp3")];
+        let mut playlists = vec![MusicCollection::new("Mix".to_string(), 4)];
+        migrate_playlists(&mut library, &mut playlists, &mut ids);
+        assert_eq!(library[1].id, 8);
+        assert_eq!(ids.next_playlist_id(), 5);
+    }
+
+    #[test]
+    fn smart_playlists_get_ids_after_the_saved_ones() {
+        let mut ids = IdCounters::default();
+        let mut smart_playlists = vec![
+            SmartPlaylist::new("Old".to_string()),
+            SmartPlaylist::new("Kept".to_string()),
+        ];
+        smart_playlists[1].id = 5;
+        assign_smart_playlist_ids(&mut smart_playlists, &mut ids);
+        assert_eq!(smart_playlists[0].id, 6);
+        assert_eq!(smart_playlists[1].id, 5);
+        assert_eq!(ids.next_smart_playlist_id(), 7);
+    }
+
+    #[test]
+    fn entries_skip_ids_that_are_not_in_the_library() {
+        let library = vec![track(1, "/music/a.mp3"), track(2, "/music/b.mp3")];
+        let mut playlist = MusicCollection::new("Mix".to_string(), 1);
+        playlist.tracks = vec![2, 9, 1];
+        assert_eq!(playlist.entries(&library), vec![(0, 1), (2, 0)]);
+        assert_eq!(playlist.songs(&library).len(), 2);
+    }
+
+    fn playlist(tracks: &[u32]) -> MusicCollection {
+        let mut playlist = MusicCollection::new("Mix".to_string(), 1);
+        playlist.tracks = tracks.to_vec();
+        playlist
+    }
+
+    fn positions(positions: &[usize]) -> BTreeSet<usize> {
+        positions.iter().copied().collect()
+    }
+
+    #[test]
+    fn move_songs_keeps_the_selection_together_in_order() {
+        // two songs that aren't next to each other, moved up and down
+        let mut mix = playlist(&[1, 2, 3, 4, 5]);
+        assert_eq!(mix.move_songs(&positions(&[3, 0]), 2), 1..3);
+        assert_eq!(mix.tracks, vec![2, 1, 4, 3, 5]);
+
+        let mut mix = playlist(&[1, 2, 3, 4, 5]);
+        assert_eq!(mix.move_songs(&positions(&[0, 1]), 4), 2..4);
+        assert_eq!(mix.tracks, vec![3, 4, 1, 2, 5]);
+    }
+
+    #[test]
+    fn move_songs_onto_the_selection_itself() {
+        // dropping on a selected song only closes the gaps around it
+        let mut mix = playlist(&[1, 2, 3, 4, 5]);
+        assert_eq!(mix.move_songs(&positions(&[1, 2]), 2), 1..3);
+        assert_eq!(mix.tracks, vec![1, 2, 3, 4, 5]);
+
+        let mut mix = playlist(&[1, 2, 3, 4, 5]);
+        assert_eq!(mix.move_songs(&positions(&[0, 2, 4]), 2), 1..4);
+        assert_eq!(mix.tracks, vec![2, 1, 3, 5, 4]);
+    }
+
+    #[test]
+    fn move_songs_to_the_end() {
+        let mut mix = playlist(&[1, 2, 3, 4, 5]);
+        assert_eq!(mix.move_songs(&positions(&[2, 0]), 5), 3..5);
+        assert_eq!(mix.tracks, vec![2, 4, 5, 1, 3]);
+
+        // anything past the last song is the end too
+        let mut mix = playlist(&[1, 2, 3]);
+        assert_eq!(mix.move_songs(&positions(&[0]), 99), 2..3);
+        assert_eq!(mix.tracks, vec![2, 3, 1]);
+    }
+
+    #[test]
+    fn remove_songs_by_position() {
+        // the same track twice, only the selected copy goes
+        let mut mix = playlist(&[1, 2, 1, 3, 4]);
+        let mut selected = BTreeSet::new();
+        for position in [4, 0, 4, 0, 9] {
+            selected.insert(position);
+        }
+        mix.remove_songs(&selected);
+        assert_eq!(mix.tracks, vec![2, 1, 3]);
+
+        mix.remove_songs(&BTreeSet::new());
+        assert_eq!(mix.tracks, vec![2, 1, 3]);
+        mix.remove_songs(&positions(&[2, 1, 0]));
+        assert!(mix.tracks.is_empty());
     }
 }
@@ -0,0 +1,430 @@
+use super::file_handling::MusicFile;
+use chrono::{Datelike, Local, TimeZone, Timelike};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+// a play shorter than half the track (or 4 minutes for long tracks) counts as a skip
+const SKIP_FRACTION: f64 = 0.5;
+const SKIP_SECONDS: f64 = 240.0;
+// how many artists, albums and tracks the statistics list
+pub const TOP_COUNT: usize = 10;
+
+//---------------------------------------------------------------------------------------------------
+// Play struct
+// One entry in the listening history. The track's title, artist and album are copied in when it is
+// logged, so the statistics still make sense after a track is retagged or removed from the library.
+// --------------------------------------------------------------------------------------------------
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct Play {
+    pub file_path: PathBuf,
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub started: u64,  // seconds since the unix epoch
+    pub listened: f64, // seconds actually played, pauses not included
+    pub skipped: bool,
+}
+
+//---------------------------------------------------------------------------------------------------
+// ListeningHistory struct
+// Every play, oldest first. The app logs plays through PlayTracker, which watches the audio player
+// every frame, so it doesn't matter which button or queue started the track.
+// --------------------------------------------------------------------------------------------------
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct ListeningHistory {
+    pub plays: Vec<Play>,
+}
+
+impl ListeningHistory {
+    pub fn log(&mut self, play: Play, library: &mut [MusicFile]) -> Option<usize> {
+        // -----------------------------------------------------------------------------------------------
+        // adds a finished play and updates the play count of the track. returns the library index of
+        // the track when it changed. skips are kept in the history but don't count as plays
+        // -----------------------------------------------------------------------------------------------
+        let mut updated = None;
+        if !play.skipped {
+            updated = library.iter().position(|m| m.file_path == play.file_path);
+            if let Some(i) = updated {
+                library[i].play_count += 1;
+                library[i].last_played = Some(play.started);
+            }
+        }
+        self.plays.push(play);
+        updated
+    }
+
+    pub fn stats(&self, range: TimeRange) -> ListeningStats {
+        let since = range.since(now_secs());
+        let mut stats = ListeningStats::default();
+        let mut artists: HashMap<&str, Ranked> = HashMap::new();
+        let mut albums: HashMap<(&str, &str), Ranked> = HashMap::new();
+        let mut tracks: HashMap<&PathBuf, Ranked> = HashMap::new();
+
+        for play in self.plays.iter().filter(|p| p.started >= since) {
+            stats.total_time += play.listened;
+            if play.skipped {
+                stats.skips += 1;
+            } else {
+                stats.plays += 1;
+            }
+
+            if let Some(time) = Local.timestamp_opt(play.started as i64, 0).single() {
+                let weekday = time.weekday().num_days_from_monday() as usize;
+                stats.heatmap[weekday][time.hour() as usize] += play.listened;
+            }
+
+            if !play.artist.is_empty() {
+                artists
+                    .entry(&play.artist)
+                    .or_insert_with(|| Ranked::new(play.artist.clone()))
+                    .add(play);
+            }
+            if !play.album.is_empty() {
+                albums
+                    .entry((&play.album, &play.artist))
+                    .or_insert_with(|| Ranked::new(format!("{} - {}", play.album, play.artist)))
+                    .add(play);
+            }
+            tracks
+                .entry(&play.file_path)
+                .or_insert_with(|| Ranked::new(play_title(play)))
+                .add(play);
+        }
+
+        stats.top_artists = top(artists.into_values().collect());
+        stats.top_albums = top(albums.into_values().collect());
+        stats.top_tracks = top(tracks.into_values().collect());
+        stats
+    }
+}
+
+fn play_title(play: &Play) -> String {
+    let title = if play.title.is_empty() {
+        play.file_path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default()
+    } else {
+        play.title.clone()
+    };
+    if play.artist.is_empty() {
+        title
+    } else {
+        format!("{} - {}", title, play.artist)
+    }
+}
+
+fn top(mut ranked: Vec<Ranked>) -> Vec<Ranked> {
+    // most plays first, then the most time listened
+    ranked.sort_by(|a, b| {
+        b.plays.cmp(&a.plays).then(
+            b.listened
+                .partial_cmp(&a.listened)
+                .unwrap_or(std::cmp::Ordering::Equal),
+        )
+    });
+    ranked.truncate(TOP_COUNT);
+    ranked
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Ranked {
+    pub name: String,
+    pub plays: usize,
+    pub listened: f64,
+}
+
+impl Ranked {
+    fn new(name: String) -> Ranked {
+        Ranked {
+            name,
+            ..Default::default()
+        }
+    }
+
+    fn add(&mut self, play: &Play) {
+        if !play.skipped {
+            self.plays += 1;
+        }
+        self.listened += play.listened;
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ListeningStats {
+    pub total_time: f64, // seconds
+    pub plays: usize,
+    pub skips: usize,
+    pub top_artists: Vec<Ranked>,
+    pub top_albums: Vec<Ranked>,
+    pub top_tracks: Vec<Ranked>,
+    pub heatmap: [[f64; 24]; 7], // seconds listened by weekday (monday first) and hour, local time
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TimeRange {
+    Week,
+    Month,
+    Year,
+    AllTime,
+}
+
+impl TimeRange {
+    pub const ALL: [TimeRange; 4] = [
+        TimeRange::Week,
+        TimeRange::Month,
+        TimeRange::Year,
+        TimeRange::AllTime,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            TimeRange::Week => "Last 7 days",
+            TimeRange::Month => "Last 30 days",
+            TimeRange::Year => "Last year",
+            TimeRange::AllTime => "All time",
+        }
+    }
+
+    fn since(&self, now: u64) -> u64 {
+        let days = match self {
+            TimeRange::Week => 7,
+            TimeRange::Month => 30,
+            TimeRange::Year => 365,
+            TimeRange::AllTime => return 0,
+        };
+        now.saturating_sub(days * 24 * 60 * 60)
+    }
+}
+
+//---------------------------------------------------------------------------------------------------
+// PlayTracker struct
+// Follows the track the audio player is on. The app ticks it every frame, and when the player's
+// load count changes (it goes up every time a file is loaded, so playing the same track twice in a
+// row still counts twice) or playback stops, it finishes the current play for the history and starts
+// the next one. Listening time only adds up while the player isn't paused.
+// --------------------------------------------------------------------------------------------------
+#[derive(Default)]
+pub struct PlayTracker {
+    current: Option<TrackedPlay>,
+}
+
+struct TrackedPlay {
+    play: Play,
+    load: usize,
+    duration: f64,
+    last_tick: Instant,
+}
+
+impl PlayTracker {
+    pub fn tick(&mut self, paused: bool) {
+        // adds the time since the last frame to the current play
+        let now = Instant::now();
+        if let Some(tracked) = &mut self.current {
+            if !paused {
+                tracked.play.listened += now.duration_since(tracked.last_tick).as_secs_f64();
+            }
+            tracked.last_tick = now;
+        }
+    }
+
+    pub fn load(&self) -> Option<usize> {
+        self.current.as_ref().map(|tracked| tracked.load)
+    }
+
+    pub fn start(&mut self, music_file: &MusicFile, load: usize) {
+        self.current = Some(TrackedPlay {
+            play: Play {
+                file_path: music_file.file_path.clone(),
+                title: music_file.title.clone(),
+                artist: music_file.artist.clone(),
+                album: music_file.album.clone(),
+                started: now_secs(),
+                listened: 0.0,
+                skipped: false,
+            },
+            load,
+            duration: music_file.duration,
+            last_tick: Instant::now(),
+        });
+    }
+
+    pub fn finish(&mut self) -> Option<Play> {
+        self.current.take().map(TrackedPlay::finish)
+    }
+}
+
+impl TrackedPlay {
+    fn finish(mut self) -> Play {
+        if self.duration > 0.0 {
+            self.play.listened = self.play.listened.min(self.duration);
+            self.play.skipped =
+                self.play.listened < (self.duration * SKIP_FRACTION).min(SKIP_SECONDS);
+        }
+        self.play
+    }
+}
+
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+pub fn format_date(secs: u64) -> String {
+    match Local.timestamp_opt(secs as i64, 0).single() {
+        Some(time) => time.format("%Y-%m-%d").to_string(),
+        None => String::new(),
+    }
+}
+
+pub fn format_listening_time(seconds: f64) -> String {
+    let minutes = (seconds / 60.0).round() as u64;
+    if minutes < 60 {
+        format!("{} min", minutes)
+    } else {
+        format!("{} h {} min", minutes / 60, minutes % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finished(duration: f64, listened: f64) -> Play {
+        TrackedPlay {
+            play: Play {
+                listened,
+                ..Default::default()
+            },
+            load: 1,
+            duration,
+            last_tick: Instant::now(),
+        }
+        .finish()
+    }
+
+    fn play(path: &str, artist: &str, album: &str, started: u64, listened: f64) -> Play {
+        Play {
+            file_path: PathBuf::from(path),
+            artist: artist.to_string(),
+            album: album.to_string(),
+            started,
+            listened,
+            ..Default::default()
+        }
+    }
+
+    fn local(year: i32, month: u32, day: u32, hour: u32) -> u64 {
+        Local
+            .with_ymd_and_hms(year, month, day, hour, 30, 0)
+            .single()
+            .unwrap()
+            .timestamp() as u64
+    }
+
+    #[test]
+    fn less_than_half_a_track_is_a_skip() {
+        assert!(finished(200.0, 99.0).skipped);
+        assert!(!finished(200.0, 100.0).skipped);
+        assert!(finished(200.0, 0.0).skipped);
+    }
+
+    #[test]
+    fn four_minutes_of_a_long_track_is_a_play() {
+        assert!(finished(600.0, 239.0).skipped);
+        assert!(!finished(600.0, 240.0).skipped);
+    }
+
+    #[test]
+    fn listening_time_is_capped_at_the_track_length() {
+        let play = finished(200.0, 250.0);
+        assert_eq!(play.listened, 200.0);
+        assert!(!play.skipped);
+        // without a known duration nothing counts as a skip
+        let play = finished(0.0, 1.0);
+        assert_eq!(play.listened, 1.0);
+        assert!(!play.skipped);
+    }
+
+    #[test]
+    fn skips_are_logged_but_not_counted() {
+        let mut library = vec![MusicFile {
+            file_path: PathBuf::from("/a.mp3"),
+            ..Default::default()
+        }];
+        let mut history = ListeningHistory::default();
+        let mut skip = play("/a.mp3", "", "", 100, 5.0);
+        skip.skipped = true;
+        assert_eq!(history.log(skip, &mut library), None);
+        assert_eq!(
+            history.log(play("/a.mp3", "", "", 200, 90.0), &mut library),
+            Some(0)
+        );
+        assert_eq!(
+            history.log(play("/b.mp3", "", "", 300, 90.0), &mut library),
+            None
+        );
+        assert_eq!(history.plays.len(), 3);
+        assert_eq!(library[0].play_count, 1);
+        assert_eq!(library[0].last_played, Some(200));
+    }
+
+    #[test]
+    fn heatmap_buckets_by_local_weekday_and_hour() {
+        // 2024-01-01 was a monday
+        let history = ListeningHistory {
+            plays: vec![
+                play("/a.mp3", "", "", local(2024, 1, 1, 13), 60.0),
+                play("/a.mp3", "", "", local(2024, 1, 1, 13), 30.0),
+                play("/a.mp3", "", "", local(2024, 1, 7, 0), 45.0),
+                play("/a.mp3", "", "", local(2024, 1, 3, 23), 10.0),
+            ],
+        };
+        let stats = history.stats(TimeRange::AllTime);
+        assert_eq!(stats.heatmap[0][13], 90.0);
+        assert_eq!(stats.heatmap[6][0], 45.0);
+        assert_eq!(stats.heatmap[2][23], 10.0);
+        let total: f64 = stats.heatmap.iter().flatten().sum();
+        assert_eq!(total, 145.0);
+        assert_eq!(stats.total_time, 145.0);
+    }
+
+    #[test]
+    fn stats_only_count_plays_in_range() {
+        let now = now_secs();
+        let day = 24 * 60 * 60;
+        let mut skip = play("/b.mp3", "B", "", now - day, 10.0);
+        skip.skipped = true;
+        let history = ListeningHistory {
+            plays: vec![
+                play("/a.mp3", "A", "One", now - 40 * day, 100.0),
+                play("/a.mp3", "A", "One", now - 8 * day, 100.0),
+                play("/b.mp3", "B", "", now - day, 50.0),
+                skip,
+            ],
+        };
+        let week = history.stats(TimeRange::Week);
+        assert_eq!((week.plays, week.skips), (1, 1));
+        assert_eq!(week.total_time, 60.0);
+        assert!(week.top_albums.is_empty());
+
+        let month = history.stats(TimeRange::Month);
+        assert_eq!((month.plays, month.skips), (2, 1));
+        let all = history.stats(TimeRange::AllTime);
+        assert_eq!(all.plays, 3);
+        // most plays first, skips add listening time but no plays
+        let artists: Vec<(&str, usize, f64)> = all
+            .top_artists
+            .iter()
+            .map(|r| (r.name.as_str(), r.plays, r.listened))
+            .collect();
+        assert_eq!(artists, vec![("A", 2, 200.0), ("B", 1, 60.0)]);
+        assert_eq!(all.top_albums[0].name, "One - A");
+        assert_eq!(all.top_tracks[0].name, "a.mp3 - A");
+    }
+}
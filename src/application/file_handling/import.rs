@@ -0,0 +1,692 @@
+use super::file_handling::{
+    assign_track_ids, unread_music_file, IdCounters, MusicCollection, MusicFile,
+};
+use roxmltree::{Document, Node, ParsingOptions};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+//---------------------------------------------------------------------------------------------------
+// Importing from other players
+// Reads the library of iTunes / Apple Music (the "Library.xml" export) or Rhythmbox (rhythmdb.xml
+// and playlists.xml) into an ImportedLibrary, which is then merged into ours:
+//  - tracks are matched to the library by path. Tracks that aren't in the library yet are added and
+//    have their tags read in the background by LibraryUpgrade, ones that can't be found end up in
+//    the report
+//  - play counts, last played dates, ratings and loved tracks are carried over. Existing values are
+//    only replaced when the imported ones are higher, so importing twice changes nothing
+//  - playlists become MusicCollections, unless there already is a playlist with the same name.
+//    Smart/automatic playlists are skipped, we can't translate their rules
+// --------------------------------------------------------------------------------------------------
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ImportSource {
+    ITunes,
+    Rhythmbox,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ImportedTrack {
+    pub path: PathBuf,
+    pub play_count: u32,
+    pub last_played: Option<u64>, // seconds since the unix epoch
+    pub rating: Option<u8>,       // 1-5 stars
+    pub favourite: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ImportedPlaylist {
+    pub name: String,
+    pub tracks: Vec<PathBuf>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ImportedLibrary {
+    pub tracks: Vec<ImportedTrack>,
+    pub playlists: Vec<ImportedPlaylist>,
+    pub skipped: Vec<String>, // entries that couldn't be read from the file at all
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ImportReport {
+    pub added: usize,
+    pub updated: usize,
+    pub playlists: usize,
+    pub unresolved: Vec<String>,
+}
+
+impl ImportReport {
+    pub fn summary(&self) -> String {
+        format!(
+            "Added {} tracks, updated {}, imported {} playlists. {} entries couldn't be resolved.",
+            self.added,
+            self.updated,
+            self.playlists,
+            self.unresolved.len()
+        )
+    }
+}
+
+impl ImportedLibrary {
+    pub fn merge_into(
+        self,
+        library: &mut Vec<MusicFile>,
+        playlists: &mut Vec<MusicCollection>,
+        ids: &mut IdCounters,
+    ) -> ImportReport {
+        let mut report = ImportReport {
+            unresolved: self.skipped,
+            ..Default::default()
+        };
+        let mut by_path: HashMap<PathBuf, usize> = library
+            .iter()
+            .enumerate()
+            .map(|(i, m)| (m.file_path.clone(), i))
+            .collect();
+
+        for track in self.tracks {
+            let i = match by_path.get(&track.path) {
+                Some(&i) => {
+                    report.updated += 1;
+                    i
+                }
+                None if track.path.is_file() => {
+                    library.push(unread_music_file(&track.path));
+                    by_path.insert(track.path.clone(), library.len() - 1);
+                    report.added += 1;
+                    library.len() - 1
+                }
+                None => {
+                    report
+                        .unresolved
+                        .push(format!("{}: file not found", track.path.display()));
+                    continue;
+                }
+            };
+            let music_file = &mut library[i];
+            music_file.play_count = music_file.play_count.max(track.play_count);
+            music_file.last_played = music_file.last_played.max(track.last_played);
+            music_file.rating = music_file.rating.max(track.rating);
+            music_file.favourite |= track.favourite;
+        }
+        assign_track_ids(library, ids);
+
+        for imported in self.playlists {
+            if playlists.iter().any(|p| p.name == imported.name) {
+                report.unresolved.push(format!(
+                    "playlist \"{}\": a playlist with that name already exists",
+                    imported.name
+                ));
+                continue;
+            }
+            let mut playlist = MusicCollection::new(imported.name, ids.next_playlist_id());
+            for path in imported.tracks {
+                match by_path.get(&path) {
+                    Some(&i) => playlist.add_song(library[i].id),
+                    None => report.unresolved.push(format!(
+                        "{} in playlist \"{}\": not in the library",
+                        path.display(),
+                        playlist.name
+                    )),
+                }
+            }
+            playlists.push(playlist);
+            report.playlists += 1;
+        }
+        report
+    }
+}
+
+fn parse(text: &str) -> Result<Document<'_>, String> {
+    // the iTunes export starts with a DOCTYPE, which roxmltree refuses unless it's allowed
+    Document::parse_with_options(
+        text,
+        ParsingOptions {
+            allow_dtd: true,
+            ..Default::default()
+        },
+    )
+    .map_err(|e| e.to_string())
+}
+
+fn read_file(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("couldn't read {}: {}", path.display(), e))
+}
+
+//---------------------------------------------------------------------------------------------------
+// iTunes / Apple Music
+// The export is a property list: a <dict> of keys and values, with "Tracks" (a dict of track id ->
+// track dict) and "Playlists" (an array of playlist dicts, each listing the ids of its tracks).
+// Ratings are 0-100 (20 per star), "Rating Computed" marks ratings derived from the album.
+// --------------------------------------------------------------------------------------------------
+pub fn read_itunes_library(path: &Path) -> Result<ImportedLibrary, String> {
+    let text = read_file(path)?;
+    let document = parse(&text)?;
+    let root = document
+        .root_element()
+        .children()
+        .find(|n| n.has_tag_name("dict"))
+        .ok_or("not an iTunes library: the file has no top level <dict>")?;
+    let root = plist_dict(root);
+
+    let mut imported = ImportedLibrary::default();
+    let mut ids: HashMap<String, PathBuf> = HashMap::new();
+    if let Some(tracks) = root.get("Tracks") {
+        for (id, track) in plist_entries(*tracks) {
+            let track = plist_dict(track);
+            let location = match track.get("Location").and_then(|n| n.text()) {
+                Some(location) => location,
+                None => continue, // streams and cloud tracks have no file
+            };
+            let path = match path_from_url(location) {
+                Some(path) => path,
+                None => {
+                    imported
+                        .skipped
+                        .push(format!("{}: not a local file", location));
+                    continue;
+                }
+            };
+            let computed = plist_bool(&track, "Rating Computed");
+            let rating = match plist_number(&track, "Rating") {
+                Some(rating) if !computed && rating > 0 => {
+                    Some(((rating + 10) / 20).clamp(1, 5) as u8)
+                }
+                _ => None,
+            };
+            let last_played = track
+                .get("Play Date UTC")
+                .and_then(|n| n.text())
+                .and_then(|date| chrono::DateTime::parse_from_rfc3339(date).ok())
+                .map(|date| date.timestamp().max(0) as u64);
+
+            ids.insert(id.to_string(), path.clone());
+            imported.tracks.push(ImportedTrack {
+                path,
+                play_count: plist_number(&track, "Play Count").unwrap_or_default() as u32,
+                last_played,
+                rating,
+                favourite: plist_bool(&track, "Loved"),
+            });
+        }
+    }
+
+    let playlists = root.get("Playlists").into_iter().flat_map(|n| n.children());
+    for playlist in playlists.filter(|n| n.has_tag_name("dict")) {
+        let playlist = plist_dict(playlist);
+        let name = playlist
+            .get("Name")
+            .and_then(|n| n.text())
+            .unwrap_or_default()
+            .to_string();
+        // the whole library, the built-in Music/Movies/... lists, folders and smart playlists
+        let built_in = ["Master", "Distinguished Kind", "Folder", "Smart Info"]
+            .iter()
+            .any(|key| playlist.contains_key(key));
+        if built_in {
+            continue;
+        }
+        let mut tracks = Vec::new();
+        if let Some(items) = playlist.get("Playlist Items") {
+            for item in items.children().filter(|n| n.has_tag_name("dict")) {
+                let id = plist_dict(item)
+                    .get("Track ID")
+                    .and_then(|n| n.text())
+                    .unwrap_or_default()
+                    .to_string();
+                match ids.get(&id) {
+                    Some(path) => tracks.push(path.clone()),
+                    None => imported.skipped.push(format!(
+                        "track {} in playlist \"{}\": not a local file",
+                        id, name
+                    )),
+                }
+            }
+        }
+        imported.playlists.push(ImportedPlaylist { name, tracks });
+    }
+    Ok(imported)
+}
+
+fn plist_entries<'a, 'input>(dict: Node<'a, 'input>) -> Vec<(&'a str, Node<'a, 'input>)> {
+    // pairs each <key> of a plist <dict> with the element that follows it, in file order
+    let mut entries = Vec::new();
+    let mut key = None;
+    for child in dict.children().filter(|n| n.is_element()) {
+        match key.take() {
+            None if child.has_tag_name("key") => key = child.text(),
+            Some(name) => entries.push((name, child)),
+            None => {}
+        }
+    }
+    entries
+}
+
+fn plist_dict<'a, 'input>(dict: Node<'a, 'input>) -> HashMap<&'a str, Node<'a, 'input>> {
+    plist_entries(dict).into_iter().collect()
+}
+
+fn plist_bool(dict: &HashMap<&str, Node>, key: &str) -> bool {
+    matches!(dict.get(key), Some(node) if node.has_tag_name("true"))
+}
+
+fn plist_number(dict: &HashMap<&str, Node>, key: &str) -> Option<i64> {
+    dict.get(key)?.text()?.trim().parse().ok()
+}
+
+//---------------------------------------------------------------------------------------------------
+// Rhythmbox
+// rhythmdb.xml has an <entry type="song"> per track with <location>, <play-count>, <rating> (0-5)
+// and <last-played> (unix time). playlists.xml has a <playlist> per playlist, static ones list the
+// <location> of each track, automatic ones hold a query instead.
+// --------------------------------------------------------------------------------------------------
+pub fn read_rhythmbox_library(
+    database: &Path,
+    playlists: Option<&Path>,
+) -> Result<ImportedLibrary, String> {
+    let text = read_file(database)?;
+    let document = parse(&text)?;
+    if !document.root_element().has_tag_name("rhythmdb") {
+        return Err("not a Rhythmbox database: the root element isn't <rhythmdb>".to_string());
+    }
+
+    let mut imported = ImportedLibrary::default();
+    let songs = document
+        .root_element()
+        .children()
+        .filter(|n| n.has_tag_name("entry") && n.attribute("type") == Some("song"));
+    for entry in songs {
+        let location = child_text(entry, "location").unwrap_or_default();
+        let path = match path_from_url(location) {
+            Some(path) => path,
+            None => {
+                imported
+                    .skipped
+                    .push(format!("{}: not a local file", location));
+                continue;
+            }
+        };
+        let number =
+            |name: &str| child_text(entry, name).and_then(|t| t.trim().parse::<u64>().ok());
+        imported.tracks.push(ImportedTrack {
+            path,
+            play_count: number("play-count").unwrap_or_default() as u32,
+            last_played: number("last-played"),
+            rating: number("rating")
+                .filter(|&rating| rating > 0)
+                .map(|rating| rating.min(5) as u8),
+            favourite: false,
+        });
+    }
+
+    if let Some(playlists) = playlists {
+        let text = read_file(playlists)?;
+        let document = parse(&text)?;
+        for playlist in document
+            .root_element()
+            .children()
+            .filter(|n| n.has_tag_name("playlist"))
+        {
+            let name = playlist.attribute("name").unwrap_or_default().to_string();
+            if playlist.attribute("type") != Some("static") {
+                continue;
+            }
+            let mut tracks = Vec::new();
+            for location in playlist.children().filter(|n| n.has_tag_name("location")) {
+                let url = location.text().unwrap_or_default();
+                match path_from_url(url) {
+                    Some(path) => tracks.push(path),
+                    None => imported.skipped.push(format!(
+                        "{} in playlist \"{}\": not a local file",
+                        url, name
+                    )),
+                }
+            }
+            imported.playlists.push(ImportedPlaylist { name, tracks });
+        }
+    }
+    Ok(imported)
+}
+
+pub fn rhythmbox_folder() -> Option<PathBuf> {
+    // where Rhythmbox keeps rhythmdb.xml and playlists.xml (~/.local/share/rhythmbox on Linux)
+    dirs::data_dir().map(|dir| dir.join("rhythmbox"))
+}
+
+fn child_text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.children().find(|n| n.has_tag_name(name))?.text()
+}
+
+pub fn path_from_url(url: &str) -> Option<PathBuf> {
+    // -----------------------------------------------------------------------------------------------
+    // turns a file:// url into a path. iTunes writes file://localhost/C:/Music/a%20b.mp3 on windows and
+    // file:///Users/me/Music/a%20b.mp3 on macOS, Rhythmbox writes file:///home/me/Music/a%20b.mp3
+    // -----------------------------------------------------------------------------------------------
+    let rest = url.trim().strip_prefix("file://")?;
+    let rest = rest.strip_prefix("localhost").unwrap_or(rest);
+    let path = percent_decode(rest);
+
+    // "/C:/Music" -> "C:/Music", only for an ascii drive letter so the slice stays on a char boundary
+    let bytes = path.as_bytes();
+    let windows_drive =
+        bytes.len() > 2 && bytes[0] == b'/' && bytes[1].is_ascii_alphabetic() && bytes[2] == b':';
+    Some(PathBuf::from(if windows_drive {
+        &path[1..]
+    } else {
+        &path[..]
+    }))
+}
+
+pub fn percent_decode(text: &str) -> String {
+    // %20 -> ' ' and so on, a % that isn't followed by two hex digits is kept as it is
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = match bytes.get(i + 1..i + 3) {
+            // from_str_radix alone would also take a sign, as in "%+1"
+            Some(hex) if bytes[i] == b'%' && hex.iter().all(u8::is_ascii_hexdigit) => {
+                std::str::from_utf8(hex)
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            }
+            _ => None,
+        };
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_file(name: &str, text: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("import_test_{}_{}", std::process::id(), name));
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    const ITUNES: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple Computer//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Major Version</key><integer>1</integer>
+    <key>Tracks</key>
+    <dict>
+        <key>101</key>
+        <dict>
+            <key>Track ID</key><integer>101</integer>
+            <key>Location</key><string>file:///Users/me/Music/Boards%20of%20Canada/Roygbiv.mp3</string>
+            <key>Play Count</key><integer>12</integer>
+            <key>Play Date UTC</key><date>2020-01-02T03:04:05Z</date>
+            <key>Rating</key><integer>80</integer>
+            <key>Loved</key><true/>
+        </dict>
+        <key>102</key>
+        <dict>
+            <key>Track ID</key><integer>102</integer>
+            <key>Location</key><string>file://localhost/C:/Music/Xtal.mp3</string>
+            <key>Rating</key><integer>60</integer>
+            <key>Rating Computed</key><true/>
+        </dict>
+        <key>103</key>
+        <dict>
+            <key>Track ID</key><integer>103</integer>
+            <key>Name</key><string>A radio stream</string>
+        </dict>
+        <key>104</key>
+        <dict>
+            <key>Track ID</key><integer>104</integer>
+            <key>Location</key><string>http://example.com/cloud.m4a</string>
+        </dict>
+    </dict>
+    <key>Playlists</key>
+    <array>
+        <dict>
+            <key>Name</key><string>Library</string>
+            <key>Master</key><true/>
+            <key>Playlist Items</key>
+            <array><dict><key>Track ID</key><integer>101</integer></dict></array>
+        </dict>
+        <dict>
+            <key>Name</key><string>Smart</string>
+            <key>Smart Info</key><data>AAAA</data>
+        </dict>
+        <dict>
+            <key>Name</key><string>Favourites</string>
+            <key>Playlist Items</key>
+            <array>
+                <dict><key>Track ID</key><integer>102</integer></dict>
+                <dict><key>Track ID</key><integer>101</integer></dict>
+                <dict><key>Track ID</key><integer>999</integer></dict>
+            </array>
+        </dict>
+    </array>
+</dict>
+</plist>
+"#;
+
+    const RHYTHMDB: &str = r#"<?xml version="1.0" standalone="yes"?>
+<rhythmdb version="2.0">
+  <entry type="song">
+    <title>Roygbiv</title>
+    <location>file:///home/me/Music/Roygbiv.mp3</location>
+    <play-count>7</play-count>
+    <last-played>1577934245</last-played>
+    <rating>4</rating>
+  </entry>
+  <entry type="song">
+    <location>file:///home/me/Music/Caf%C3%A9.flac</location>
+  </entry>
+  <entry type="iradio">
+    <location>http://example.com/stream</location>
+  </entry>
+  <entry type="song">
+    <location>smb://server/share/a.mp3</location>
+  </entry>
+</rhythmdb>
+"#;
+
+    const RHYTHMBOX_PLAYLISTS: &str = r#"<?xml version="1.0"?>
+<rhythmdb-playlists>
+  <playlist name="My top rated" type="automatic">
+    <conjunction><equals prop="type">song</equals></conjunction>
+  </playlist>
+  <playlist name="Mix" type="static">
+    <location>file:///home/me/Music/Caf%C3%A9.flac</location>
+    <location>file:///home/me/Music/Roygbiv.mp3</location>
+    <location>http://example.com/a.mp3</location>
+  </playlist>
+</rhythmdb-playlists>
+"#;
+
+    #[test]
+    fn paths_from_urls() {
+        assert_eq!(
+            path_from_url("file:///home/me/a%20b.mp3"),
+            Some(PathBuf::from("/home/me/a b.mp3"))
+        );
+        assert_eq!(
+            path_from_url("file://localhost/C:/Music/a.mp3"),
+            Some(PathBuf::from("C:/Music/a.mp3"))
+        );
+        assert_eq!(
+            path_from_url(" file:///Users/me/Caf%C3%A9.mp3 "),
+            Some(PathBuf::from("/Users/me/Café.mp3"))
+        );
+        assert_eq!(path_from_url("http://example.com/a.mp3"), None);
+        assert_eq!(path_from_url(""), None);
+    }
+
+    #[test]
+    fn paths_from_urls_with_multibyte_starts() {
+        // a multibyte first character followed by ':' isn't a drive letter
+        assert_eq!(path_from_url("file://é:x"), Some(PathBuf::from("é:x")));
+        assert_eq!(path_from_url("file:///é:x"), Some(PathBuf::from("/é:x")));
+        assert_eq!(path_from_url("file://ab:c"), Some(PathBuf::from("ab:c")));
+    }
+
+    #[test]
+    fn percent_decoding() {
+        assert_eq!(percent_decode("a%20b%2Fc"), "a b/c");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%4"), "%zz%4");
+        assert_eq!(percent_decode("%E2%99%AB"), "♫");
+        assert_eq!(percent_decode("%+1%-1%+F"), "%+1%-1%+F");
+        assert_eq!(percent_decode("%4a%4A"), "JJ");
+    }
+
+    #[test]
+    fn itunes_library() {
+        let path = temp_file("Library.xml", ITUNES);
+        let imported = read_itunes_library(&path).unwrap();
+        fs::remove_file(path).ok();
+
+        assert_eq!(imported.tracks.len(), 2);
+        let roygbiv = &imported.tracks[0];
+        assert_eq!(
+            roygbiv.path,
+            PathBuf::from("/Users/me/Music/Boards of Canada/Roygbiv.mp3")
+        );
+        assert_eq!(roygbiv.play_count, 12);
+        assert_eq!(roygbiv.last_played, Some(1577934245));
+        assert_eq!(roygbiv.rating, Some(4));
+        assert!(roygbiv.favourite);
+        let xtal = &imported.tracks[1];
+        assert_eq!(xtal.path, PathBuf::from("C:/Music/Xtal.mp3"));
+        assert_eq!(xtal.rating, None); // computed from the album
+        assert!(!xtal.favourite);
+
+        // the master and smart playlists are skipped, and so is the unknown track
+        assert_eq!(imported.playlists.len(), 1);
+        assert_eq!(imported.playlists[0].name, "Favourites");
+        assert_eq!(
+            imported.playlists[0].tracks,
+            vec![xtal.path.clone(), roygbiv.path.clone()]
+        );
+        assert_eq!(imported.skipped.len(), 2); // the cloud track and track 999
+    }
+
+    #[test]
+    fn itunes_library_needs_a_dict() {
+        let path = temp_file("NotALibrary.xml", "<plist><array/></plist>");
+        assert!(read_itunes_library(&path).is_err());
+        fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn rhythmbox_library() {
+        let database = temp_file("rhythmdb.xml", RHYTHMDB);
+        let playlists = temp_file("playlists.xml", RHYTHMBOX_PLAYLISTS);
+        let imported = read_rhythmbox_library(&database, Some(&playlists)).unwrap();
+        fs::remove_file(database).ok();
+        fs::remove_file(playlists).ok();
+
+        let roygbiv = PathBuf::from("/home/me/Music/Roygbiv.mp3");
+        let cafe = PathBuf::from("/home/me/Music/Café.flac");
+        assert_eq!(imported.tracks.len(), 2);
+        assert_eq!(imported.tracks[0].path, roygbiv);
+        assert_eq!(imported.tracks[0].play_count, 7);
+        assert_eq!(imported.tracks[0].last_played, Some(1577934245));
+        assert_eq!(imported.tracks[0].rating, Some(4));
+        assert_eq!(imported.tracks[1].path, cafe);
+        assert_eq!(imported.tracks[1].play_count, 0);
+        assert_eq!(imported.tracks[1].rating, None);
+
+        // only the static playlist is imported
+        assert_eq!(imported.playlists.len(), 1);
+        assert_eq!(imported.playlists[0].name, "Mix");
+        assert_eq!(imported.playlists[0].tracks, vec![cafe, roygbiv]);
+        assert_eq!(imported.skipped.len(), 2); // the smb:// song and the http:// playlist entry
+    }
+
+    #[test]
+    fn rhythmbox_library_needs_rhythmdb() {
+        let database = temp_file("notrhythmdb.xml", "<plist/>");
+        assert!(read_rhythmbox_library(&database, None).is_err());
+        fs::remove_file(database).ok();
+    }
+
+    #[test]
+    fn merge_keeps_the_highest_values() {
+        let mut library = vec![MusicFile {
+            id: 1,
+            file_path: PathBuf::from("/music/a.mp3"),
+            play_count: 20,
+            rating: Some(2),
+            ..Default::default()
+        }];
+        let mut playlists = Vec::new();
+        let imported = ImportedLibrary {
+            tracks: vec![ImportedTrack {
+                path: PathBuf::from("/music/a.mp3"),
+                play_count: 5,
+                last_played: Some(100),
+                rating: Some(4),
+                favourite: true,
+            }],
+            playlists: vec![ImportedPlaylist {
+                name: "Mix".to_string(),
+                tracks: vec![
+                    PathBuf::from("/music/a.mp3"),
+                    PathBuf::from("/music/missing.mp3"),
+                ],
+            }],
+            skipped: Vec::new(),
+        };
+        let report = imported.merge_into(&mut library, &mut playlists, &mut IdCounters::default());
+
+        assert_eq!(report.updated, 1);
+        assert_eq!(report.playlists, 1);
+        assert_eq!(report.unresolved.len(), 1);
+        assert_eq!(library[0].play_count, 20);
+        assert_eq!(library[0].last_played, Some(100));
+        assert_eq!(library[0].rating, Some(4));
+        assert!(library[0].favourite);
+        assert_eq!(playlists[0].tracks, vec![1]);
+    }
+
+    #[test]
+    fn merge_adds_files_that_exist_for_reading_later() {
+        let path = temp_file("new.mp3", "");
+        let mut library = Vec::new();
+        let imported = ImportedLibrary {
+            tracks: vec![
+                ImportedTrack {
+                    path: path.clone(),
+                    play_count: 3,
+                    favourite: true,
+                    ..Default::default()
+                },
+                ImportedTrack {
+                    path: PathBuf::from("/no/such/file.mp3"),
+                    ..Default::default()
+                },
+            ],
+            playlists: Vec::new(),
+            skipped: Vec::new(),
+        };
+        let report = imported.merge_into(&mut library, &mut Vec::new(), &mut IdCounters::default());
+
+        assert_eq!(report.added, 1);
+        assert_eq!(report.unresolved, vec!["/no/such/file.mp3: file not found"]);
+        // the tags are read by LibraryUpgrade, which keeps the imported play count and favourite
+        assert_eq!(library.len(), 1);
+        assert_eq!(library[0].file_path, path);
+        assert_eq!(library[0].version, 0);
+        assert_eq!(library[0].play_count, 3);
+        assert!(library[0].favourite);
+        assert_eq!(library[0].id, 1);
+        fs::remove_file(path).ok();
+    }
+}
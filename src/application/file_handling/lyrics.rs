@@ -0,0 +1,468 @@
+use id3::{Content, Frame, TagLike};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const LYRICS: &str = "LYRICS";
+const UNSYNCED_LYRICS: &str = "UNSYNCEDLYRICS";
+const LYRICS_LANGUAGE: &str = "eng";
+
+//---------------------------------------------------------------------------------------------------
+// Lyrics
+// Lyrics come from a sidecar file next to the track with the same name (song.lrc for synced lyrics,
+// song.txt for plain text) or from the track's own tags:
+//  - MP3 (ID3): a SYLT frame for synced lyrics (only with millisecond timestamps, MPEG frame
+//    timestamps aren't supported), otherwise a USLT frame with the text
+//  - FLAC (Vorbis comments): LYRICS, or UNSYNCEDLYRICS as some taggers write it
+// A .lrc sidecar wins over the tags, and the tags win over a .txt sidecar. Lyrics text from a tag
+// or a .txt sidecar that has LRC timestamps in it is read as synced lyrics.
+//
+// An LRC file has one or more [mm:ss.xx] timestamps in front of each line (a chorus can list all
+// the times it is sung), an optional [offset:+/-ms] tag that shifts every line, and other
+// [tag:value] lines (artist, album, ...) that we ignore. Word timings from the "enhanced" format
+// (<mm:ss.xx> inside a line) are dropped, lines are highlighted as a whole.
+// --------------------------------------------------------------------------------------------------
+#[derive(Clone, Debug, PartialEq)]
+pub struct LyricLine {
+    pub time: Option<f64>, // seconds, None for plain text lyrics
+    pub text: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum LyricsSource {
+    Sidecar(PathBuf),
+    #[default]
+    Embedded,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Lyrics {
+    pub lines: Vec<LyricLine>,
+    pub source: LyricsSource,
+}
+
+impl Lyrics {
+    pub fn is_synced(&self) -> bool {
+        self.lines.iter().any(|line| line.time.is_some())
+    }
+
+    pub fn current_line(&self, position: f64) -> Option<usize> {
+        // the last line that has started by now
+        let started = self
+            .lines
+            .partition_point(|line| matches!(line.time, Some(time) if time <= position));
+        started.checked_sub(1).filter(|_| self.is_synced())
+    }
+
+    pub fn to_text(&self) -> String {
+        // the text for editing: LRC when the lyrics are synced, so the timestamps survive a save
+        let mut text = String::new();
+        for line in &self.lines {
+            if let Some(time) = line.time {
+                let hundredths = (time * 100.0).round() as u64;
+                text.push_str(&format!(
+                    "[{:02}:{:02}.{:02}]",
+                    hundredths / 6000,
+                    hundredths / 100 % 60,
+                    hundredths % 100
+                ));
+            }
+            text.push_str(&line.text);
+            text.push('\n');
+        }
+        text
+    }
+}
+
+pub fn load_lyrics(track: &Path) -> Option<Lyrics> {
+    for extension in ["lrc", "LRC"] {
+        let sidecar = track.with_extension(extension);
+        if let Ok(text) = fs::read_to_string(&sidecar) {
+            return Some(Lyrics {
+                source: LyricsSource::Sidecar(sidecar),
+                ..parse_lrc(&text)
+            });
+        }
+    }
+    if let Some(lyrics) = read_embedded_lyrics(track) {
+        return Some(lyrics);
+    }
+    for extension in ["txt", "TXT"] {
+        let sidecar = track.with_extension(extension);
+        if let Ok(text) = fs::read_to_string(&sidecar) {
+            return Some(Lyrics {
+                source: LyricsSource::Sidecar(sidecar),
+                ..text_lyrics(&text)
+            });
+        }
+    }
+    None
+}
+
+pub fn read_embedded_lyrics(track: &Path) -> Option<Lyrics> {
+    let text = match extension(track).as_str() {
+        "mp3" => {
+            let tag = id3::Tag::read_from_path(track).ok()?;
+            let synced = tag
+                .synchronised_lyrics()
+                .find(|sylt| sylt.timestamp_format == id3::frame::TimestampFormat::Ms);
+            if let Some(sylt) = synced {
+                let mut lines: Vec<LyricLine> = sylt
+                    .content
+                    .iter()
+                    .map(|(ms, text)| LyricLine {
+                        time: Some(*ms as f64 / 1000.0),
+                        text: text.trim().to_string(),
+                    })
+                    .collect();
+                lines.sort_by(|a, b| {
+                    a.time
+                        .partial_cmp(&b.time)
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                });
+                return Some(Lyrics {
+                    lines,
+                    source: LyricsSource::Embedded,
+                });
+            }
+            let uslt = tag.lyrics().next()?.text.clone();
+            uslt
+        }
+        "flac" => {
+            let tag = metaflac::Tag::read_from_path(track).ok()?;
+            let values = tag
+                .get_vorbis(LYRICS)
+                .or_else(|| tag.get_vorbis(UNSYNCED_LYRICS))?;
+            values.collect::<Vec<&str>>().join("\n")
+        }
+        _ => return None,
+    };
+    if text.trim().is_empty() {
+        return None;
+    }
+    Some(text_lyrics(&text))
+}
+
+pub fn save_lyrics(track: &Path, source: &LyricsSource, text: &str) -> Result<(), String> {
+    // writes edited lyrics back where they were read from, lyrics that are new go into the tags
+    match source {
+        LyricsSource::Sidecar(sidecar) => fs::write(sidecar, text)
+            .map_err(|e| format!("couldn't write {}: {}", sidecar.display(), e)),
+        LyricsSource::Embedded => write_embedded_lyrics(track, text),
+    }
+}
+
+pub fn write_embedded_lyrics(track: &Path, text: &str) -> Result<(), String> {
+    // -----------------------------------------------------------------------------------------------
+    // empty text removes the lyrics. MP3s always get a USLT frame with the text as typed (players
+    // that don't read SYLT still show something), plus a SYLT frame when the text has timestamps.
+    // Only the frames we write (our language, no description) are replaced, lyrics other taggers
+    // added in other languages or under a description are kept, and the tag keeps its ID3 version
+    // -----------------------------------------------------------------------------------------------
+    let text = text.trim_end();
+    match extension(track).as_str() {
+        "mp3" => {
+            let mut tag = id3::no_tag_ok(id3::Tag::read_from_path(track))
+                .map_err(|e| e.to_string())?
+                .unwrap_or_default();
+            remove_own_lyrics(&mut tag);
+            if !text.is_empty() {
+                tag.add_frame(Frame::with_content(
+                    "USLT",
+                    Content::Lyrics(id3::frame::Lyrics {
+                        lang: LYRICS_LANGUAGE.to_string(),
+                        description: String::new(),
+                        text: text.to_string(),
+                    }),
+                ));
+                let synced = parse_lrc(text);
+                if synced.is_synced() {
+                    tag.add_frame(Frame::with_content(
+                        "SYLT",
+                        Content::SynchronisedLyrics(id3::frame::SynchronisedLyrics {
+                            lang: LYRICS_LANGUAGE.to_string(),
+                            timestamp_format: id3::frame::TimestampFormat::Ms,
+                            content_type: id3::frame::SynchronisedLyricsType::Lyrics,
+                            description: String::new(),
+                            content: synced
+                                .lines
+                                .into_iter()
+                                .map(|line| {
+                                    ((line.time.unwrap_or_default() * 1000.0) as u32, line.text)
+                                })
+                                .collect(),
+                        }),
+                    ));
+                }
+            }
+            let version = tag.version();
+            tag.write_to_path(track, version).map_err(|e| e.to_string())
+        }
+        "flac" => {
+            let mut tag = metaflac::Tag::read_from_path(track).map_err(|e| e.to_string())?;
+            tag.remove_vorbis(UNSYNCED_LYRICS);
+            if text.is_empty() {
+                tag.remove_vorbis(LYRICS);
+            } else {
+                tag.set_vorbis(LYRICS, vec![text.to_string()]);
+            }
+            tag.save().map_err(|e| e.to_string())
+        }
+        other => Err(format!("lyrics can't be saved in .{} files", other)),
+    }
+}
+
+fn remove_own_lyrics(tag: &mut id3::Tag) {
+    let mut others = Vec::new();
+    for frame in tag.remove("USLT").into_iter().chain(tag.remove("SYLT")) {
+        let own = match frame.content() {
+            Content::Lyrics(uslt) => is_own(&uslt.lang, &uslt.description),
+            Content::SynchronisedLyrics(sylt) => is_own(&sylt.lang, &sylt.description),
+            _ => false,
+        };
+        if !own {
+            others.push(frame);
+        }
+    }
+    for frame in others {
+        tag.add_frame(frame);
+    }
+}
+
+fn is_own(lang: &str, description: &str) -> bool {
+    lang == LYRICS_LANGUAGE && description.is_empty()
+}
+
+fn text_lyrics(text: &str) -> Lyrics {
+    // text that has LRC timestamps in it is synced lyrics, anything else is plain text
+    let lyrics = parse_lrc(text);
+    if lyrics.is_synced() {
+        lyrics
+    } else {
+        plain_lyrics(text)
+    }
+}
+
+fn plain_lyrics(text: &str) -> Lyrics {
+    Lyrics {
+        lines: text
+            .lines()
+            .map(|line| LyricLine {
+                time: None,
+                text: line.trim_end().to_string(),
+            })
+            .collect(),
+        source: LyricsSource::Embedded,
+    }
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+pub fn parse_lrc(text: &str) -> Lyrics {
+    let mut lines = Vec::new();
+    let mut offset = 0.0;
+    for line in text.lines() {
+        let mut rest = line.trim();
+        let mut times = Vec::new();
+        while let Some(tag_end) = rest.strip_prefix('[').and_then(|r| r.find(']')) {
+            let tag = &rest[1..tag_end + 1];
+            rest = rest[tag_end + 2..].trim_start();
+            match parse_timestamp(tag) {
+                Some(time) => times.push(time),
+                None => {
+                    if let Some(value) = tag.strip_prefix("offset:") {
+                        // milliseconds, positive means the lyrics come earlier
+                        offset = value.trim().parse::<f64>().unwrap_or_default() / 1000.0;
+                    }
+                }
+            }
+        }
+        let text = strip_word_times(rest);
+        for time in times {
+            lines.push(LyricLine {
+                time: Some(time),
+                text: text.clone(),
+            });
+        }
+    }
+
+    for line in &mut lines {
+        line.time = line.time.map(|time| (time - offset).max(0.0));
+    }
+    lines.sort_by(|a, b| {
+        a.time
+            .partial_cmp(&b.time)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    Lyrics {
+        lines,
+        source: LyricsSource::Embedded,
+    }
+}
+
+fn parse_timestamp(tag: &str) -> Option<f64> {
+    // mm:ss, mm:ss.xx or mm:ss:xx
+    let (minutes, seconds) = tag.split_once(':')?;
+    let minutes: f64 = minutes.trim().parse().ok()?;
+    let seconds: f64 = match seconds.split_once(':') {
+        Some((seconds, hundredths)) => format!("{}.{}", seconds, hundredths).parse().ok()?,
+        None => seconds.parse().ok()?,
+    };
+    Some(minutes * 60.0 + seconds)
+}
+
+fn strip_word_times(text: &str) -> String {
+    let mut stripped = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        match rest[start..].find('>') {
+            Some(end) if parse_timestamp(&rest[start + 1..start + end]).is_some() => {
+                stripped.push_str(&rest[..start]);
+                rest = &rest[start + end + 1..];
+            }
+            _ => {
+                stripped.push_str(&rest[..start + 1]);
+                rest = &rest[start + 1..];
+            }
+        }
+    }
+    stripped.push_str(rest);
+    stripped.trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timed(lyrics: &Lyrics) -> Vec<(f64, &str)> {
+        // times rounded to hundredths, which is what LRC can store
+        lyrics
+            .lines
+            .iter()
+            .map(|line| {
+                let time = (line.time.unwrap() * 100.0).round() / 100.0;
+                (time, line.text.as_str())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parse_lines_and_ignore_tags() {
+        let lyrics = parse_lrc("[ar:Artist]\n[ti:Title]\n[00:01.50]First\n[01:02.25] Second \n\n");
+        assert!(lyrics.is_synced());
+        assert_eq!(timed(&lyrics), vec![(1.5, "First"), (62.25, "Second")]);
+    }
+
+    #[test]
+    fn multiple_timestamps_become_sorted_lines() {
+        let lyrics = parse_lrc("[00:30.00][00:10.00]Chorus\n[00:20.00]Verse\n");
+        assert_eq!(
+            timed(&lyrics),
+            vec![(10.0, "Chorus"), (20.0, "Verse"), (30.0, "Chorus")]
+        );
+    }
+
+    #[test]
+    fn offset_shifts_every_line() {
+        let lyrics = parse_lrc("[00:10.00]A\n[offset:+500]\n[00:00.20]B\n");
+        assert_eq!(timed(&lyrics), vec![(0.0, "B"), (9.5, "A")]);
+        let lyrics = parse_lrc("[offset:-1000]\n[00:10.00]A\n");
+        assert_eq!(timed(&lyrics), vec![(11.0, "A")]);
+    }
+
+    #[test]
+    fn timestamp_formats() {
+        let lyrics = parse_lrc("[00:05]A\n[00:06:50]B\n[1:07.5]C\n");
+        assert_eq!(timed(&lyrics), vec![(5.0, "A"), (6.5, "B"), (67.5, "C")]);
+    }
+
+    #[test]
+    fn word_times_are_dropped() {
+        let lyrics = parse_lrc("[00:01.00]<00:01.00>Hello <00:01.50>world <b>\n");
+        assert_eq!(timed(&lyrics), vec![(1.0, "Hello world <b>")]);
+    }
+
+    #[test]
+    fn to_text_writes_lrc() {
+        let lyrics = parse_lrc("[00:30.00][00:01.50]A\n[61:02.25]B\n");
+        assert_eq!(lyrics.to_text(), "[00:01.50]A\n[00:30.00]A\n[61:02.25]B\n");
+    }
+
+    #[test]
+    fn round_trip_keeps_offset_and_repeated_lines() {
+        let original = "[offset:250]\n[00:12.34][01:00.00]Chorus\n[00:20.10]Verse\n";
+        let lyrics = parse_lrc(original);
+        let reparsed = parse_lrc(&lyrics.to_text());
+        // the offset is applied once, saving doesn't shift the lines again
+        assert_eq!(timed(&reparsed), timed(&lyrics));
+        assert_eq!(
+            timed(&reparsed),
+            vec![(12.09, "Chorus"), (19.85, "Verse"), (59.75, "Chorus")]
+        );
+    }
+
+    #[test]
+    fn current_line() {
+        let lyrics = parse_lrc("[00:10.00]A\n[00:20.00]B\n");
+        assert_eq!(lyrics.current_line(5.0), None);
+        assert_eq!(lyrics.current_line(10.0), Some(0));
+        assert_eq!(lyrics.current_line(25.0), Some(1));
+
+        let plain = plain_lyrics("A\nB\n");
+        assert!(!plain.is_synced());
+        assert_eq!(plain.current_line(25.0), None);
+        assert_eq!(plain.to_text(), "A\nB\n");
+    }
+
+    #[test]
+    fn txt_sidecar_with_timestamps_is_synced() {
+        let dir = std::env::temp_dir().join(format!("lyrics_test_{}_txt", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let track = dir.join("song.ogg");
+        fs::write(dir.join("song.txt"), "[00:01.00]A\n[00:02.00]B\n").unwrap();
+        let lyrics = load_lyrics(&track).unwrap();
+        assert_eq!(lyrics.source, LyricsSource::Sidecar(dir.join("song.txt")));
+        assert_eq!(timed(&lyrics), vec![(1.0, "A"), (2.0, "B")]);
+
+        fs::write(dir.join("song.txt"), "A\nB\n").unwrap();
+        assert!(!load_lyrics(&track).unwrap().is_synced());
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn write_keeps_other_lyrics_and_the_tag_version() {
+        let path = std::env::temp_dir().join(format!("lyrics_test_{}.mp3", std::process::id()));
+        fs::write(&path, b"").unwrap();
+        let mut tag = id3::Tag::with_version(id3::Version::Id3v23);
+        for (lang, description, text) in [("eng", "", "old"), ("deu", "", "Deutsch")] {
+            tag.add_frame(Frame::with_content(
+                "USLT",
+                Content::Lyrics(id3::frame::Lyrics {
+                    lang: lang.to_string(),
+                    description: description.to_string(),
+                    text: text.to_string(),
+                }),
+            ));
+        }
+        tag.write_to_path(&path, id3::Version::Id3v23).unwrap();
+
+        write_embedded_lyrics(&path, "new").unwrap();
+        let written = id3::Tag::read_from_path(&path).unwrap();
+        assert_eq!(written.version(), id3::Version::Id3v23);
+        let mut texts: Vec<(&str, &str)> = written
+            .lyrics()
+            .map(|uslt| (uslt.lang.as_str(), uslt.text.as_str()))
+            .collect();
+        texts.sort();
+        assert_eq!(texts, vec![("deu", "Deutsch"), ("eng", "new")]);
+
+        write_embedded_lyrics(&path, "").unwrap();
+        let written = id3::Tag::read_from_path(&path).unwrap();
+        let texts: Vec<&str> = written.lyrics().map(|uslt| uslt.text.as_str()).collect();
+        assert_eq!(texts, vec!["Deutsch"]);
+        fs::remove_file(path).ok();
+    }
+}
@@ -1,2 +1,21 @@
 pub mod file_handling;
-pub mod audio_player;
\ No newline at end of file
+pub mod audio_player;
+pub mod tag_editor;
+pub mod album_art;
+pub mod albums;
+pub mod browser;
+pub mod search;
+pub mod query;
+pub mod smart_playlist;
+pub mod columns;
+pub mod ratings;
+pub mod history;
+pub mod relocate;
+pub mod import;
+pub mod export;
+pub mod lyrics;
+pub mod roots;
+pub mod profiles;
+pub mod playlist_files;
+pub mod playlist_folders;
+pub mod playlist_stats;
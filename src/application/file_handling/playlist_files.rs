@@ -0,0 +1,902 @@
+use super::file_handling::{
+    assign_track_ids, unread_music_file, IdCounters, MusicCollection, MusicFile,
+};
+use super::import::{path_from_url, percent_decode};
+use roxmltree::Document;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+//---------------------------------------------------------------------------------------------------
+// Playlist files
+// Playlists can be exported to files other players read, and playlist files can be imported as new
+// playlists. The format comes from the file extension:
+//  - M3U / M3U8: one path per line, with an #EXTINF line in front giving the duration in seconds and
+//    "artist - title". .m3u8 files are UTF-8, plain .m3u files from older players may be Latin-1
+//  - PLS: an ini style [playlist] section with numbered File1=, Title1= and Length1= keys
+//  - XSPF: XML with a <track> per entry, giving its location as a url along with title, creator
+//    (the artist), album and duration in milliseconds
+// Paths in a playlist file are relative to the folder the file is in, unless they are absolute or
+// file:// urls. When importing, entries are matched to the library by path first. Files that aren't
+// in the library yet are added to it and have their tags read in the background by LibraryUpgrade,
+// and entries whose file can't be found are matched by their title, artist, album and duration
+// instead. What still doesn't match is reported.
+// --------------------------------------------------------------------------------------------------
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PlaylistFormat {
+    M3u,
+    Pls,
+    Xspf,
+}
+
+impl PlaylistFormat {
+    pub fn from_path(path: &Path) -> Result<PlaylistFormat, String> {
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match extension.as_str() {
+            "m3u" | "m3u8" => Ok(PlaylistFormat::M3u),
+            "pls" => Ok(PlaylistFormat::Pls),
+            "xspf" => Ok(PlaylistFormat::Xspf),
+            _ => Err(format!(
+                "{} isn't a playlist file, use .m3u, .m3u8, .pls or .xspf",
+                path.display()
+            )),
+        }
+    }
+}
+
+//---------------------------------------------------------------------------------------------------
+// PlaylistEntry struct
+// One entry of a playlist file as it was written, before it is matched to the library. location is
+// the text from the file, for the report when the entry can't be resolved
+// --------------------------------------------------------------------------------------------------
+#[derive(Clone, Debug, Default)]
+pub struct PlaylistEntry {
+    pub location: String,
+    pub path: Option<PathBuf>,
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub duration: Option<f64>, // seconds
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PlaylistFile {
+    pub title: Option<String>, // only XSPF files have one
+    pub entries: Vec<PlaylistEntry>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PlaylistImport {
+    pub name: String,
+    pub tracks: usize,
+    pub added: usize,           // tracks that weren't in the library before
+    pub matched_by_tags: usize, // entries whose file wasn't found, matched by title/artist/album
+    pub unresolved: Vec<String>,
+}
+
+impl PlaylistImport {
+    pub fn summary(&self) -> String {
+        format!(
+            "Imported \"{}\" with {} tracks ({} new to the library, {} matched by tags). {} entries \
+             couldn't be resolved.",
+            self.name,
+            self.tracks,
+            self.added,
+            self.matched_by_tags,
+            self.unresolved.len()
+        )
+    }
+}
+
+pub fn import_playlist(
+    path: &Path,
+    library: &mut Vec<MusicFile>,
+    playlists: &mut Vec<MusicCollection>,
+    ids: &mut IdCounters,
+) -> Result<PlaylistImport, String> {
+    let file = read_playlist_file(path)?;
+
+    // "name", "name (2)", ... so importing the same file twice doesn't clash
+    let stem = file
+        .title
+        .filter(|title| !title.trim().is_empty())
+        .or_else(|| path.file_stem().map(|s| s.to_string_lossy().to_string()))
+        .unwrap_or_else(|| "Imported playlist".to_string());
+    let mut name = stem.clone();
+    let mut n = 1;
+    while playlists.iter().any(|p| p.name == name) {
+        n += 1;
+        name = format!("{} ({})", stem, n);
+    }
+
+    let mut report = PlaylistImport {
+        name: name.clone(),
+        ..Default::default()
+    };
+    let mut index = LibraryIndex::new(library);
+    let mut positions = Vec::new();
+    for entry in file.entries {
+        match resolve_entry(&entry, library, &mut index, &mut report) {
+            Some(i) => positions.push(i),
+            None => report.unresolved.push(entry.location),
+        }
+    }
+    // the files that were added to the library need their ids before the playlist can refer to them
+    assign_track_ids(library, ids);
+    let mut playlist = MusicCollection::new(name, ids.next_playlist_id());
+    for i in positions {
+        playlist.add_song(library[i].id);
+    }
+    report.tracks = playlist.song_count();
+    playlists.push(playlist);
+    Ok(report)
+}
+
+// where the library's tracks are by path and by title, built once per import
+struct LibraryIndex {
+    by_path: HashMap<PathBuf, usize>,
+    by_title: HashMap<String, Vec<usize>>,
+}
+
+impl LibraryIndex {
+    fn new(library: &[MusicFile]) -> LibraryIndex {
+        let mut index = LibraryIndex {
+            by_path: HashMap::new(),
+            by_title: HashMap::new(),
+        };
+        for (i, music_file) in library.iter().enumerate() {
+            index
+                .by_path
+                .entry(music_file.file_path.clone())
+                .or_insert(i);
+            index
+                .by_title
+                .entry(title_key(&music_file.title))
+                .or_default()
+                .push(i);
+        }
+        index
+    }
+}
+
+fn title_key(title: &str) -> String {
+    title.trim().to_ascii_lowercase()
+}
+
+fn resolve_entry(
+    entry: &PlaylistEntry,
+    library: &mut Vec<MusicFile>,
+    index: &mut LibraryIndex,
+    report: &mut PlaylistImport,
+) -> Option<usize> {
+    // the position of the entry's track in the library
+    if let Some(path) = &entry.path {
+        if let Some(&i) = index.by_path.get(path) {
+            return Some(i);
+        }
+        if path.is_file() {
+            library.push(unread_music_file(path));
+            index.by_path.insert(path.clone(), library.len() - 1);
+            report.added += 1;
+            return Some(library.len() - 1);
+        }
+    }
+    let i = *index
+        .by_title
+        .get(&title_key(&entry.title))?
+        .iter()
+        .find(|&&i| same_track(entry, &library[i]))?;
+    report.matched_by_tags += 1;
+    Some(i)
+}
+
+fn same_track(entry: &PlaylistEntry, music_file: &MusicFile) -> bool {
+    // -----------------------------------------------------------------------------------------------
+    // the title has to be the same, and so do the artist, album and duration (within 3 seconds) when
+    // the entry has them. a playlist entry with nothing but a title is too vague to match
+    // -----------------------------------------------------------------------------------------------
+    let same = |a: &str, b: &str| a.trim().eq_ignore_ascii_case(b.trim());
+    let known = |text: &str| !text.trim().is_empty();
+    if !known(&entry.title) || !(known(&entry.artist) || known(&entry.album)) {
+        return false;
+    }
+    let same_duration = match entry.duration {
+        Some(duration) if music_file.duration > 0.0 => (duration - music_file.duration).abs() < 3.0,
+        _ => true,
+    };
+    same(&entry.title, &music_file.title)
+        && (!known(&entry.artist) || same(&entry.artist, &music_file.artist))
+        && (!known(&entry.album) || same(&entry.album, &music_file.album))
+        && same_duration
+}
+
+pub fn read_playlist_file(path: &Path) -> Result<PlaylistFile, String> {
+    let bytes = fs::read(path).map_err(|e| format!("couldn't read {}: {}", path.display(), e))?;
+    // anything that isn't UTF-8 is taken to be Latin-1, which maps byte for byte to chars
+    let text = String::from_utf8(bytes)
+        .unwrap_or_else(|e| e.into_bytes().iter().map(|&b| b as char).collect());
+    let text = text.trim_start_matches('\u{feff}');
+    let folder = path.parent().unwrap_or_else(|| Path::new(""));
+    match PlaylistFormat::from_path(path)? {
+        PlaylistFormat::M3u => Ok(PlaylistFile {
+            title: None,
+            entries: parse_m3u(text, folder),
+        }),
+        PlaylistFormat::Pls => Ok(PlaylistFile {
+            title: None,
+            entries: parse_pls(text, folder),
+        }),
+        PlaylistFormat::Xspf => parse_xspf(text, folder),
+    }
+}
+
+pub fn export_playlist(
+    playlist: &MusicCollection,
+    library: &[MusicFile],
+    path: &Path,
+    relative: bool,
+) -> Result<(), String> {
+    let folder = path.parent().unwrap_or_else(|| Path::new(""));
+    let tracks = playlist.songs(library);
+    let text = match PlaylistFormat::from_path(path)? {
+        PlaylistFormat::M3u => write_m3u(&tracks, folder, relative),
+        PlaylistFormat::Pls => write_pls(&tracks, folder, relative),
+        PlaylistFormat::Xspf => write_xspf(&playlist.name, &tracks, folder, relative),
+    };
+    fs::write(path, text).map_err(|e| format!("couldn't write {}: {}", path.display(), e))
+}
+
+fn parse_m3u(text: &str, folder: &Path) -> Vec<PlaylistEntry> {
+    let mut entries = Vec::new();
+    let mut info: Option<(Option<f64>, String)> = None;
+    for line in text.lines() {
+        let line = line.trim();
+        if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+            // #EXTINF:123,Artist - Title, the duration can be followed by attributes before the comma
+            let (duration, display) = extinf.split_once(',').unwrap_or((extinf, ""));
+            let duration = duration
+                .split_whitespace()
+                .next()
+                .and_then(|d| d.parse::<f64>().ok())
+                .filter(|d| *d >= 0.0);
+            info = Some((duration, display.trim().to_string()));
+            continue;
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (duration, display) = info.take().unwrap_or_default();
+        let (artist, title) = match display.split_once(" - ") {
+            Some((artist, title)) => (artist.to_string(), title.to_string()),
+            None => (String::new(), display),
+        };
+        entries.push(PlaylistEntry {
+            location: line.to_string(),
+            path: entry_path(line, folder),
+            title,
+            artist,
+            duration,
+            ..Default::default()
+        });
+    }
+    entries
+}
+
+fn write_m3u(tracks: &[&MusicFile], folder: &Path, relative: bool) -> String {
+    let mut text = String::from("#EXTM3U\n");
+    for music_file in tracks {
+        let duration = if music_file.duration > 0.0 {
+            music_file.duration.round() as i64
+        } else {
+            -1
+        };
+        text.push_str(&format!(
+            "#EXTINF:{},{}\n",
+            duration,
+            display_title(music_file)
+        ));
+        text.push_str(&export_path(&music_file.file_path, folder, relative).to_string_lossy());
+        text.push('\n');
+    }
+    text
+}
+
+fn parse_pls(text: &str, folder: &Path) -> Vec<PlaylistEntry> {
+    // entries are numbered from 1, but the keys can come in any order and numbers can be missing
+    let mut numbered: Vec<(u32, PlaylistEntry)> = Vec::new();
+    for line in text.lines() {
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim().to_lowercase(), value.trim()),
+            None => continue,
+        };
+        let (name, number) = match key.find(|c: char| c.is_ascii_digit()) {
+            Some(start) => (&key[..start], key[start..].parse::<u32>()),
+            None => continue,
+        };
+        let number = match number {
+            Ok(number) => number,
+            Err(_) => continue,
+        };
+        let i = match numbered.iter().position(|(n, _)| *n == number) {
+            Some(i) => i,
+            None => {
+                numbered.push((number, PlaylistEntry::default()));
+                numbered.len() - 1
+            }
+        };
+        let entry = &mut numbered[i].1;
+        match name {
+            "file" => {
+                entry.location = value.to_string();
+                entry.path = entry_path(value, folder);
+            }
+            "title" => match value.split_once(" - ") {
+                Some((artist, title)) => {
+                    entry.artist = artist.to_string();
+                    entry.title = title.to_string();
+                }
+                None => entry.title = value.to_string(),
+            },
+            "length" => {
+                entry.duration = value.parse::<f64>().ok().filter(|d| *d >= 0.0);
+            }
+            _ => {}
+        }
+    }
+    numbered.sort_by_key(|(number, _)| *number);
+    numbered
+        .into_iter()
+        .map(|(_, entry)| entry)
+        .filter(|entry| !entry.location.is_empty())
+        .collect()
+}
+
+fn write_pls(tracks: &[&MusicFile], folder: &Path, relative: bool) -> String {
+    let mut text = String::from("[playlist]\n");
+    for (i, music_file) in tracks.iter().enumerate() {
+        let number = i + 1;
+        let duration = if music_file.duration > 0.0 {
+            music_file.duration.round() as i64
+        } else {
+            -1
+        };
+        text.push_str(&format!(
+            "File{}={}\nTitle{}={}\nLength{}={}\n",
+            number,
+            export_path(&music_file.file_path, folder, relative).to_string_lossy(),
+            number,
+            display_title(music_file),
+            number,
+            duration
+        ));
+    }
+    text.push_str(&format!("NumberOfEntries={}\nVersion=2\n", tracks.len()));
+    text
+}
+
+fn parse_xspf(text: &str, folder: &Path) -> Result<PlaylistFile, String> {
+    let document = Document::parse(text).map_err(|e| e.to_string())?;
+    let root = document.root_element();
+    if !root.has_tag_name("playlist") {
+        return Err("not an XSPF playlist".to_string());
+    }
+    let child_text = |node: roxmltree::Node, name: &str| -> String {
+        node.children()
+            .find(|child| child.has_tag_name(name))
+            .and_then(|child| child.text())
+            .map(|text| text.trim().to_string())
+            .unwrap_or_default()
+    };
+
+    let tracks = root
+        .descendants()
+        .filter(|node| node.has_tag_name("trackList"))
+        .flat_map(|list| list.children().filter(|node| node.has_tag_name("track")));
+    let mut entries = Vec::new();
+    for track in tracks {
+        // a track can list several locations for the same song, the first one that is a file wins
+        let locations: Vec<String> = track
+            .children()
+            .filter(|child| child.has_tag_name("location"))
+            .filter_map(|child| child.text())
+            .map(|text| text.trim().to_string())
+            .collect();
+        let path = locations.iter().find_map(|location| {
+            if location.contains("://") {
+                entry_path(location, folder)
+            } else {
+                entry_path(&percent_decode(location), folder)
+            }
+        });
+        entries.push(PlaylistEntry {
+            location: locations
+                .first()
+                .cloned()
+                .unwrap_or_else(|| child_text(track, "title")),
+            path,
+            title: child_text(track, "title"),
+            artist: child_text(track, "creator"),
+            album: child_text(track, "album"),
+            duration: child_text(track, "duration")
+                .parse::<f64>()
+                .ok()
+                .map(|ms| ms / 1000.0),
+        });
+    }
+    Ok(PlaylistFile {
+        title: Some(child_text(root, "title")).filter(|title| !title.is_empty()),
+        entries,
+    })
+}
+
+fn write_xspf(name: &str, tracks: &[&MusicFile], folder: &Path, relative: bool) -> String {
+    let mut text = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n",
+    );
+    text.push_str(&format!("  <title>{}</title>\n", xml_escape(name)));
+    text.push_str("  <trackList>\n");
+    for music_file in tracks {
+        text.push_str("    <track>\n");
+        let location = url(&export_path(&music_file.file_path, folder, relative));
+        text.push_str(&format!(
+            "      <location>{}</location>\n",
+            xml_escape(&location)
+        ));
+        let title = if music_file.title.is_empty() {
+            &music_file.name
+        } else {
+            &music_file.title
+        };
+        for (tag, value) in [
+            ("title", title),
+            ("creator", &music_file.artist),
+            ("album", &music_file.album),
+        ] {
+            if !value.is_empty() {
+                text.push_str(&format!("      <{0}>{1}</{0}>\n", tag, xml_escape(value)));
+            }
+        }
+        if music_file.duration > 0.0 {
+            text.push_str(&format!(
+                "      <duration>{}</duration>\n",
+                (music_file.duration * 1000.0).round() as u64
+            ));
+        }
+        text.push_str("    </track>\n");
+    }
+    text.push_str("  </trackList>\n</playlist>\n");
+    text
+}
+
+fn display_title(music_file: &MusicFile) -> String {
+    // "artist - title" as M3U and PLS players show it
+    let title = if music_file.title.is_empty() {
+        &music_file.name
+    } else {
+        &music_file.title
+    };
+    if music_file.artist.is_empty() {
+        title.to_string()
+    } else {
+        format!("{} - {}", music_file.artist, title)
+    }
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn url(path: &Path) -> String {
+    // -----------------------------------------------------------------------------------------------
+    // XSPF locations are urls: file:///home/me/a%20b.mp3 or file:///C:/Music/a.mp3 for absolute paths,
+    // a%20b.mp3 or ../Music/a.mp3 for relative ones. everything but letters, digits, -._~ and the
+    // separators is percent encoded
+    // -----------------------------------------------------------------------------------------------
+    let text = path.to_string_lossy().replace('\\', "/");
+    let mut encoded = String::new();
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/:".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    if !path.is_absolute() {
+        encoded
+    } else if encoded.starts_with('/') {
+        format!("file://{}", encoded)
+    } else {
+        format!("file:///{}", encoded)
+    }
+}
+
+fn entry_path(location: &str, folder: &Path) -> Option<PathBuf> {
+    if location.starts_with("file://") {
+        return path_from_url(location);
+    }
+    if location.contains("://") {
+        // streams and other urls aren't files we can play from the library
+        return None;
+    }
+    // playlists written on windows use backslashes, which are part of the name everywhere else
+    let location = if cfg!(windows) {
+        location.to_string()
+    } else {
+        location.replace('\\', "/")
+    };
+    Some(normalize(&folder.join(location)))
+}
+
+fn normalize(path: &Path) -> PathBuf {
+    // folds away . and .. so "lists/../music/a.mp3" matches "music/a.mp3" in the library
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    normalized.push("..");
+                }
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+fn export_path(path: &Path, folder: &Path, relative: bool) -> PathBuf {
+    match relative_path(path, folder) {
+        Some(relative_path) if relative => relative_path,
+        _ => path.to_path_buf(),
+    }
+}
+
+fn relative_path(path: &Path, folder: &Path) -> Option<PathBuf> {
+    // -----------------------------------------------------------------------------------------------
+    // the path as seen from the folder, with .. for every folder that has to be left first. None when
+    // the two don't share a root (e.g. different drives on windows) or either one isn't absolute
+    // -----------------------------------------------------------------------------------------------
+    if !path.is_absolute() || !folder.is_absolute() {
+        return None;
+    }
+    let path: Vec<Component> = path.components().collect();
+    let folder: Vec<Component> = folder.components().collect();
+    if path.first() != folder.first() {
+        return None;
+    }
+    let common = path.iter().zip(&folder).take_while(|(a, b)| a == b).count();
+    let mut relative = PathBuf::new();
+    for _ in common..folder.len() {
+        relative.push("..");
+    }
+    relative.extend(&path[common..]);
+    Some(relative)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(path: &str, artist: &str, title: &str, duration: f64) -> MusicFile {
+        MusicFile {
+            file_path: PathBuf::from(path),
+            name: Path::new(path)
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .to_string(),
+            artist: artist.to_string(),
+            title: title.to_string(),
+            duration,
+            ..Default::default()
+        }
+    }
+
+    fn refs(tracks: &[MusicFile]) -> Vec<&MusicFile> {
+        tracks.iter().collect()
+    }
+
+    fn paths(entries: &[PlaylistEntry]) -> Vec<Option<PathBuf>> {
+        entries.iter().map(|entry| entry.path.clone()).collect()
+    }
+
+    #[test]
+    fn parse_m3u_entries() {
+        let text = "#EXTM3U\n\
+                    #EXTINF:123 tvg-id=\"x\",Boards of Canada - Roygbiv\n\
+                    music/Roygbiv.mp3\n\
+                    # a comment\n\
+                    \n\
+                    /abs/Xtal.mp3\n\
+                    #EXTINF:-1,Stream\n\
+                    http://example.com/stream\n\
+                    ../other/./a.mp3\n";
+        let entries = parse_m3u(text, Path::new("/lists"));
+
+        assert_eq!(
+            paths(&entries),
+            vec![
+                Some(PathBuf::from("/lists/music/Roygbiv.mp3")),
+                Some(PathBuf::from("/abs/Xtal.mp3")),
+                None,
+                Some(PathBuf::from("/other/a.mp3")),
+            ]
+        );
+        assert_eq!(entries[0].artist, "Boards of Canada");
+        assert_eq!(entries[0].title, "Roygbiv");
+        assert_eq!(entries[0].duration, Some(123.0));
+        // the #EXTINF only belongs to the line right after it
+        assert_eq!(entries[1].title, "");
+        assert_eq!(entries[1].duration, None);
+        assert_eq!(entries[2].title, "Stream");
+        assert_eq!(entries[2].duration, None);
+        assert_eq!(entries[2].location, "http://example.com/stream");
+    }
+
+    #[test]
+    fn parse_m3u_file_urls_and_backslashes() {
+        let text = "file:///music/a%20b.mp3\nsub\\c.mp3\n";
+        let entries = parse_m3u(text, Path::new("/lists"));
+        let backslash = if cfg!(windows) {
+            PathBuf::from("/lists/sub\\c.mp3")
+        } else {
+            PathBuf::from("/lists/sub/c.mp3")
+        };
+        assert_eq!(
+            paths(&entries),
+            vec![Some(PathBuf::from("/music/a b.mp3")), Some(backslash)]
+        );
+    }
+
+    #[test]
+    fn m3u_round_trip() {
+        let tracks = vec![
+            track(
+                "/music/Boards of Canada/Roygbiv.mp3",
+                "Boards of Canada",
+                "Roygbiv",
+                151.4,
+            ),
+            track("/lists/Xtal.mp3", "", "", 0.0),
+        ];
+        let text = write_m3u(&refs(&tracks), Path::new("/lists"), true);
+        assert_eq!(
+            text,
+            "#EXTM3U\n\
+             #EXTINF:151,Boards of Canada - Roygbiv\n\
+             ../music/Boards of Canada/Roygbiv.mp3\n\
+             #EXTINF:-1,Xtal.mp3\n\
+             Xtal.mp3\n"
+        );
+
+        let entries = parse_m3u(&text, Path::new("/lists"));
+        assert_eq!(
+            paths(&entries),
+            tracks
+                .iter()
+                .map(|t| Some(t.file_path.clone()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(entries[0].artist, "Boards of Canada");
+        assert_eq!(entries[0].title, "Roygbiv");
+        assert_eq!(entries[0].duration, Some(151.0));
+        assert_eq!(entries[1].duration, None);
+    }
+
+    #[test]
+    fn absolute_export_paths() {
+        let tracks = vec![track("/music/a.mp3", "", "A", 1.0)];
+        let text = write_m3u(&refs(&tracks), Path::new("/lists"), false);
+        assert!(text.ends_with("\n/music/a.mp3\n"));
+    }
+
+    #[test]
+    fn relative_paths() {
+        let relative = |path: &str, folder: &str| relative_path(Path::new(path), Path::new(folder));
+        assert_eq!(relative("/a/b/c.mp3", "/a"), Some(PathBuf::from("b/c.mp3")));
+        assert_eq!(
+            relative("/a/c.mp3", "/a/b"),
+            Some(PathBuf::from("../c.mp3"))
+        );
+        assert_eq!(
+            relative("/x/c.mp3", "/a/b"),
+            Some(PathBuf::from("../../x/c.mp3"))
+        );
+        assert_eq!(relative("c.mp3", "/a"), None);
+    }
+
+    #[test]
+    fn parse_pls_out_of_order_and_missing_numbers() {
+        let text = "[playlist]\n\
+                    Title3=Aphex Twin - Xtal\n\
+                    File3=/music/Xtal.mp3\n\
+                    Length3=291\n\
+                    file1 = /music/Roygbiv.mp3\n\
+                    Title1=Roygbiv\n\
+                    Length1=-1\n\
+                    Title2=No file for this one\n\
+                    File10=/music/Ten.mp3\n\
+                    FileX=/music/not-numbered.mp3\n\
+                    NumberOfEntries=4\n\
+                    Version=2\n";
+        let entries = parse_pls(text, Path::new("/lists"));
+
+        // sorted by number, 10 after 3, and entries without a file are dropped
+        assert_eq!(
+            paths(&entries),
+            vec![
+                Some(PathBuf::from("/music/Roygbiv.mp3")),
+                Some(PathBuf::from("/music/Xtal.mp3")),
+                Some(PathBuf::from("/music/Ten.mp3")),
+            ]
+        );
+        assert_eq!(entries[0].title, "Roygbiv");
+        assert_eq!(entries[0].duration, None);
+        assert_eq!(entries[1].artist, "Aphex Twin");
+        assert_eq!(entries[1].title, "Xtal");
+        assert_eq!(entries[1].duration, Some(291.0));
+        assert_eq!(entries[2].title, "");
+    }
+
+    #[test]
+    fn pls_round_trip() {
+        let tracks = vec![
+            track("/music/Roygbiv.mp3", "Boards of Canada", "Roygbiv", 151.0),
+            track("/music/sub/Xtal.mp3", "", "Xtal", 0.0),
+        ];
+        let text = write_pls(&refs(&tracks), Path::new("/music"), true);
+        assert_eq!(
+            text,
+            "[playlist]\n\
+             File1=Roygbiv.mp3\nTitle1=Boards of Canada - Roygbiv\nLength1=151\n\
+             File2=sub/Xtal.mp3\nTitle2=Xtal\nLength2=-1\n\
+             NumberOfEntries=2\nVersion=2\n"
+        );
+
+        let entries = parse_pls(&text, Path::new("/music"));
+        assert_eq!(
+            paths(&entries),
+            tracks
+                .iter()
+                .map(|t| Some(t.file_path.clone()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(entries[0].artist, "Boards of Canada");
+        assert_eq!(entries[0].duration, Some(151.0));
+        assert_eq!(entries[1].title, "Xtal");
+        assert_eq!(entries[1].duration, None);
+    }
+
+    #[test]
+    fn xspf_round_trip() {
+        let mut tracks = vec![
+            track(
+                "/music/AC&DC/Back <in> Black.mp3",
+                "AC/DC",
+                "Back \"in\" Black",
+                255.5,
+            ),
+            track("/lists/Café 100%.flac", "Sigur Rós", "", 0.0),
+            track("/other/ünï.ogg", "", "Ünï", 12.0),
+        ];
+        tracks[0].album = "Back in Black".to_string();
+        let text = write_xspf("Rock & Roll", &refs(&tracks), Path::new("/lists"), true);
+        assert!(text.contains("<title>Rock &amp; Roll</title>"));
+        assert!(text.contains("<location>Caf%C3%A9%20100%25.flac</location>"));
+        assert!(text.contains("<location>../music/AC%26DC/Back%20%3Cin%3E%20Black.mp3</location>"));
+
+        let playlist = parse_xspf(&text, Path::new("/lists")).unwrap();
+        assert_eq!(playlist.title.as_deref(), Some("Rock & Roll"));
+        assert_eq!(
+            paths(&playlist.entries),
+            tracks
+                .iter()
+                .map(|t| Some(t.file_path.clone()))
+                .collect::<Vec<_>>()
+        );
+        let first = &playlist.entries[0];
+        assert_eq!(first.title, "Back \"in\" Black");
+        assert_eq!(first.artist, "AC/DC");
+        assert_eq!(first.album, "Back in Black");
+        assert_eq!(first.duration, Some(255.5));
+        // a track without a title is written with its file name
+        assert_eq!(playlist.entries[1].title, "Café 100%.flac");
+        assert_eq!(playlist.entries[1].artist, "Sigur Rós");
+        assert_eq!(playlist.entries[1].duration, None);
+    }
+
+    #[test]
+    fn xspf_absolute_locations() {
+        let tracks = vec![track("/music/a b.mp3", "", "A", 1.0)];
+        let text = write_xspf("A", &refs(&tracks), Path::new("/lists"), false);
+        assert!(text.contains("<location>file:///music/a%20b.mp3</location>"));
+        let playlist = parse_xspf(&text, Path::new("/lists")).unwrap();
+        assert_eq!(
+            paths(&playlist.entries),
+            vec![Some(PathBuf::from("/music/a b.mp3"))]
+        );
+    }
+
+    #[test]
+    fn parse_xspf_locations() {
+        let text = r#"<?xml version="1.0"?>
+<playlist version="1" xmlns="http://xspf.org/ns/0/">
+  <trackList>
+    <track>
+      <location>http://example.com/a.mp3</location>
+      <location>file:///music/a.mp3</location>
+      <title>A</title>
+    </track>
+    <track>
+      <location>http://example.com/stream</location>
+    </track>
+    <track>
+      <title>No location</title>
+    </track>
+  </trackList>
+</playlist>"#;
+        let playlist = parse_xspf(text, Path::new("/lists")).unwrap();
+        assert_eq!(playlist.title, None);
+        // the first location that is a file wins
+        assert_eq!(
+            paths(&playlist.entries),
+            vec![Some(PathBuf::from("/music/a.mp3")), None, None]
+        );
+        assert_eq!(playlist.entries[0].location, "http://example.com/a.mp3");
+        assert_eq!(playlist.entries[2].location, "No location");
+    }
+
+    #[test]
+    fn parse_xspf_needs_a_playlist() {
+        assert!(parse_xspf("<rss/>", Path::new("/")).is_err());
+        assert!(parse_xspf("not xml", Path::new("/")).is_err());
+    }
+
+    #[test]
+    fn import_matches_by_path_then_tags_and_adds_new_files() {
+        let dir = std::env::temp_dir().join(format!("playlist_files_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let new_file = dir.join("new.mp3");
+        fs::write(&new_file, b"").unwrap();
+        let list = dir.join("list.m3u");
+        let text = format!(
+            "/music/a.mp3\n\
+             #EXTINF:200,Artist - Moved\n\
+             /gone/moved.mp3\n\
+             {}\n\
+             #EXTINF:200,Nobody - Nothing\n\
+             /gone/nothing.mp3\n",
+            new_file.display()
+        );
+        fs::write(&list, text).unwrap();
+
+        let mut library = vec![
+            track("/music/a.mp3", "Artist", "A", 100.0),
+            track("/music/moved.mp3", "artist", " moved ", 201.0),
+        ];
+        let mut playlists = Vec::new();
+        let mut ids = IdCounters::default();
+        assign_track_ids(&mut library, &mut ids);
+        let report = import_playlist(&list, &mut library, &mut playlists, &mut ids).unwrap();
+
+        assert_eq!(report.name, "list");
+        assert_eq!(report.tracks, 3);
+        assert_eq!(report.added, 1);
+        assert_eq!(report.matched_by_tags, 1);
+        assert_eq!(report.unresolved, vec!["/gone/nothing.mp3".to_string()]);
+        // the new file is read later, by LibraryUpgrade
+        assert_eq!(library.len(), 3);
+        assert_eq!(library[2].file_path, new_file);
+        assert_eq!(library[2].version, 0);
+        assert_eq!(playlists[0].tracks, vec![1, 2, 3]);
+        fs::remove_dir_all(dir).ok();
+    }
+}
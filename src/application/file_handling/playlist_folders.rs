@@ -0,0 +1,197 @@
+use super::file_handling::MusicCollection;
+use std::collections::HashSet;
+
+//---------------------------------------------------------------------------------------------------
+// Playlist folders
+// Folders group playlists in the left panel and can be nested. Each folder knows the folder it is in
+// and each playlist knows its folder (MusicCollection::folder), None being the top level. Anything
+// whose folder doesn't exist anymore shows at the top level. Inside a folder the subfolders come
+// first and then the playlists, and playing a folder plays everything in it in that same order.
+// --------------------------------------------------------------------------------------------------
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct PlaylistFolder {
+    pub id: u32,
+    pub name: String,
+    pub parent: Option<u32>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct PlaylistFolders {
+    pub folders: Vec<PlaylistFolder>,
+    next_id: u32,
+}
+
+impl PlaylistFolders {
+    pub fn add(&mut self, name: &str, parent: Option<u32>) -> u32 {
+        self.next_id += 1;
+        self.folders.push(PlaylistFolder {
+            id: self.next_id,
+            name: name.to_string(),
+            parent: self.location(parent),
+        });
+        self.next_id
+    }
+
+    pub fn get(&self, id: u32) -> Option<&PlaylistFolder> {
+        self.folders.iter().find(|folder| folder.id == id)
+    }
+
+    pub fn rename(&mut self, id: u32, name: &str) -> Result<(), String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("a folder needs a name".to_string());
+        }
+        match self.folders.iter_mut().find(|folder| folder.id == id) {
+            Some(folder) => {
+                folder.name = name.to_string();
+                Ok(())
+            }
+            None => Err("that folder doesn't exist anymore".to_string()),
+        }
+    }
+
+    pub fn location(&self, folder: Option<u32>) -> Option<u32> {
+        // where something filed under `folder` shows, the top level if the folder is gone
+        folder.filter(|&id| self.get(id).is_some())
+    }
+
+    pub fn subfolders(&self, parent: Option<u32>) -> Vec<&PlaylistFolder> {
+        self.folders
+            .iter()
+            .filter(|folder| self.location(folder.parent) == parent)
+            .collect()
+    }
+
+    pub fn playlists_in<'a>(
+        &self,
+        parent: Option<u32>,
+        playlists: &'a [MusicCollection],
+    ) -> Vec<&'a MusicCollection> {
+        playlists
+            .iter()
+            .filter(|playlist| self.location(playlist.folder) == parent)
+            .collect()
+    }
+
+    pub fn is_inside(&self, folder: u32, ancestor: u32) -> bool {
+        // whether `folder` is `ancestor` or somewhere below it
+        let mut current = Some(folder);
+        let mut depth = 0;
+        while let Some(id) = current {
+            if id == ancestor {
+                return true;
+            }
+            // a loop in saved data can't keep this going forever
+            depth += 1;
+            if depth > self.folders.len() {
+                return false;
+            }
+            current = self.get(id).and_then(|folder| folder.parent);
+        }
+        false
+    }
+
+    pub fn move_folder(&mut self, id: u32, parent: Option<u32>) -> Result<(), String> {
+        let parent = self.location(parent);
+        if let Some(parent) = parent {
+            if self.is_inside(parent, id) {
+                return Err("a folder can't be moved into itself".to_string());
+            }
+        }
+        match self.folders.iter_mut().find(|folder| folder.id == id) {
+            Some(folder) => {
+                folder.parent = parent;
+                Ok(())
+            }
+            None => Err("that folder doesn't exist anymore".to_string()),
+        }
+    }
+
+    pub fn move_playlist(&self, playlist: &mut MusicCollection, folder: Option<u32>) {
+        playlist.folder = self.location(folder);
+    }
+
+    pub fn remove(&mut self, id: u32, playlists: &mut [MusicCollection]) {
+        // deleting a folder keeps what is in it, its subfolders and playlists move up a level
+        let parent = match self.get(id) {
+            Some(folder) => self.location(folder.parent),
+            None => return,
+        };
+        for folder in &mut self.folders {
+            if folder.parent == Some(id) {
+                folder.parent = parent;
+            }
+        }
+        for playlist in playlists.iter_mut() {
+            if playlist.folder == Some(id) {
+                playlist.folder = parent;
+            }
+        }
+        self.folders.retain(|folder| folder.id != id);
+    }
+
+    pub fn folder_tracks(&self, id: u32, playlists: &[MusicCollection]) -> Vec<u32> {
+        // the track ids of every playlist in the folder and its subfolders, one playlist after another
+        let mut tracks = Vec::new();
+        self.collect_tracks(id, playlists, &mut HashSet::new(), &mut tracks);
+        tracks
+    }
+
+    fn collect_tracks(
+        &self,
+        id: u32,
+        playlists: &[MusicCollection],
+        visited: &mut HashSet<u32>,
+        tracks: &mut Vec<u32>,
+    ) {
+        // a loop in saved data (a folder somewhere inside itself) stops at the first folder seen twice
+        if !visited.insert(id) {
+            return;
+        }
+        for folder in self.subfolders(Some(id)) {
+            self.collect_tracks(folder.id, playlists, visited, tracks);
+        }
+        for playlist in self.playlists_in(Some(id), playlists) {
+            tracks.extend(&playlist.tracks);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playlist(id: u32, folder: Option<u32>, tracks: Vec<u32>) -> MusicCollection {
+        let mut playlist = MusicCollection::new(format!("playlist {}", id), id);
+        playlist.folder = folder;
+        playlist.tracks = tracks;
+        playlist
+    }
+
+    #[test]
+    fn folder_tracks_plays_subfolders_first() {
+        let mut folders = PlaylistFolders::default();
+        let outer = folders.add("Outer", None);
+        let inner = folders.add("Inner", Some(outer));
+        let playlists = vec![
+            playlist(1, Some(outer), vec![1, 2]),
+            playlist(2, Some(inner), vec![3]),
+        ];
+        assert_eq!(folders.folder_tracks(outer, &playlists), vec![3, 1, 2]);
+        assert_eq!(folders.folder_tracks(inner, &playlists), vec![3]);
+    }
+
+    #[test]
+    fn folder_tracks_stops_at_a_loop() {
+        // saved data where two folders are inside each other
+        let mut folders = PlaylistFolders::default();
+        let a = folders.add("A", None);
+        let b = folders.add("B", Some(a));
+        folders.folders[0].parent = Some(b);
+        let playlists = vec![playlist(1, Some(a), vec![1]), playlist(2, Some(b), vec![2])];
+        assert_eq!(folders.folder_tracks(a, &playlists), vec![2, 1]);
+        assert_eq!(folders.folder_tracks(b, &playlists), vec![1, 2]);
+    }
+}
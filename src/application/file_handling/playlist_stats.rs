@@ -0,0 +1,87 @@
+use super::albums::album_artist_of;
+use super::file_handling::MusicFile;
+use std::collections::HashSet;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+//---------------------------------------------------------------------------------------------------
+// PlaylistStats struct
+// The numbers in the header above a playlist. Artists and albums are counted case-insensitively, the
+// same way the album view groups them, and tracks without an artist or album aren't counted as one.
+// --------------------------------------------------------------------------------------------------
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PlaylistStats {
+    pub tracks: usize,
+    pub duration: f64,
+    pub artists: usize,
+    pub albums: usize,
+}
+
+impl PlaylistStats {
+    pub fn of<'a>(tracks: impl IntoIterator<Item = &'a MusicFile>) -> PlaylistStats {
+        let mut stats = PlaylistStats::default();
+        let mut artists = HashSet::new();
+        let mut albums = HashSet::new();
+        for music_file in tracks {
+            stats.tracks += 1;
+            stats.duration += music_file.duration;
+            if !music_file.artist.trim().is_empty() {
+                artists.insert(music_file.artist.trim().to_lowercase());
+            }
+            if !music_file.album.trim().is_empty() {
+                albums.insert((
+                    album_artist_of(music_file).trim().to_lowercase(),
+                    music_file.album.trim().to_lowercase(),
+                ));
+            }
+        }
+        stats.artists = artists.len();
+        stats.albums = albums.len();
+        stats
+    }
+}
+
+pub fn shuffled(tracks: &[usize]) -> Vec<usize> {
+    // -----------------------------------------------------------------------------------------------
+    // the tracks in a random order (Fisher-Yates). the randomness comes from a xorshift generator
+    // seeded with the clock, which is plenty for shuffling a playlist
+    // -----------------------------------------------------------------------------------------------
+    let mut state = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default()
+        | 1;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    let mut tracks = tracks.to_vec();
+    for i in (1..tracks.len()).rev() {
+        let j = (next() % (i as u64 + 1)) as usize;
+        tracks.swap(i, j);
+    }
+    tracks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(mut tracks: Vec<usize>) -> Vec<usize> {
+        tracks.sort_unstable();
+        tracks
+    }
+
+    #[test]
+    fn shuffled_is_a_permutation() {
+        assert!(shuffled(&[]).is_empty());
+        assert_eq!(shuffled(&[7]), vec![7]);
+
+        // a playlist can have the same track more than once
+        let tracks = vec![4, 1, 1, 9, 0, 3, 3, 3, 12, 5];
+        for _ in 0..20 {
+            assert_eq!(sorted(shuffled(&tracks)), sorted(tracks.clone()));
+        }
+    }
+}
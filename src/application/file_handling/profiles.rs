@@ -0,0 +1,98 @@
+//---------------------------------------------------------------------------------------------------
+// Profiles
+// Separate collections (work background music, a DJ crate, ...) live in profiles. Each profile saves
+// the whole app state (library, playlists, queue, settings, history) under its own storage key, and
+// the list of profiles with the one that is open is saved under PROFILES_KEY. The first profile uses
+// eframe::APP_KEY, where everything was saved before profiles existed, so that state opens as the
+// Default profile. Keys come from ids rather than names, so renaming a profile doesn't move anything.
+// --------------------------------------------------------------------------------------------------
+pub const PROFILES_KEY: &str = "profiles";
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct Profile {
+    pub id: u32,
+    pub name: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ProfileList {
+    pub profiles: Vec<Profile>,
+    pub current: u32,
+    next_id: u32,
+}
+
+impl Default for ProfileList {
+    fn default() -> Self {
+        ProfileList {
+            profiles: vec![Profile {
+                id: 0,
+                name: "Default".to_string(),
+            }],
+            current: 0,
+            next_id: 0,
+        }
+    }
+}
+
+impl ProfileList {
+    pub fn storage_key(id: u32) -> String {
+        if id == 0 {
+            eframe::APP_KEY.to_string()
+        } else {
+            format!("profile {}", id)
+        }
+    }
+
+    pub fn current_name(&self) -> &str {
+        self.profiles
+            .iter()
+            .find(|profile| profile.id == self.current)
+            .map(|profile| profile.name.as_str())
+            .unwrap_or_default()
+    }
+
+    pub fn add(&mut self, name: &str) -> Result<u32, String> {
+        let name = self.check_name(name, None)?;
+        self.next_id += 1;
+        self.profiles.push(Profile {
+            id: self.next_id,
+            name,
+        });
+        Ok(self.next_id)
+    }
+
+    pub fn rename(&mut self, id: u32, name: &str) -> Result<(), String> {
+        let name = self.check_name(name, Some(id))?;
+        match self.profiles.iter_mut().find(|profile| profile.id == id) {
+            Some(profile) => {
+                profile.name = name;
+                Ok(())
+            }
+            None => Err("that profile doesn't exist anymore".to_string()),
+        }
+    }
+
+    pub fn remove(&mut self, id: u32) -> Result<(), String> {
+        if id == self.current {
+            return Err("switch to another profile before deleting this one".to_string());
+        }
+        self.profiles.retain(|profile| profile.id != id);
+        Ok(())
+    }
+
+    fn check_name(&self, name: &str, renaming: Option<u32>) -> Result<String, String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("enter a name for the profile".to_string());
+        }
+        let taken = self
+            .profiles
+            .iter()
+            .any(|profile| Some(profile.id) != renaming && profile.name.eq_ignore_ascii_case(name));
+        if taken {
+            return Err(format!("there is already a profile called {}", name));
+        }
+        Ok(name.to_string())
+    }
+}
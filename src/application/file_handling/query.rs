@@ -0,0 +1,840 @@
+use super::file_handling::{format_duration, MusicFile};
+use super::history::now_secs;
+use std::fmt;
+
+//---------------------------------------------------------------------------------------------------
+// Query language for filtering the library.
+//
+//   artist:"Boards of Canada" year>=1998 duration<5:00 -genre:live
+//   (genre:ambient OR genre:idm) year:1995..2005
+//
+// - terms next to each other must all match (AND is optional), OR / | matches either side
+// - a leading - (or NOT / !) negates a term, parentheses group terms
+// - field:value matches when the field contains the value, field=value when it is equal,
+//   != < <= > >= compare, and field:a..b matches an inclusive range
+// - a term without a field matches the title, artist, album or file name
+// - text comparisons are case-insensitive, durations can be written as m:ss or h:mm:ss
+//
+// The same engine is used by the search box and by smart playlists, and can be used from code:
+//   let query = Query::parse("artist:aphex year<2000")?;
+//   let matching: Vec<usize> = query.filter(&library);
+// --------------------------------------------------------------------------------------------------
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Field {
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    Genre,
+    Composer,
+    Comment,
+    Name,
+    Path,
+    Codec,
+    Year,
+    Track,
+    Disc,
+    Duration,
+    Bitrate,
+    SampleRate,
+    Channels,
+    Size,
+    Rating,
+    Favourite,
+    Plays,
+    LastPlayed,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FieldKind {
+    Text,
+    Number,
+    Duration,
+}
+
+impl Field {
+    pub const ALL: [Field; 22] = [
+        Field::Title,
+        Field::Artist,
+        Field::Album,
+        Field::AlbumArtist,
+        Field::Genre,
+        Field::Composer,
+        Field::Comment,
+        Field::Name,
+        Field::Path,
+        Field::Codec,
+        Field::Year,
+        Field::Track,
+        Field::Disc,
+        Field::Duration,
+        Field::Bitrate,
+        Field::SampleRate,
+        Field::Channels,
+        Field::Size,
+        Field::Rating,
+        Field::Favourite,
+        Field::Plays,
+        Field::LastPlayed,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Field::Title => "title",
+            Field::Artist => "artist",
+            Field::Album => "album",
+            Field::AlbumArtist => "albumartist",
+            Field::Genre => "genre",
+            Field::Composer => "composer",
+            Field::Comment => "comment",
+            Field::Name => "name",
+            Field::Path => "path",
+            Field::Codec => "codec",
+            Field::Year => "year",
+            Field::Track => "track",
+            Field::Disc => "disc",
+            Field::Duration => "duration",
+            Field::Bitrate => "bitrate",
+            Field::SampleRate => "samplerate",
+            Field::Channels => "channels",
+            Field::Size => "size",
+            Field::Rating => "rating",
+            Field::Favourite => "favourite",
+            Field::Plays => "plays",
+            Field::LastPlayed => "lastplayed",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Field> {
+        let name = name.to_lowercase();
+        let alias = match name.as_str() {
+            "album_artist" => "albumartist",
+            "file" | "filename" => "name",
+            "date" => "year",
+            "tracknumber" => "track",
+            "discnumber" => "disc",
+            "length" | "time" => "duration",
+            "sample_rate" => "samplerate",
+            "stars" => "rating",
+            "favorite" | "fav" | "loved" => "favourite",
+            "playcount" | "play_count" => "plays",
+            "last_played" | "played" => "lastplayed",
+            other => other,
+        };
+        Field::ALL
+            .iter()
+            .copied()
+            .find(|field| field.name() == alias)
+    }
+
+    pub fn kind(&self) -> FieldKind {
+        match self {
+            Field::Duration => FieldKind::Duration,
+            Field::Year
+            | Field::Track
+            | Field::Disc
+            | Field::Bitrate
+            | Field::SampleRate
+            | Field::Channels
+            | Field::Size
+            | Field::Rating
+            | Field::Favourite
+            | Field::Plays
+            | Field::LastPlayed => FieldKind::Number,
+            _ => FieldKind::Text,
+        }
+    }
+
+    pub fn text(&self, music_file: &MusicFile) -> String {
+        match self {
+            Field::Title => music_file.title.clone(),
+            Field::Artist => music_file.artist.clone(),
+            Field::Album => music_file.album.clone(),
+            Field::AlbumArtist => music_file.album_artist.clone(),
+            Field::Genre => music_file.genre.clone(),
+            Field::Composer => music_file.composer.clone(),
+            Field::Comment => music_file.comment.clone(),
+            Field::Name => music_file.name.clone(),
+            Field::Path => music_file.file_path.to_string_lossy().to_string(),
+            Field::Codec => music_file.codec.clone(),
+            _ => self
+                .number(music_file)
+                .map(|n| n.to_string())
+                .unwrap_or_default(),
+        }
+    }
+
+    // size is in megabytes, duration in seconds, favourite is 1 or 0, lastplayed is days ago
+    pub fn number(&self, music_file: &MusicFile) -> Option<f64> {
+        match self {
+            Field::Year => music_file.year.map(|n| n as f64),
+            Field::Track => music_file.track_number.map(|n| n as f64),
+            Field::Disc => music_file.disc_number.map(|n| n as f64),
+            Field::Duration => Some(music_file.duration),
+            Field::Bitrate => music_file.bitrate.map(|n| n as f64),
+            Field::SampleRate => music_file.sample_rate.map(|n| n as f64),
+            Field::Channels => music_file.channels.map(|n| n as f64),
+            Field::Size => Some(music_file.file_size as f64 / 1_000_000.0),
+            Field::Rating => music_file.rating.map(|n| n as f64),
+            Field::Favourite => Some(if music_file.favourite { 1.0 } else { 0.0 }),
+            Field::Plays => Some(music_file.play_count as f64),
+            Field::LastPlayed => music_file
+                .last_played
+                .map(|t| now_secs().saturating_sub(t) as f64 / (24.0 * 60.0 * 60.0)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Op {
+    Contains,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Op {
+    pub const ALL: [Op; 7] = [
+        Op::Contains,
+        Op::Equal,
+        Op::NotEqual,
+        Op::Less,
+        Op::LessOrEqual,
+        Op::Greater,
+        Op::GreaterOrEqual,
+    ];
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Op::Contains => ":",
+            Op::Equal => "=",
+            Op::NotEqual => "!=",
+            Op::Less => "<",
+            Op::LessOrEqual => "<=",
+            Op::Greater => ">",
+            Op::GreaterOrEqual => ">=",
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, PartialEq, Debug)]
+pub enum Value {
+    Text(String), // stored lowercased
+    Number(f64),
+    Range(f64, f64),
+}
+
+//---------------------------------------------------------------------------------------------------
+// Condition struct
+// A single field comparison, e.g. year>=1998. Values are parsed according to the kind of field when
+// the condition is created, so evaluating it doesn't have to parse anything.
+// --------------------------------------------------------------------------------------------------
+#[derive(serde::Serialize, serde::Deserialize, Clone, PartialEq, Debug)]
+pub struct Condition {
+    pub field: Field,
+    pub op: Op,
+    pub value: Value,
+}
+
+impl Condition {
+    pub fn new(field: Field, op: Op, raw: &str) -> Result<Condition, String> {
+        if field.kind() == FieldKind::Text {
+            return Ok(Condition {
+                field,
+                op,
+                value: Value::Text(raw.to_lowercase()),
+            });
+        }
+
+        let parse = |text: &str| -> Result<f64, String> {
+            let parsed = match (field, field.kind()) {
+                (_, FieldKind::Duration) => parse_duration(text),
+                (Field::Favourite, _) => match text.trim().to_lowercase().as_str() {
+                    "yes" | "true" => Some(1.0),
+                    "no" | "false" => Some(0.0),
+                    other => other.parse::<f64>().ok(),
+                },
+                _ => text.trim().parse::<f64>().ok(),
+            };
+            parsed.ok_or_else(|| match field.kind() {
+                FieldKind::Duration => format!("'{}' is not a duration (use m:ss)", text),
+                _ => format!("'{}' is not a number", text),
+            })
+        };
+
+        let value = match raw.split_once("..") {
+            Some((low, high)) => {
+                if !matches!(op, Op::Contains | Op::Equal) {
+                    return Err(format!("a range can't be used with '{}'", op.symbol()));
+                }
+                Value::Range(parse(low)?, parse(high)?)
+            }
+            None => Value::Number(parse(raw)?),
+        };
+        Ok(Condition { field, op, value })
+    }
+
+    pub fn matches(&self, music_file: &MusicFile) -> bool {
+        match &self.value {
+            Value::Text(value) => {
+                let text = self.field.text(music_file).to_lowercase();
+                match self.op {
+                    Op::Contains => text.contains(value.as_str()),
+                    Op::Equal => &text == value,
+                    Op::NotEqual => &text != value,
+                    Op::Less => &text < value,
+                    Op::LessOrEqual => &text <= value,
+                    Op::Greater => &text > value,
+                    Op::GreaterOrEqual => &text >= value,
+                }
+            }
+            Value::Number(value) => match self.field.number(music_file) {
+                Some(number) => match self.op {
+                    Op::Contains | Op::Equal => (number - value).abs() < 0.5,
+                    Op::NotEqual => (number - value).abs() >= 0.5,
+                    Op::Less => number < *value,
+                    Op::LessOrEqual => number <= *value,
+                    Op::Greater => number > *value,
+                    Op::GreaterOrEqual => number >= *value,
+                },
+                None => self.op == Op::NotEqual,
+            },
+            Value::Range(low, high) => match self.field.number(music_file) {
+                Some(number) => *low <= number && number <= *high,
+                None => false,
+            },
+        }
+    }
+
+    pub fn value_text(&self) -> String {
+        let number = |n: f64| match self.field.kind() {
+            FieldKind::Duration => format_duration(n),
+            _ => n.to_string(),
+        };
+        match &self.value {
+            Value::Text(text) if text.contains(char::is_whitespace) || text.is_empty() => {
+                format!("\"{}\"", text)
+            }
+            Value::Text(text) => text.clone(),
+            Value::Number(n) => number(*n),
+            Value::Range(low, high) => format!("{}..{}", number(*low), number(*high)),
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}{}",
+            self.field.name(),
+            self.op.symbol(),
+            self.value_text()
+        )
+    }
+}
+
+pub fn parse_duration(text: &str) -> Option<f64> {
+    // "300", "5:00" and "1:02:03" are all accepted
+    let mut seconds = 0.0;
+    for part in text.trim().split(':') {
+        seconds = seconds * 60.0 + part.parse::<f64>().ok()?;
+    }
+    Some(seconds)
+}
+
+//---------------------------------------------------------------------------------------------------
+// QueryError struct
+// position is the character offset in the query where the problem was found
+// --------------------------------------------------------------------------------------------------
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueryError {
+    pub position: usize,
+    pub message: String,
+}
+
+impl QueryError {
+    fn new(position: usize, message: impl Into<String>) -> QueryError {
+        QueryError {
+            position,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (at position {})", self.message, self.position + 1)
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Condition(Condition),
+    Text(String), // stored lowercased
+}
+
+impl Expr {
+    pub fn matches(&self, music_file: &MusicFile) -> bool {
+        match self {
+            Expr::And(a, b) => a.matches(music_file) && b.matches(music_file),
+            Expr::Or(a, b) => a.matches(music_file) || b.matches(music_file),
+            Expr::Not(expr) => !expr.matches(music_file),
+            Expr::Condition(condition) => condition.matches(music_file),
+            Expr::Text(text) => [
+                &music_file.title,
+                &music_file.artist,
+                &music_file.album,
+                &music_file.name,
+            ]
+            .iter()
+            .any(|field| field.to_lowercase().contains(text.as_str())),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Query {
+    pub expr: Option<Expr>, // None for an empty query, which matches everything
+}
+
+impl Query {
+    pub fn parse(input: &str) -> Result<Query, QueryError> {
+        let tokens = lex(input)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            end: input.chars().count(),
+        };
+        if parser.tokens.is_empty() {
+            return Ok(Query { expr: None });
+        }
+        let expr = parser.parse_or()?;
+        if let Some((_, position)) = parser.tokens.get(parser.pos) {
+            return Err(QueryError::new(*position, "unexpected ')'"));
+        }
+        Ok(Query { expr: Some(expr) })
+    }
+
+    pub fn matches(&self, music_file: &MusicFile) -> bool {
+        self.expr
+            .as_ref()
+            .map_or(true, |expr| expr.matches(music_file))
+    }
+
+    pub fn filter(&self, library: &[MusicFile]) -> Vec<usize> {
+        library
+            .iter()
+            .enumerate()
+            .filter(|(_, music_file)| self.matches(music_file))
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+pub fn is_structured(input: &str) -> bool {
+    // -----------------------------------------------------------------------------------------------
+    // true when the search box text uses any query syntax. plain words are left to the fuzzy search,
+    // which is more forgiving for simple lookups
+    // -----------------------------------------------------------------------------------------------
+    input.contains([':', '<', '>', '=', '(', ')', '|', '"'])
+        || input.split_whitespace().any(|word| {
+            matches!(word, "OR" | "AND" | "NOT") || word.starts_with('-') || word.starts_with('!')
+        })
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    LParen,
+    RParen,
+    Not,
+    Or,
+    And,
+    Field(String),
+    Op(Op),
+    Word(String),
+    Quoted(String),
+}
+
+fn read_op(chars: &[char], i: usize) -> Option<(Op, usize)> {
+    let next = chars.get(i + 1).copied();
+    match (chars.get(i)?, next) {
+        (':', _) => Some((Op::Contains, 1)),
+        ('!', Some('=')) => Some((Op::NotEqual, 2)),
+        ('<', Some('=')) => Some((Op::LessOrEqual, 2)),
+        ('>', Some('=')) => Some((Op::GreaterOrEqual, 2)),
+        ('=', _) => Some((Op::Equal, 1)),
+        ('<', _) => Some((Op::Less, 1)),
+        ('>', _) => Some((Op::Greater, 1)),
+        _ => None,
+    }
+}
+
+fn read_quoted(chars: &[char], start: usize) -> Result<(String, usize), QueryError> {
+    let mut text = String::new();
+    let mut i = start + 1;
+    while i < chars.len() {
+        match chars[i] {
+            '"' => return Ok((text, i + 1)),
+            '\\' if i + 1 < chars.len() => {
+                text.push(chars[i + 1]);
+                i += 2;
+            }
+            c => {
+                text.push(c);
+                i += 1;
+            }
+        }
+    }
+    Err(QueryError::new(start, "unterminated quote"))
+}
+
+fn lex(input: &str) -> Result<Vec<(Token, usize)>, QueryError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    let mut expect_value = false;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+
+        if c.is_whitespace() {
+            if expect_value {
+                return Err(QueryError::new(i, "expected a value after the operator"));
+            }
+            i += 1;
+            continue;
+        }
+        if c == '"' {
+            let (text, next) = read_quoted(&chars, i)?;
+            tokens.push((Token::Quoted(text), start));
+            i = next;
+            expect_value = false;
+            continue;
+        }
+        if expect_value {
+            // an unquoted value runs until the next space or closing parenthesis
+            while i < chars.len() && !chars[i].is_whitespace() && chars[i] != ')' {
+                i += 1;
+            }
+            if i == start {
+                return Err(QueryError::new(i, "expected a value after the operator"));
+            }
+            tokens.push((Token::Word(chars[start..i].iter().collect()), start));
+            expect_value = false;
+            continue;
+        }
+
+        match c {
+            '(' => {
+                tokens.push((Token::LParen, start));
+                i += 1;
+            }
+            ')' => {
+                tokens.push((Token::RParen, start));
+                i += 1;
+            }
+            '|' => {
+                tokens.push((Token::Or, start));
+                i += 1;
+            }
+            '&' => {
+                tokens.push((Token::And, start));
+                i += 1;
+            }
+            '-' | '!' if chars.get(i + 1) != Some(&'=') => {
+                tokens.push((Token::Not, start));
+                i += 1;
+            }
+            _ => {
+                while i < chars.len()
+                    && !chars[i].is_whitespace()
+                    && !matches!(chars[i], '(' | ')' | '"' | ':' | '<' | '>' | '=')
+                    && !(chars[i] == '!' && chars.get(i + 1) == Some(&'='))
+                {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+
+                if let Some((op, length)) = read_op(&chars, i) {
+                    if word.is_empty() {
+                        return Err(QueryError::new(
+                            i,
+                            format!("expected a field name before '{}'", op.symbol()),
+                        ));
+                    }
+                    tokens.push((Token::Field(word), start));
+                    tokens.push((Token::Op(op), i));
+                    i += length;
+                    expect_value = true;
+                } else if word.is_empty() {
+                    return Err(QueryError::new(i, format!("unexpected '{}'", c)));
+                } else {
+                    let token = match word.as_str() {
+                        "OR" => Token::Or,
+                        "AND" => Token::And,
+                        "NOT" => Token::Not,
+                        _ => Token::Word(word),
+                    };
+                    tokens.push((token, start));
+                }
+            }
+        }
+    }
+
+    if expect_value {
+        return Err(QueryError::new(
+            chars.len(),
+            "expected a value after the operator",
+        ));
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .map(|(_, position)| *position)
+            .unwrap_or(self.end)
+    }
+
+    fn next(&mut self) -> Option<(Token, usize)> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, QueryError> {
+        let mut left = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            let right = self.parse_and()?;
+            left = Expr::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, QueryError> {
+        let mut left = self.parse_unary()?;
+        loop {
+            match self.peek() {
+                None | Some(Token::Or) | Some(Token::RParen) => break,
+                Some(Token::And) => self.pos += 1,
+                _ => {}
+            }
+            let right = self.parse_unary()?;
+            left = Expr::And(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, QueryError> {
+        if self.peek() == Some(&Token::Not) {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.parse_unary()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, QueryError> {
+        let position = self.position();
+        match self.next() {
+            Some((Token::LParen, _)) => {
+                let expr = self.parse_or()?;
+                match self.next() {
+                    Some((Token::RParen, _)) => Ok(expr),
+                    _ => Err(QueryError::new(position, "missing ')' for this '('")),
+                }
+            }
+            Some((Token::Field(name), _)) => {
+                let field = Field::from_name(&name).ok_or_else(|| {
+                    QueryError::new(position, format!("unknown field '{}'", name))
+                })?;
+                let op = match self.next() {
+                    Some((Token::Op(op), _)) => op,
+                    _ => return Err(QueryError::new(position, "expected an operator")),
+                };
+                let value_position = self.position();
+                let raw = match self.next() {
+                    Some((Token::Word(raw), _)) | Some((Token::Quoted(raw), _)) => raw,
+                    _ => {
+                        return Err(QueryError::new(
+                            value_position,
+                            "expected a value after the operator",
+                        ))
+                    }
+                };
+                Condition::new(field, op, &raw)
+                    .map(Expr::Condition)
+                    .map_err(|message| QueryError::new(value_position, message))
+            }
+            Some((Token::Word(text), _)) | Some((Token::Quoted(text), _)) => {
+                Ok(Expr::Text(text.to_lowercase()))
+            }
+            Some((Token::RParen, _)) => Err(QueryError::new(position, "unexpected ')'")),
+            Some((Token::Or, _)) | Some((Token::And, _)) => Err(QueryError::new(
+                position,
+                "expected a search term before the operator",
+            )),
+            Some((Token::Not, _)) | Some((Token::Op(_), _)) | None => {
+                Err(QueryError::new(position, "expected a search term"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(word: &str) -> Box<Expr> {
+        Box::new(Expr::Text(word.to_string()))
+    }
+
+    fn error_position(input: &str) -> usize {
+        Query::parse(input).unwrap_err().position
+    }
+
+    fn track(title: &str, artist: &str, year: i32, duration: f64) -> MusicFile {
+        MusicFile {
+            title: title.to_string(),
+            artist: artist.to_string(),
+            year: Some(year),
+            duration,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let query = Query::parse("a b OR c").unwrap();
+        let expected = Expr::Or(Box::new(Expr::And(text("a"), text("b"))), text("c"));
+        assert_eq!(query.expr, Some(expected));
+
+        let query = Query::parse("a | b AND c").unwrap();
+        let expected = Expr::Or(text("a"), Box::new(Expr::And(text("b"), text("c"))));
+        assert_eq!(query.expr, Some(expected));
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and() {
+        let query = Query::parse("-a b").unwrap();
+        let expected = Expr::And(Box::new(Expr::Not(text("a"))), text("b"));
+        assert_eq!(query.expr, Some(expected));
+
+        let query = Query::parse("NOT !a").unwrap();
+        let expected = Expr::Not(Box::new(Expr::Not(text("a"))));
+        assert_eq!(query.expr, Some(expected));
+    }
+
+    #[test]
+    fn parentheses_group() {
+        let query = Query::parse("a (b OR c)").unwrap();
+        let expected = Expr::And(text("a"), Box::new(Expr::Or(text("b"), text("c"))));
+        assert_eq!(query.expr, Some(expected));
+
+        let query = Query::parse("-(a b)").unwrap();
+        let expected = Expr::Not(Box::new(Expr::And(text("a"), text("b"))));
+        assert_eq!(query.expr, Some(expected));
+    }
+
+    #[test]
+    fn empty_query_matches_everything() {
+        let query = Query::parse("   ").unwrap();
+        assert_eq!(query.expr, None);
+        assert!(query.matches(&MusicFile::default()));
+    }
+
+    #[test]
+    fn quoted_values_keep_spaces() {
+        let query = Query::parse("artist:\"Boards of Canada\"").unwrap();
+        let expected = Condition {
+            field: Field::Artist,
+            op: Op::Contains,
+            value: Value::Text("boards of canada".to_string()),
+        };
+        assert_eq!(query.expr, Some(Expr::Condition(expected)));
+    }
+
+    #[test]
+    fn quotes_can_be_escaped() {
+        let query = Query::parse(r#""say \"hi\"""#).unwrap();
+        assert_eq!(query.expr, Some(*text("say \"hi\"")));
+    }
+
+    #[test]
+    fn quoted_operators_are_text() {
+        let query = Query::parse("\"a OR b\"").unwrap();
+        assert_eq!(query.expr, Some(*text("a or b")));
+    }
+
+    #[test]
+    fn error_positions() {
+        assert_eq!(error_position("title:\"abc"), 6); // unterminated quote
+        assert_eq!(error_position("bogus:x"), 0); // unknown field
+        assert_eq!(error_position("year>=abc"), 6); // not a number
+        assert_eq!(error_position("year>"), 5); // missing value at the end
+        assert_eq!(error_position("year: 1999"), 5); // space after the operator
+        assert_eq!(error_position("a)"), 1);
+        assert_eq!(error_position("a (b"), 2);
+        assert_eq!(error_position(":x"), 0);
+        assert_eq!(error_position("a OR"), 4);
+        assert_eq!(error_position("OR a"), 0);
+        assert_eq!(error_position("year<1..2"), 5); // ranges only with : and =
+    }
+
+    #[test]
+    fn error_positions_count_characters() {
+        // the position is a character offset, not a byte offset
+        assert_eq!(error_position("é year>=x"), 8);
+        assert_eq!(error_position("ünïcödé)"), 7);
+    }
+
+    #[test]
+    fn conditions_match() {
+        let library = vec![
+            track("Roygbiv", "Boards of Canada", 1998, 151.0),
+            track("Xtal", "Aphex Twin", 1992, 291.0),
+            track("Avril 14th", "Aphex Twin", 2001, 125.0),
+        ];
+        let filter = |input: &str| Query::parse(input).unwrap().filter(&library);
+
+        assert_eq!(filter("artist:aphex"), vec![1, 2]);
+        assert_eq!(filter("artist=\"aphex twin\" year<2000"), vec![1]);
+        assert_eq!(filter("year:1995..2005"), vec![0, 2]);
+        assert_eq!(filter("duration<2:30"), vec![2]);
+        assert_eq!(filter("-artist:aphex"), vec![0]);
+        assert_eq!(filter("xtal OR roygbiv"), vec![0, 1]);
+        assert_eq!(filter("TWIN"), vec![1, 2]);
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("300"), Some(300.0));
+        assert_eq!(parse_duration("5:00"), Some(300.0));
+        assert_eq!(parse_duration("1:02:03"), Some(3723.0));
+        assert_eq!(parse_duration("5:xx"), None);
+    }
+}
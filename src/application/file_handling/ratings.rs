@@ -0,0 +1,211 @@
+use id3::{Content, Frame, TagLike};
+use std::path::Path;
+
+//---------------------------------------------------------------------------------------------------
+// Ratings in file tags
+// Ratings are stored in the library as 1-5 stars. They can also be read from and written to the
+// files themselves, so other players see them too:
+//  - MP3 (ID3): a POPM (popularimeter) frame, rating 1-255. Players disagree on how that maps to
+//    stars, so we use the common Windows Media Player steps (1, 64, 128, 196, 255) when writing and
+//    round to the nearest star when reading. A TXXX:FMPS_Rating frame is used if there is no POPM
+//  - FLAC (Vorbis comments): FMPS_RATING, a number from 0.0 to 1.0
+// Only our own POPM frame is replaced, the ones other players keep under their own user are left
+// alone, and the tag is written back in the ID3 version it was read in.
+// --------------------------------------------------------------------------------------------------
+const POPM_USER: &str = "Windows Media Player 9 Series";
+const POPM_STEPS: [u8; 5] = [1, 64, 128, 196, 255];
+const FMPS_RATING: &str = "FMPS_RATING";
+
+pub fn read_rating(path: &Path) -> Option<u8> {
+    match extension(path).as_str() {
+        "mp3" => {
+            let tag = id3::Tag::read_from_path(path).ok()?;
+            let popm = tag.frames().find_map(|frame| match frame.content() {
+                Content::Popularimeter(popm) if popm.rating > 0 => Some(popm.rating),
+                _ => None,
+            });
+            match popm {
+                Some(rating) => Some(stars_from_popm(rating)),
+                None => tag
+                    .extended_texts()
+                    .find(|text| text.description.eq_ignore_ascii_case(FMPS_RATING))
+                    .and_then(|text| stars_from_fmps(&text.value)),
+            }
+        }
+        "flac" => {
+            let tag = metaflac::Tag::read_from_path(path).ok()?;
+            let mut values = tag.get_vorbis(FMPS_RATING)?;
+            values.next().and_then(stars_from_fmps)
+        }
+        _ => None,
+    }
+}
+
+pub fn write_rating(path: &Path, rating: Option<u8>) -> Result<(), String> {
+    // None removes the rating from the file
+    match extension(path).as_str() {
+        "mp3" => {
+            let mut tag = id3::no_tag_ok(id3::Tag::read_from_path(path))
+                .map_err(|e| e.to_string())?
+                .unwrap_or_default();
+            set_popm(&mut tag, rating);
+            tag.write_to_path(path, tag.version())
+                .map_err(|e| e.to_string())
+        }
+        "flac" => {
+            let mut tag = metaflac::Tag::read_from_path(path).map_err(|e| e.to_string())?;
+            match rating {
+                Some(stars) => tag.set_vorbis(
+                    FMPS_RATING,
+                    vec![format!("{}", stars.clamp(1, 5) as f32 / 5.0)],
+                ),
+                None => tag.remove_vorbis(FMPS_RATING),
+            }
+            tag.save().map_err(|e| e.to_string())
+        }
+        other => Err(format!("ratings can't be saved in .{} files", other)),
+    }
+}
+
+fn set_popm(tag: &mut id3::Tag, rating: Option<u8>) {
+    // replaces the POPM frame we write, keeping the play counter that was in it
+    let mut counter = 0;
+    let mut others = Vec::new();
+    for frame in tag.remove("POPM") {
+        match frame.content() {
+            Content::Popularimeter(popm) if popm.user == POPM_USER => counter = popm.counter,
+            _ => others.push(frame),
+        }
+    }
+    for frame in others {
+        tag.add_frame(frame);
+    }
+    tag.remove_extended_text(Some("FMPS_Rating"), None);
+    if let Some(stars) = rating {
+        tag.add_frame(Frame::with_content(
+            "POPM",
+            Content::Popularimeter(id3::frame::Popularimeter {
+                user: POPM_USER.to_string(),
+                rating: POPM_STEPS[(stars.clamp(1, 5) - 1) as usize],
+                counter,
+            }),
+        ));
+    }
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+fn stars_from_popm(rating: u8) -> u8 {
+    // nearest of the steps used when writing, so ratings written by us read back unchanged
+    let nearest = POPM_STEPS
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, &step)| (step as i16 - rating as i16).abs())
+        .map(|(i, _)| i)
+        .unwrap_or_default();
+    nearest as u8 + 1
+}
+
+fn stars_from_fmps(value: &str) -> Option<u8> {
+    let value: f32 = value.trim().parse().ok()?;
+    if value <= 0.0 {
+        return None;
+    }
+    Some(((value.min(1.0) * 5.0).round() as u8).max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use id3::frame::Popularimeter;
+
+    fn popm(user: &str, rating: u8) -> Frame {
+        Frame::with_content(
+            "POPM",
+            Content::Popularimeter(Popularimeter {
+                user: user.to_string(),
+                rating,
+                counter: 3,
+            }),
+        )
+    }
+
+    fn popms(tag: &id3::Tag) -> Vec<(String, u8)> {
+        let mut popms: Vec<(String, u8)> = tag
+            .frames()
+            .filter_map(|frame| match frame.content() {
+                Content::Popularimeter(popm) => Some((popm.user.clone(), popm.rating)),
+                _ => None,
+            })
+            .collect();
+        popms.sort();
+        popms
+    }
+
+    #[test]
+    fn popm_steps() {
+        assert_eq!(stars_from_popm(0), 1);
+        assert_eq!(stars_from_popm(1), 1);
+        assert_eq!(stars_from_popm(32), 1);
+        assert_eq!(stars_from_popm(33), 2);
+        assert_eq!(stars_from_popm(64), 2);
+        assert_eq!(stars_from_popm(128), 3);
+        assert_eq!(stars_from_popm(196), 4);
+        assert_eq!(stars_from_popm(255), 5);
+        for stars in 1..=5 {
+            assert_eq!(stars_from_popm(POPM_STEPS[stars as usize - 1]), stars);
+        }
+    }
+
+    #[test]
+    fn fmps_values() {
+        assert_eq!(stars_from_fmps("0.0"), None);
+        assert_eq!(stars_from_fmps("-1"), None);
+        assert_eq!(stars_from_fmps("0.05"), Some(1));
+        assert_eq!(stars_from_fmps("0.2"), Some(1));
+        assert_eq!(stars_from_fmps("0.5"), Some(3));
+        assert_eq!(stars_from_fmps(" 0.6 "), Some(3));
+        assert_eq!(stars_from_fmps("0.7"), Some(4));
+        assert_eq!(stars_from_fmps("1.0"), Some(5));
+        assert_eq!(stars_from_fmps("7"), Some(5));
+        assert_eq!(stars_from_fmps("high"), None);
+    }
+
+    #[test]
+    fn other_players_ratings_are_kept() {
+        let mut tag = id3::Tag::new();
+        tag.add_frame(popm("someone@example.com", 10));
+        tag.add_frame(popm(POPM_USER, 64));
+        set_popm(&mut tag, Some(5));
+        assert_eq!(
+            popms(&tag),
+            vec![
+                (POPM_USER.to_string(), 255),
+                ("someone@example.com".to_string(), 10)
+            ]
+        );
+
+        set_popm(&mut tag, None);
+        assert_eq!(popms(&tag), vec![("someone@example.com".to_string(), 10)]);
+    }
+
+    #[test]
+    fn write_keeps_the_tag_version() {
+        let path = std::env::temp_dir().join(format!("ratings_test_{}.mp3", std::process::id()));
+        std::fs::write(&path, b"").unwrap();
+        let mut tag = id3::Tag::with_version(id3::Version::Id3v23);
+        tag.set_title("Song");
+        tag.write_to_path(&path, id3::Version::Id3v23).unwrap();
+
+        write_rating(&path, Some(4)).unwrap();
+        let written = id3::Tag::read_from_path(&path).unwrap();
+        assert_eq!(written.version(), id3::Version::Id3v23);
+        assert_eq!(written.title(), Some("Song"));
+        assert_eq!(read_rating(&path), Some(4));
+        std::fs::remove_file(path).ok();
+    }
+}
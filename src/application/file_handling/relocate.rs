@@ -0,0 +1,405 @@
+use super::file_handling::MusicFile;
+use audiotags::Tag;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::thread;
+
+const AUDIO_EXTENSIONS: [&str; 6] = ["mp3", "flac", "wav", "ogg", "m4a", "aac"];
+// a candidate needs at least two of: same file name, same size, same tags
+const MIN_SCORE: u32 = 2;
+
+//---------------------------------------------------------------------------------------------------
+// Missing files and relocation
+// The library stores absolute paths, so moving or renaming music on disk leaves entries pointing at
+// nothing. find_missing() lists those entries, and a RelocationSearch looks for where they went:
+// every audio file under a chosen folder is a candidate for a missing track when its file name or
+// size is the same, and candidates earn a point each for the same name, the same size and the same
+// tags (title, artist and duration). The best candidate wins if it scores at least MIN_SCORE and no
+// other candidate scores as well, so a track is never moved to a file that only might be it.
+//
+// Only the tags of candidates are read, without decoding the audio, and the search runs on a
+// background thread that reports how far it got. The web build has no threads, so there the search
+// is done before start() returns.
+// --------------------------------------------------------------------------------------------------
+pub fn find_missing(library: &[MusicFile]) -> HashSet<PathBuf> {
+    library
+        .iter()
+        .filter(|music_file| !music_file.file_path.exists())
+        .map(|music_file| music_file.file_path.clone())
+        .collect()
+}
+
+// what is known about a missing track, copied out of the library for the search thread
+#[derive(Clone, Debug, Default)]
+struct MissingTrack {
+    path: PathBuf,
+    size: u64,
+    title: String,
+    artist: String,
+    duration: f64,
+}
+
+// a file that might be a missing track. tags is None until they are needed, and stays None when the
+// file has none that can be read
+#[derive(Clone, Debug, Default)]
+struct Candidate {
+    path: PathBuf,
+    size: u64,
+    tags: Option<CandidateTags>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct CandidateTags {
+    title: String,
+    artist: String,
+    duration: Option<f64>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RelocationReport {
+    pub relocations: HashMap<PathBuf, PathBuf>,
+    pub ambiguous: usize, // missing tracks that more than one file matched equally well
+    pub unreadable: Vec<String>, // folders that couldn't be searched
+}
+
+enum SearchMessage {
+    Progress(usize, usize),
+    Done(RelocationReport),
+}
+
+pub struct RelocationSearch {
+    messages: Receiver<SearchMessage>,
+    pub progress: (usize, usize), // missing tracks looked for so far, out of how many
+}
+
+impl RelocationSearch {
+    pub fn start(
+        library: &[MusicFile],
+        missing: &HashSet<PathBuf>,
+        root: &Path,
+        ctx: &egui::Context,
+    ) -> RelocationSearch {
+        let known: HashSet<PathBuf> = library.iter().map(|m| m.file_path.clone()).collect();
+        let mut tracks: Vec<MissingTrack> = Vec::new();
+        let mut seen: HashSet<&PathBuf> = HashSet::new();
+        for music_file in library.iter().filter(|m| missing.contains(&m.file_path)) {
+            if seen.insert(&music_file.file_path) {
+                tracks.push(MissingTrack {
+                    path: music_file.file_path.clone(),
+                    size: music_file.file_size,
+                    title: music_file.title.clone(),
+                    artist: music_file.artist.clone(),
+                    duration: music_file.duration,
+                });
+            }
+        }
+
+        let (sender, receiver) = channel();
+        let root = root.to_path_buf();
+        let ctx = ctx.clone();
+        let work = move || {
+            let report = search(&tracks, &known, &root, |done, total| {
+                let _ = sender.send(SearchMessage::Progress(done, total));
+                ctx.request_repaint();
+            });
+            let _ = sender.send(SearchMessage::Done(report));
+            ctx.request_repaint();
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        thread::spawn(work);
+        #[cfg(target_arch = "wasm32")]
+        work();
+
+        RelocationSearch {
+            messages: receiver,
+            progress: (0, missing.len()),
+        }
+    }
+
+    pub fn receive(&mut self) -> Option<RelocationReport> {
+        // the report once the search is done, None while it is still going
+        loop {
+            match self.messages.try_recv() {
+                Ok(SearchMessage::Progress(done, total)) => self.progress = (done, total),
+                Ok(SearchMessage::Done(report)) => return Some(report),
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => return Some(RelocationReport::default()),
+            }
+        }
+    }
+}
+
+pub fn relocate_tracks<'a>(
+    tracks: impl IntoIterator<Item = &'a mut MusicFile>,
+    relocations: &HashMap<PathBuf, PathBuf>,
+) {
+    // points every copy of a moved track (library, queue) at its new location
+    for music_file in tracks {
+        if let Some(new_path) = relocations.get(&music_file.file_path) {
+            music_file.file_path = new_path.clone();
+            // LibraryRoots::resolve() finds the root of the new location
+            music_file.root = None;
+            music_file.name = new_path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default();
+        }
+    }
+}
+
+fn search(
+    tracks: &[MissingTrack],
+    known: &HashSet<PathBuf>,
+    root: &Path,
+    mut progress: impl FnMut(usize, usize),
+) -> RelocationReport {
+    let mut report = RelocationReport::default();
+    let mut candidates: Vec<Candidate> = Vec::new();
+    let mut by_name: HashMap<String, Vec<usize>> = HashMap::new();
+    let mut by_size: HashMap<u64, Vec<usize>> = HashMap::new();
+    for path in audio_files(root, &mut report.unreadable) {
+        // files that are already in the library aren't where a missing track went
+        if known.contains(&path) {
+            continue;
+        }
+        let size = fs::metadata(&path).map(|m| m.len()).unwrap_or_default();
+        by_name
+            .entry(file_name(&path))
+            .or_default()
+            .push(candidates.len());
+        by_size.entry(size).or_default().push(candidates.len());
+        candidates.push(Candidate {
+            path,
+            size,
+            tags: None,
+        });
+    }
+
+    let mut taken: HashSet<usize> = HashSet::new();
+    for (done, track) in tracks.iter().enumerate() {
+        progress(done, tracks.len());
+        let mut possible: Vec<usize> = Vec::new();
+        let same_name = by_name.get(&file_name(&track.path));
+        let same_size = by_size.get(&track.size);
+        for &i in same_name.into_iter().chain(same_size).flatten() {
+            if !possible.contains(&i) && !taken.contains(&i) {
+                possible.push(i);
+            }
+        }
+        // the tags only matter for a track that has some to compare with
+        if !track.title.is_empty() {
+            for &i in &possible {
+                if candidates[i].tags.is_none() {
+                    candidates[i].tags = Some(read_tags(&candidates[i].path));
+                }
+            }
+        }
+
+        match best_match(track, possible.iter().map(|&i| (i, &candidates[i]))) {
+            Match::Found(i) => {
+                taken.insert(i);
+                report
+                    .relocations
+                    .insert(track.path.clone(), candidates[i].path.clone());
+            }
+            Match::Ambiguous => report.ambiguous += 1,
+            Match::None => {}
+        }
+    }
+    progress(tracks.len(), tracks.len());
+    report
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Match {
+    Found(usize),
+    Ambiguous,
+    None,
+}
+
+fn best_match<'a>(
+    track: &MissingTrack,
+    candidates: impl IntoIterator<Item = (usize, &'a Candidate)>,
+) -> Match {
+    let mut scored: Vec<(u32, usize)> = candidates
+        .into_iter()
+        .map(|(i, candidate)| (score(track, candidate), i))
+        .collect();
+    scored.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+    match scored.as_slice() {
+        [(best, _), (second, _), ..] if best == second && *best >= MIN_SCORE => Match::Ambiguous,
+        [(best, i), ..] if *best >= MIN_SCORE => Match::Found(*i),
+        _ => Match::None,
+    }
+}
+
+fn score(track: &MissingTrack, candidate: &Candidate) -> u32 {
+    let mut score = 0;
+    if file_name(&candidate.path) == file_name(&track.path) {
+        score += 1;
+    }
+    if candidate.size == track.size {
+        score += 1;
+    }
+    if let Some(tags) = &candidate.tags {
+        // a file whose tags don't say how long it is can still match on title and artist
+        let same_duration = match tags.duration {
+            Some(duration) => (duration - track.duration).abs() < 2.0,
+            None => true,
+        };
+        let same_tags = tags.title.eq_ignore_ascii_case(&track.title)
+            && tags.artist.eq_ignore_ascii_case(&track.artist)
+            && same_duration;
+        if same_tags && !track.title.is_empty() {
+            score += 1;
+        }
+    }
+    score
+}
+
+fn read_tags(path: &Path) -> CandidateTags {
+    match Tag::new().read_from_path(path) {
+        Ok(tag) => CandidateTags {
+            title: tag.title().map(|s| s.to_string()).unwrap_or_default(),
+            artist: tag
+                .artists()
+                .map(|artists| artists.join(", "))
+                .unwrap_or_default(),
+            duration: tag.duration(),
+        },
+        Err(_) => CandidateTags::default(),
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+fn audio_files(root: &Path, unreadable: &mut Vec<String>) -> Vec<PathBuf> {
+    // every audio file under root, symlinked folders aren't followed so links can't loop
+    let mut files = Vec::new();
+    let mut folders = vec![root.to_path_buf()];
+    while let Some(folder) = folders.pop() {
+        let entries = match fs::read_dir(&folder) {
+            Ok(entries) => entries,
+            Err(e) => {
+                unreadable.push(format!("{}: {}", folder.display(), e));
+                continue;
+            }
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => folders.push(path),
+                Ok(_) => {
+                    let extension = path
+                        .extension()
+                        .map(|e| e.to_string_lossy().to_lowercase())
+                        .unwrap_or_default();
+                    if AUDIO_EXTENSIONS.contains(&extension.as_str()) {
+                        files.push(path);
+                    }
+                }
+                Err(_) => {}
+            }
+        }
+    }
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn missing(path: &str, size: u64, title: &str) -> MissingTrack {
+        MissingTrack {
+            path: PathBuf::from(path),
+            size,
+            title: title.to_string(),
+            artist: "Artist".to_string(),
+            duration: 200.0,
+        }
+    }
+
+    fn candidate(path: &str, size: u64, title: Option<&str>) -> Candidate {
+        Candidate {
+            path: PathBuf::from(path),
+            size,
+            tags: title.map(|title| CandidateTags {
+                title: title.to_string(),
+                artist: "artist".to_string(),
+                duration: Some(201.0),
+            }),
+        }
+    }
+
+    #[test]
+    fn score_counts_name_size_and_tags() {
+        let track = missing("/old/Song.mp3", 1000, "Song");
+        assert_eq!(
+            score(&track, &candidate("/new/song.MP3", 1000, Some("SONG"))),
+            3
+        );
+        assert_eq!(score(&track, &candidate("/new/other.mp3", 1000, None)), 1);
+        assert_eq!(
+            score(&track, &candidate("/new/Song.mp3", 5, Some("Else"))),
+            1
+        );
+        assert_eq!(score(&track, &candidate("/new/x.mp3", 5, Some("Song"))), 1);
+    }
+
+    #[test]
+    fn tags_need_a_close_duration_and_a_title() {
+        let track = missing("/old/a.mp3", 1, "Song");
+        let mut far = candidate("/new/b.mp3", 2, Some("Song"));
+        far.tags.as_mut().unwrap().duration = Some(210.0);
+        assert_eq!(score(&track, &far), 0);
+        // tags without a duration still count
+        far.tags.as_mut().unwrap().duration = None;
+        assert_eq!(score(&track, &far), 1);
+
+        let untitled = missing("/old/a.mp3", 1, "");
+        assert_eq!(score(&untitled, &candidate("/new/b.mp3", 2, Some(""))), 0);
+    }
+
+    #[test]
+    fn best_match_needs_a_clear_winner() {
+        let track = missing("/old/Song.mp3", 1000, "Song");
+        let found = [
+            candidate("/new/Song.mp3", 1000, None),
+            candidate("/new/other.mp3", 1000, None),
+        ];
+        assert_eq!(
+            best_match(&track, found.iter().enumerate()),
+            Match::Found(0)
+        );
+
+        let tied = [
+            candidate("/a/Song.mp3", 1000, None),
+            candidate("/b/Song.mp3", 1000, None),
+        ];
+        assert_eq!(
+            best_match(&track, tied.iter().enumerate()),
+            Match::Ambiguous
+        );
+
+        // one point isn't enough, even without competition
+        let weak = [candidate("/new/Song.mp3", 5, None)];
+        assert_eq!(best_match(&track, weak.iter().enumerate()), Match::None);
+        assert_eq!(best_match(&track, Vec::new()), Match::None);
+    }
+
+    #[test]
+    fn tied_low_scores_are_not_ambiguous() {
+        let track = missing("/old/Song.mp3", 1000, "Song");
+        let weak = [
+            candidate("/a/Song.mp3", 5, None),
+            candidate("/b/Song.mp3", 6, None),
+        ];
+        assert_eq!(best_match(&track, weak.iter().enumerate()), Match::None);
+    }
+}
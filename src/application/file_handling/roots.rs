@@ -0,0 +1,224 @@
+use super::file_handling::MusicFile;
+use std::path::{Component, Path, PathBuf};
+
+//---------------------------------------------------------------------------------------------------
+// Library roots
+// A library root is a folder the music lives under, e.g. /mnt/music or D:\Music. Tracks under a root
+// store the root's id and their path relative to it (with / separators, so a saved library works on
+// any OS), and their absolute file_path is rebuilt from the two when the app starts. Moving the music
+// to a new drive or mount point then only needs the root remapped, and the same saved state can be
+// shared between machines that mount the music in different places. Tracks that aren't under any
+// root keep their absolute path as before.
+// --------------------------------------------------------------------------------------------------
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct LibraryRoot {
+    pub id: u32,
+    pub path: PathBuf,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct LibraryRoots {
+    pub roots: Vec<LibraryRoot>,
+    next_id: u32,
+}
+
+impl LibraryRoots {
+    pub fn add(&mut self, path: &Path) -> u32 {
+        // adding a folder that is already a root returns the existing one
+        if let Some(root) = self.roots.iter().find(|root| root.path == path) {
+            return root.id;
+        }
+        self.next_id += 1;
+        self.roots.push(LibraryRoot {
+            id: self.next_id,
+            path: path.to_path_buf(),
+        });
+        self.next_id
+    }
+
+    pub fn remove(&mut self, id: u32) {
+        self.roots.retain(|root| root.id != id);
+    }
+
+    pub fn get(&self, id: u32) -> Option<&LibraryRoot> {
+        self.roots.iter().find(|root| root.id == id)
+    }
+
+    pub fn remap(&mut self, id: u32, path: &Path) -> Option<PathBuf> {
+        // moves a root to a new location, returns where it was
+        let root = self.roots.iter_mut().find(|root| root.id == id)?;
+        Some(std::mem::replace(&mut root.path, path.to_path_buf()))
+    }
+
+    pub fn root_of(&self, path: &Path) -> Option<(u32, String)> {
+        // the deepest root the path is under, with the path relative to it
+        self.roots
+            .iter()
+            .filter_map(|root| {
+                let relative = path.strip_prefix(&root.path).ok()?;
+                Some((root, relative_string(relative)?))
+            })
+            .max_by_key(|(root, _)| root.path.components().count())
+            .map(|(root, relative)| (root.id, relative))
+    }
+
+    pub fn resolve(&self, music_file: &mut MusicFile) {
+        // -----------------------------------------------------------------------------------------------
+        // points a track at its root: the absolute path is rebuilt from the root when it has one that
+        // still exists, otherwise the track is matched to a root by its absolute path (new tracks,
+        // tracks whose root was removed, tracks that were moved by relocation)
+        // -----------------------------------------------------------------------------------------------
+        if let Some(root) = music_file.root.and_then(|id| self.get(id)) {
+            let mut path = root.path.clone();
+            path.extend(music_file.relative_path.split('/'));
+            music_file.file_path = path;
+            return;
+        }
+        match self.root_of(&music_file.file_path) {
+            Some((id, relative)) => {
+                music_file.root = Some(id);
+                music_file.relative_path = relative;
+            }
+            None => {
+                music_file.root = None;
+                music_file.relative_path = String::new();
+            }
+        }
+    }
+
+    pub fn resolve_tracks<'a>(&self, tracks: impl IntoIterator<Item = &'a mut MusicFile>) {
+        for music_file in tracks {
+            self.resolve(music_file);
+        }
+    }
+}
+
+fn relative_string(relative: &Path) -> Option<String> {
+    // only plain folder and file names, a root never reaches outside itself with ..
+    let parts: Option<Vec<String>> = relative
+        .components()
+        .map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .collect();
+    parts
+        .filter(|parts| !parts.is_empty())
+        .map(|parts| parts.join("/"))
+}
+
+pub fn remap_path(path: &Path, from: &Path, to: &Path) -> Option<PathBuf> {
+    // the path moved from one root location to another, None if it isn't under the old one
+    path.strip_prefix(from)
+        .ok()
+        .map(|relative| to.join(relative))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(path: &str) -> MusicFile {
+        MusicFile {
+            file_path: PathBuf::from(path),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn root_of_picks_the_deepest_root() {
+        let mut roots = LibraryRoots::default();
+        let music = roots.add(Path::new("/music"));
+        let jazz = roots.add(Path::new("/music/jazz"));
+        assert_eq!(roots.add(Path::new("/music")), music);
+
+        assert_eq!(
+            roots.root_of(Path::new("/music/jazz/Miles/So What.mp3")),
+            Some((jazz, "Miles/So What.mp3".to_string()))
+        );
+        assert_eq!(
+            roots.root_of(Path::new("/music/rock/a.mp3")),
+            Some((music, "rock/a.mp3".to_string()))
+        );
+        // the root folder itself isn't a track under it
+        assert_eq!(roots.root_of(Path::new("/music")), None);
+    }
+
+    #[test]
+    fn prefixes_only_match_whole_folder_names() {
+        let mut roots = LibraryRoots::default();
+        roots.add(Path::new("/music"));
+        assert_eq!(roots.root_of(Path::new("/musicals/a.mp3")), None);
+        assert_eq!(roots.root_of(Path::new("/other/music/a.mp3")), None);
+        assert_eq!(
+            remap_path(
+                Path::new("/musicals/a.mp3"),
+                Path::new("/music"),
+                Path::new("/new")
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn trailing_separators_are_ignored() {
+        let mut roots = LibraryRoots::default();
+        let id = roots.add(Path::new("/music/"));
+        assert_eq!(
+            roots.root_of(Path::new("/music/a/b.mp3")),
+            Some((id, "a/b.mp3".to_string()))
+        );
+        assert_eq!(
+            remap_path(
+                Path::new("/music/a/b.mp3"),
+                Path::new("/music/"),
+                Path::new("/mnt/music/")
+            ),
+            Some(PathBuf::from("/mnt/music/a/b.mp3"))
+        );
+    }
+
+    #[test]
+    fn remap_moves_the_tracks_under_a_root() {
+        let mut roots = LibraryRoots::default();
+        let id = roots.add(Path::new("/music"));
+        let mut music_file = track("/music/a/b.mp3");
+        roots.resolve(&mut music_file);
+        assert_eq!(music_file.root, Some(id));
+        assert_eq!(music_file.relative_path, "a/b.mp3");
+
+        assert_eq!(
+            roots.remap(id, Path::new("/mnt/music")),
+            Some(PathBuf::from("/music"))
+        );
+        assert_eq!(roots.remap(id + 1, Path::new("/elsewhere")), None);
+        roots.resolve(&mut music_file);
+        assert_eq!(
+            music_file.file_path,
+            Path::new("/mnt/music").join("a").join("b.mp3")
+        );
+    }
+
+    #[test]
+    fn tracks_of_a_removed_root_fall_back_to_their_path() {
+        let mut roots = LibraryRoots::default();
+        let outer = roots.add(Path::new("/music"));
+        let inner = roots.add(Path::new("/music/jazz"));
+        let mut music_file = track("/music/jazz/a.mp3");
+        roots.resolve(&mut music_file);
+        assert_eq!(music_file.root, Some(inner));
+
+        roots.remove(inner);
+        roots.resolve(&mut music_file);
+        assert_eq!(music_file.root, Some(outer));
+        assert_eq!(music_file.relative_path, "jazz/a.mp3");
+
+        roots.remove(outer);
+        roots.resolve(&mut music_file);
+        assert_eq!(music_file.root, None);
+        assert_eq!(music_file.relative_path, "");
+        assert_eq!(music_file.file_path, PathBuf::from("/music/jazz/a.mp3"));
+    }
+}
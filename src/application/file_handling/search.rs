@@ -0,0 +1,414 @@
+use super::file_handling::MusicFile;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::ops::Range;
+
+// how much a match in each field counts: title, artist, album, file name
+const FIELD_WEIGHTS: [f32; 4] = [1.0, 0.8, 0.6, 0.4];
+
+#[derive(Clone, Copy, Debug)]
+pub struct SearchHit {
+    pub index: usize,
+    pub score: f32,
+}
+
+//---------------------------------------------------------------------------------------------------
+// SearchIndex struct
+// Prebuilt index for searching the library while the user types. Every distinct word in the title,
+// artist, album and file name is stored once, in sorted order, with the list of tracks that contain
+// it. A query word is matched against the index rather than against every track:
+//  - exact and prefix matches are found with a binary search in the sorted word list
+//  - substring and typo-tolerant matches are found through a trigram index over the word list,
+//    then checked with a bounded edit distance
+// --------------------------------------------------------------------------------------------------
+pub struct SearchIndex {
+    words: Vec<String>,
+    postings: Vec<Vec<(u32, u8)>>, // per word: (track index, bitmask of fields containing the word)
+    trigrams: HashMap<[char; 3], Vec<u32>>, // trigram -> ids of the words containing it
+}
+
+impl SearchIndex {
+    pub fn build(library: &[MusicFile]) -> SearchIndex {
+        let mut word_map: HashMap<String, Vec<(u32, u8)>> = HashMap::new();
+
+        for (i, music_file) in library.iter().enumerate() {
+            let fields = [
+                &music_file.title,
+                &music_file.artist,
+                &music_file.album,
+                &music_file.name,
+            ];
+            let mut track_words: HashMap<String, u8> = HashMap::new();
+            for (f, text) in fields.iter().enumerate() {
+                for word in tokenize(text) {
+                    *track_words.entry(word).or_default() |= 1 << f;
+                }
+            }
+            for (word, fields) in track_words {
+                word_map.entry(word).or_default().push((i as u32, fields));
+            }
+        }
+
+        let mut entries: Vec<(String, Vec<(u32, u8)>)> = word_map.into_iter().collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut trigram_map: HashMap<[char; 3], Vec<u32>> = HashMap::new();
+        for (id, (word, _)) in entries.iter().enumerate() {
+            for trigram in trigrams(word) {
+                let ids = trigram_map.entry(trigram).or_default();
+                if ids.last() != Some(&(id as u32)) {
+                    ids.push(id as u32);
+                }
+            }
+        }
+
+        let (words, postings): (Vec<String>, Vec<Vec<(u32, u8)>>) = entries.into_iter().unzip();
+        SearchIndex {
+            words,
+            postings,
+            trigrams: trigram_map,
+        }
+    }
+
+    fn matching_words(&self, token: &str) -> HashMap<u32, f32> {
+        let mut found: HashMap<u32, f32> = HashMap::new();
+
+        let start = self.words.partition_point(|w| w.as_str() < token);
+        for (offset, word) in self.words[start..]
+            .iter()
+            .enumerate()
+            .take_while(|(_, w)| w.starts_with(token))
+        {
+            let score = if word == token { 1.0 } else { 0.9 };
+            found.insert((start + offset) as u32, score);
+        }
+
+        let length = token.chars().count();
+        if length < 3 {
+            return found;
+        }
+
+        let token_trigrams = trigrams(token);
+        let max_distance = if length > 5 { 2 } else { 1 };
+        let min_shared = token_trigrams.len().saturating_sub(3 * max_distance).max(1);
+
+        let mut shared: HashMap<u32, usize> = HashMap::new();
+        for trigram in &token_trigrams {
+            if let Some(ids) = self.trigrams.get(trigram) {
+                for &id in ids {
+                    *shared.entry(id).or_default() += 1;
+                }
+            }
+        }
+
+        for (id, count) in shared {
+            if count < min_shared || found.contains_key(&id) {
+                continue;
+            }
+            let word = &self.words[id as usize];
+            let score = if word.contains(token) {
+                0.6
+            } else {
+                match fuzzy_distance(token, word, max_distance) {
+                    Some(distance) => 0.5 - 0.1 * distance as f32,
+                    None => continue,
+                }
+            };
+            found.insert(id, score);
+        }
+
+        found
+    }
+
+    pub fn search(&self, query: &str) -> Vec<SearchHit> {
+        // -----------------------------------------------------------------------------------------------
+        // every word of the query has to match (prefix, substring or with a typo) some word of the
+        // track. a track's score is the sum of its best score for each query word, weighted by the
+        // field the match was found in
+        // -----------------------------------------------------------------------------------------------
+        let tokens = tokenize(query);
+        let mut scores: Option<HashMap<u32, f32>> = None;
+
+        for token in &tokens {
+            let mut token_scores: HashMap<u32, f32> = HashMap::new();
+            for (word_id, word_score) in self.matching_words(token) {
+                for &(track, fields) in &self.postings[word_id as usize] {
+                    let score = word_score * field_weight(fields);
+                    let best = token_scores.entry(track).or_insert(0.0);
+                    if score > *best {
+                        *best = score;
+                    }
+                }
+            }
+
+            scores = Some(match scores {
+                None => token_scores,
+                Some(previous) => previous
+                    .into_iter()
+                    .filter_map(|(track, score)| {
+                        token_scores.get(&track).map(|s| (track, score + s))
+                    })
+                    .collect(),
+            });
+        }
+
+        let mut hits: Vec<SearchHit> = scores
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(track, score)| SearchHit {
+                index: track as usize,
+                score,
+            })
+            .collect();
+        hits.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(Ordering::Equal)
+                .then(a.index.cmp(&b.index))
+        });
+        hits
+    }
+}
+
+fn field_weight(fields: u8) -> f32 {
+    FIELD_WEIGHTS
+        .iter()
+        .enumerate()
+        .filter(|(f, _)| fields & (1 << f) != 0)
+        .map(|(_, weight)| *weight)
+        .fold(0.0, f32::max)
+}
+
+pub fn tokenize(text: &str) -> Vec<String> {
+    word_ranges(text)
+        .into_iter()
+        .map(|range| text[range].to_lowercase())
+        .collect()
+}
+
+fn word_ranges(text: &str) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start: Option<usize> = None;
+    for (i, c) in text.char_indices() {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                ranges.push(s..i);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        ranges.push(s..text.len());
+    }
+    ranges
+}
+
+fn trigrams(word: &str) -> Vec<[char; 3]> {
+    // the leading '^' marks the start of the word, so prefixes share more trigrams than substrings
+    let chars: Vec<char> = std::iter::once('^').chain(word.chars()).collect();
+    chars.windows(3).map(|w| [w[0], w[1], w[2]]).collect()
+}
+
+fn fuzzy_distance(token: &str, word: &str, max_distance: usize) -> Option<usize> {
+    // -----------------------------------------------------------------------------------------------
+    // edit distance between the token and the word, also comparing against the start of the word so
+    // a half-typed word with a typo ("bords of can") still matches. None when it's over max_distance
+    // -----------------------------------------------------------------------------------------------
+    let token: Vec<char> = token.chars().collect();
+    let word: Vec<char> = word.chars().collect();
+    let prefix = &word[..word.len().min(token.len())];
+    let distance = levenshtein(&token, &word).min(levenshtein(&token, prefix));
+    if distance <= max_distance {
+        Some(distance)
+    } else {
+        None
+    }
+}
+
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == cb { 0 } else { 1 };
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+pub fn highlight_ranges(text: &str, query: &str) -> Vec<Range<usize>> {
+    // -----------------------------------------------------------------------------------------------
+    // byte ranges of `text` that matched the query, in order and non-overlapping, for highlighting
+    // the results. prefix and substring matches highlight just the matched part of the word, typo
+    // matches highlight the whole word
+    // -----------------------------------------------------------------------------------------------
+    let tokens = tokenize(query);
+    let mut ranges = Vec::new();
+
+    for range in word_ranges(text) {
+        let original = &text[range.clone()];
+        let word = original.to_lowercase();
+        // converts a char offset in the lowercased word back to a byte offset in the original text
+        let byte_at = |chars: usize| {
+            range.start
+                + original
+                    .char_indices()
+                    .nth(chars)
+                    .map(|(i, _)| i)
+                    .unwrap_or(original.len())
+        };
+
+        for token in &tokens {
+            let token_chars = token.chars().count();
+            if word.starts_with(token.as_str()) {
+                ranges.push(range.start..byte_at(token_chars));
+                break;
+            }
+            if token_chars >= 3 {
+                if let Some(position) = word.find(token.as_str()) {
+                    let start = word[..position].chars().count();
+                    ranges.push(byte_at(start)..byte_at(start + token_chars));
+                    break;
+                }
+                let max_distance = if token_chars > 5 { 2 } else { 1 };
+                if fuzzy_distance(token, &word, max_distance).is_some() {
+                    ranges.push(range.clone());
+                    break;
+                }
+            }
+        }
+    }
+
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn distance(a: &str, b: &str) -> usize {
+        let a: Vec<char> = a.chars().collect();
+        let b: Vec<char> = b.chars().collect();
+        levenshtein(&a, &b)
+    }
+
+    fn track(title: &str, artist: &str, album: &str) -> MusicFile {
+        MusicFile {
+            title: title.to_string(),
+            artist: artist.to_string(),
+            album: album.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn highlighted<'a>(text: &'a str, query: &str) -> Vec<&'a str> {
+        highlight_ranges(text, query)
+            .into_iter()
+            .map(|range| &text[range])
+            .collect()
+    }
+
+    #[test]
+    fn levenshtein_distance() {
+        assert_eq!(distance("kitten", "sitting"), 3);
+        assert_eq!(distance("", "abc"), 3);
+        assert_eq!(distance("abc", ""), 3);
+        assert_eq!(distance("same", "same"), 0);
+        assert_eq!(distance("flaw", "lawn"), 2);
+        // characters, not bytes
+        assert_eq!(distance("café", "cafe"), 1);
+    }
+
+    #[test]
+    fn fuzzy_distance_limits() {
+        assert_eq!(fuzzy_distance("bords", "boards", 1), Some(1));
+        assert_eq!(fuzzy_distance("bords", "boards", 0), None);
+        // a typo in a half-typed word is compared against the start of the word
+        assert_eq!(fuzzy_distance("camad", "canadian", 1), Some(1));
+        assert_eq!(fuzzy_distance("xyz", "canadian", 2), None);
+    }
+
+    #[test]
+    fn tokenize_splits_on_non_alphanumerics() {
+        assert_eq!(
+            tokenize("Boards of Canada - Roygbiv (Live)"),
+            vec!["boards", "of", "canada", "roygbiv", "live"]
+        );
+        assert_eq!(tokenize("Sigur Rós"), vec!["sigur", "rós"]);
+        assert!(tokenize(" -- ").is_empty());
+    }
+
+    #[test]
+    fn search_matches_every_word() {
+        let library = vec![
+            track(
+                "Roygbiv",
+                "Boards of Canada",
+                "Music Has the Right to Children",
+            ),
+            track("Xtal", "Aphex Twin", "Selected Ambient Works 85-92"),
+            track("Avril 14th", "Aphex Twin", "Drukqs"),
+        ];
+        let index = SearchIndex::build(&library);
+        let found = |query: &str| {
+            let mut found: Vec<usize> = index.search(query).iter().map(|hit| hit.index).collect();
+            found.sort_unstable();
+            found
+        };
+
+        assert_eq!(found("aphex"), vec![1, 2]);
+        assert_eq!(found("aphex drukqs"), vec![2]);
+        assert_eq!(found("aph"), vec![1, 2]); // prefix
+        assert_eq!(found("ygbi"), vec![0]); // substring
+        assert_eq!(found("aphx twn"), vec![1, 2]); // typos
+        assert_eq!(found("bords of canda"), vec![0]);
+        assert!(found("").is_empty());
+        assert!(found("nothing").is_empty());
+    }
+
+    #[test]
+    fn search_ranks_titles_above_artists() {
+        let library = vec![
+            track("Something", "Window", "Album"),
+            track("Window", "Someone", "Album"),
+        ];
+        let hits = SearchIndex::build(&library).search("window");
+        assert_eq!(
+            hits.iter().map(|hit| hit.index).collect::<Vec<_>>(),
+            vec![1, 0]
+        );
+        assert!(hits[0].score > hits[1].score);
+    }
+
+    #[test]
+    fn highlight_empty_query() {
+        assert!(highlight_ranges("Roygbiv", "").is_empty());
+        assert!(highlight_ranges("Roygbiv", " - ").is_empty());
+        assert!(highlight_ranges("", "roy").is_empty());
+    }
+
+    #[test]
+    fn highlight_matched_parts() {
+        assert_eq!(highlighted("Boards of Canada", "can"), vec!["Can"]);
+        assert_eq!(highlighted("Boards of Canada", "ana"), vec!["ana"]);
+        assert_eq!(
+            highlighted("Boards of Canada", "bords can"),
+            vec!["Boards", "Can"]
+        );
+    }
+
+    #[test]
+    fn highlight_multibyte_text() {
+        // the ranges are byte ranges that fall on character boundaries
+        assert_eq!(highlighted("Motörhead", "mot"), vec!["Mot"]);
+        assert_eq!(highlighted("Motörhead", "örh"), vec!["örh"]);
+        assert_eq!(highlighted("Motörhead", "motö"), vec!["Motö"]);
+        assert_eq!(highlighted("ÉTÉ Ω", "été ω"), vec!["ÉTÉ", "Ω"]);
+        assert_eq!(highlighted("Sigur Rós", "ros"), vec!["Rós"]);
+    }
+}
@@ -0,0 +1,414 @@
+use super::file_handling::MusicFile;
+use super::query::{Condition, Field, FieldKind, Op, Value};
+use std::cmp::Ordering;
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MatchMode {
+    All,
+    Any,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum Limit {
+    Tracks(usize),
+    Minutes(f64),
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SortRule {
+    pub field: Field,
+    pub descending: bool,
+}
+
+//---------------------------------------------------------------------------------------------------
+// SmartPlaylist struct
+// A playlist whose contents come from rules instead of being picked by hand. The rules are query
+// conditions (see query.rs), combined with "match all" or "match any", then the matching tracks are
+// sorted and cut off at a number of tracks or a total duration.
+//
+// The result is cached together with the library generation it was computed for, so the playlist is
+// re-evaluated automatically whenever the library (or anything stored in it) changes.
+//
+// Like playlists, a smart playlist is known by its id (see IdCounters) rather than by where it is in
+// the list, so deleting one doesn't change which one the others are.
+// --------------------------------------------------------------------------------------------------
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct SmartPlaylist {
+    #[serde(default)]
+    pub id: u32,
+    pub name: String,
+    pub rules: Vec<Condition>,
+    pub match_mode: MatchMode,
+    pub sort: Option<SortRule>,
+    pub limit: Option<Limit>,
+    #[serde(skip)]
+    cache: Option<(u64, Vec<usize>)>,
+}
+
+impl SmartPlaylist {
+    pub fn new(name: String) -> SmartPlaylist {
+        SmartPlaylist {
+            id: 0,
+            name,
+            rules: Vec::new(),
+            match_mode: MatchMode::All,
+            sort: None,
+            limit: None,
+            cache: None,
+        }
+    }
+
+    pub fn favourites() -> SmartPlaylist {
+        // the built-in collection of every track marked as a favourite
+        let mut playlist = SmartPlaylist::new("Favourites".to_string());
+        playlist.rules.push(Condition {
+            field: Field::Favourite,
+            op: Op::Equal,
+            value: Value::Number(1.0),
+        });
+        playlist
+    }
+
+    pub fn matches(&self, music_file: &MusicFile) -> bool {
+        // a playlist without rules is every track (see describe), whichever way they are combined
+        match self.match_mode {
+            MatchMode::All => self.rules.iter().all(|rule| rule.matches(music_file)),
+            MatchMode::Any => {
+                self.rules.is_empty() || self.rules.iter().any(|rule| rule.matches(music_file))
+            }
+        }
+    }
+
+    pub fn evaluate(&self, library: &[MusicFile]) -> Vec<usize> {
+        let mut tracks: Vec<usize> = (0..library.len())
+            .filter(|&i| self.matches(&library[i]))
+            .collect();
+
+        if let Some(sort) = self.sort {
+            tracks.sort_by(|&a, &b| {
+                let ordering = compare_field(sort.field, &library[a], &library[b]);
+                if sort.descending {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            });
+        }
+
+        match self.limit {
+            Some(Limit::Tracks(count)) => tracks.truncate(count),
+            Some(Limit::Minutes(minutes)) => {
+                let mut total = 0.0;
+                let keep = tracks
+                    .iter()
+                    .take_while(|&&i| {
+                        total += library[i].duration;
+                        total <= minutes * 60.0
+                    })
+                    .count();
+                tracks.truncate(keep);
+            }
+            None => {}
+        }
+        tracks
+    }
+
+    pub fn tracks(&mut self, library: &[MusicFile], generation: u64) -> &[usize] {
+        if self.cache.as_ref().map(|(g, _)| *g) != Some(generation) {
+            self.cache = Some((generation, self.evaluate(library)));
+        }
+        match &self.cache {
+            Some((_, tracks)) => tracks,
+            None => &[],
+        }
+    }
+
+    pub fn invalidate(&mut self) {
+        self.cache = None;
+    }
+
+    pub fn describe(&self) -> String {
+        let joiner = match self.match_mode {
+            MatchMode::All => " and ",
+            MatchMode::Any => " or ",
+        };
+        let rules: Vec<String> = self.rules.iter().map(|rule| rule.to_string()).collect();
+        if rules.is_empty() {
+            "all tracks".to_string()
+        } else {
+            rules.join(joiner)
+        }
+    }
+}
+
+pub fn compare_field(field: Field, a: &MusicFile, b: &MusicFile) -> Ordering {
+    match field.kind() {
+        FieldKind::Text => field
+            .text(a)
+            .to_lowercase()
+            .cmp(&field.text(b).to_lowercase()),
+        _ => field
+            .number(a)
+            .partial_cmp(&field.number(b))
+            .unwrap_or(Ordering::Equal),
+    }
+}
+
+//---------------------------------------------------------------------------------------------------
+// SmartPlaylistEditor struct
+// Holds the values in the smart playlist editor while the user is changing them. Rule values are
+// kept as text and only parsed when the playlist is saved, so half-typed values don't get lost.
+// --------------------------------------------------------------------------------------------------
+pub struct RuleEdit {
+    pub field: Field,
+    pub op: Op,
+    pub value: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LimitKind {
+    None,
+    Tracks,
+    Minutes,
+}
+
+pub struct SmartPlaylistEditor {
+    pub id: Option<u32>, // Some when editing an existing playlist
+    pub name: String,
+    pub match_mode: MatchMode,
+    pub rules: Vec<RuleEdit>,
+    pub sort_enabled: bool,
+    pub sort: SortRule,
+    pub limit_kind: LimitKind,
+    pub limit_value: f64,
+    pub error: Option<String>,
+}
+
+impl SmartPlaylistEditor {
+    pub fn new() -> SmartPlaylistEditor {
+        SmartPlaylistEditor {
+            id: None,
+            name: String::new(),
+            match_mode: MatchMode::All,
+            rules: vec![RuleEdit {
+                field: Field::Artist,
+                op: Op::Contains,
+                value: String::new(),
+            }],
+            sort_enabled: false,
+            sort: SortRule {
+                field: Field::Title,
+                descending: false,
+            },
+            limit_kind: LimitKind::None,
+            limit_value: 25.0,
+            error: None,
+        }
+    }
+
+    pub fn edit(playlist: &SmartPlaylist) -> SmartPlaylistEditor {
+        let (limit_kind, limit_value) = match playlist.limit {
+            Some(Limit::Tracks(count)) => (LimitKind::Tracks, count as f64),
+            Some(Limit::Minutes(minutes)) => (LimitKind::Minutes, minutes),
+            None => (LimitKind::None, 25.0),
+        };
+        SmartPlaylistEditor {
+            id: Some(playlist.id),
+            name: playlist.name.clone(),
+            match_mode: playlist.match_mode,
+            rules: playlist
+                .rules
+                .iter()
+                .map(|rule| RuleEdit {
+                    field: rule.field,
+                    op: rule.op,
+                    value: rule.value_text().trim_matches('"').to_string(),
+                })
+                .collect(),
+            sort_enabled: playlist.sort.is_some(),
+            sort: playlist.sort.unwrap_or(SortRule {
+                field: Field::Title,
+                descending: false,
+            }),
+            limit_kind,
+            limit_value,
+            error: None,
+        }
+    }
+
+    pub fn build(&self) -> Result<SmartPlaylist, String> {
+        if self.name.trim().is_empty() {
+            return Err("the playlist needs a name".to_string());
+        }
+        let mut rules = Vec::new();
+        for rule in &self.rules {
+            let condition = Condition::new(rule.field, rule.op, &rule.value)
+                .map_err(|e| format!("{} rule: {}", rule.field.name(), e))?;
+            rules.push(condition);
+        }
+
+        Ok(SmartPlaylist {
+            id: self.id.unwrap_or(0),
+            name: self.name.trim().to_string(),
+            rules,
+            match_mode: self.match_mode,
+            sort: if self.sort_enabled {
+                Some(self.sort)
+            } else {
+                None
+            },
+            limit: match self.limit_kind {
+                LimitKind::None => None,
+                LimitKind::Tracks => Some(Limit::Tracks(self.limit_value.max(0.0) as usize)),
+                LimitKind::Minutes => Some(Limit::Minutes(self.limit_value.max(0.0))),
+            },
+            cache: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(title: &str, artist: &str, year: i32, duration: f64) -> MusicFile {
+        MusicFile {
+            title: title.to_string(),
+            artist: artist.to_string(),
+            year: Some(year),
+            duration,
+            ..Default::default()
+        }
+    }
+
+    fn library() -> Vec<MusicFile> {
+        vec![
+            track("Intro", "Boards of Canada", 1998, 60.0),
+            track("Roygbiv", "Boards of Canada", 1998, 150.0),
+            track("Windowlicker", "Aphex Twin", 1999, 360.0),
+            track("Xtal", "Aphex Twin", 1992, 290.0),
+        ]
+    }
+
+    fn rule(field: Field, op: Op, value: &str) -> Condition {
+        Condition::new(field, op, value).unwrap()
+    }
+
+    #[test]
+    fn match_all_and_match_any() {
+        let library = library();
+        let mut playlist = SmartPlaylist::new("test".to_string());
+        playlist.rules = vec![
+            rule(Field::Artist, Op::Contains, "aphex"),
+            rule(Field::Year, Op::Less, "1995"),
+        ];
+        assert_eq!(playlist.evaluate(&library), vec![3]);
+        playlist.match_mode = MatchMode::Any;
+        assert_eq!(playlist.evaluate(&library), vec![2, 3]);
+
+        // no rules at all is every track, also when matching any rule
+        playlist.rules.clear();
+        assert_eq!(playlist.evaluate(&library), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn favourites_are_the_favourite_tracks() {
+        let mut library = library();
+        library[1].favourite = true;
+        library[3].favourite = true;
+        assert_eq!(SmartPlaylist::favourites().evaluate(&library), vec![1, 3]);
+    }
+
+    #[test]
+    fn sort_then_limit_to_a_number_of_tracks() {
+        let library = library();
+        let mut playlist = SmartPlaylist::new("test".to_string());
+        playlist.sort = Some(SortRule {
+            field: Field::Year,
+            descending: true,
+        });
+        playlist.limit = Some(Limit::Tracks(3));
+        // equal years keep library order
+        assert_eq!(playlist.evaluate(&library), vec![2, 0, 1]);
+
+        playlist.sort = Some(SortRule {
+            field: Field::Title,
+            descending: false,
+        });
+        playlist.limit = Some(Limit::Tracks(10));
+        assert_eq!(playlist.evaluate(&library), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn limit_by_minutes_stops_before_going_over() {
+        let library = library();
+        let mut playlist = SmartPlaylist::new("test".to_string());
+        // 60 + 150 = 210 seconds fit in 4 minutes, the 360 second track doesn't
+        playlist.limit = Some(Limit::Minutes(4.0));
+        assert_eq!(playlist.evaluate(&library), vec![0, 1]);
+        playlist.limit = Some(Limit::Minutes(3.5));
+        assert_eq!(playlist.evaluate(&library), vec![0, 1]);
+        playlist.limit = Some(Limit::Minutes(0.5));
+        assert!(playlist.evaluate(&library).is_empty());
+    }
+
+    #[test]
+    fn tracks_are_cached_until_the_generation_changes() {
+        let mut library = library();
+        let mut playlist = SmartPlaylist::favourites();
+        library[0].favourite = true;
+        assert_eq!(playlist.tracks(&library, 1), &[0]);
+
+        // the same generation gives the cached tracks, even though the library changed
+        library[2].favourite = true;
+        assert_eq!(playlist.tracks(&library, 1), &[0]);
+        assert_eq!(playlist.tracks(&library, 2), &[0, 2]);
+
+        library[0].favourite = false;
+        playlist.invalidate();
+        assert_eq!(playlist.tracks(&library, 2), &[2]);
+    }
+
+    #[test]
+    fn editor_round_trip() {
+        let mut playlist = SmartPlaylist::new("Nineties".to_string());
+        playlist.id = 7;
+        playlist.match_mode = MatchMode::Any;
+        playlist.rules = vec![
+            rule(Field::Year, Op::Equal, "1990..1999"),
+            rule(Field::Artist, Op::Contains, "aphex twin"),
+        ];
+        playlist.sort = Some(SortRule {
+            field: Field::Year,
+            descending: true,
+        });
+        playlist.limit = Some(Limit::Minutes(30.0));
+
+        let built = SmartPlaylistEditor::edit(&playlist).build().unwrap();
+        assert_eq!(built.id, 7);
+        assert_eq!(built.name, "Nineties");
+        assert_eq!(built.match_mode, MatchMode::Any);
+        assert_eq!(built.rules, playlist.rules);
+        assert_eq!(built.sort, playlist.sort);
+        assert_eq!(built.limit, playlist.limit);
+    }
+
+    #[test]
+    fn editor_errors() {
+        let mut editor = SmartPlaylistEditor::new();
+        assert!(editor.build().is_err());
+        editor.name = "Old".to_string();
+        editor.rules = vec![RuleEdit {
+            field: Field::Year,
+            op: Op::Less,
+            value: "long ago".to_string(),
+        }];
+        assert_eq!(
+            editor.build().unwrap_err(),
+            "year rule: 'long ago' is not a number"
+        );
+        // a new playlist gets its id when it is saved
+        editor.rules[0].value = "1990".to_string();
+        assert_eq!(editor.build().unwrap().id, 0);
+    }
+}
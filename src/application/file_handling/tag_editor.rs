@@ -0,0 +1,404 @@
+use super::file_handling::MusicFile;
+use audiotags::Tag;
+
+//---------------------------------------------------------------------------------------------------
+// TagField enum
+// The tag fields that can be changed from the batch editor
+// --------------------------------------------------------------------------------------------------
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TagField {
+    Title,
+    Artist,
+    Album,
+}
+
+impl TagField {
+    pub const ALL: [TagField; 3] = [TagField::Title, TagField::Artist, TagField::Album];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            TagField::Title => "Title",
+            TagField::Artist => "Artist",
+            TagField::Album => "Album",
+        }
+    }
+
+    pub fn get<'a>(&self, music_file: &'a MusicFile) -> &'a str {
+        match self {
+            TagField::Title => &music_file.title,
+            TagField::Artist => &music_file.artist,
+            TagField::Album => &music_file.album,
+        }
+    }
+
+    pub fn set(&self, music_file: &mut MusicFile, value: String) {
+        match self {
+            TagField::Title => music_file.title = value,
+            TagField::Artist => music_file.artist = value,
+            TagField::Album => music_file.album = value,
+        }
+    }
+}
+
+//---------------------------------------------------------------------------------------------------
+// CaseConversion enum
+// Case changes that can be applied to a field of every selected track
+// --------------------------------------------------------------------------------------------------
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CaseConversion {
+    None,
+    Upper,
+    Lower,
+    Title,
+}
+
+impl CaseConversion {
+    pub const ALL: [CaseConversion; 4] = [
+        CaseConversion::None,
+        CaseConversion::Upper,
+        CaseConversion::Lower,
+        CaseConversion::Title,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            CaseConversion::None => "No change",
+            CaseConversion::Upper => "UPPER CASE",
+            CaseConversion::Lower => "lower case",
+            CaseConversion::Title => "Title Case",
+        }
+    }
+
+    pub fn apply(&self, value: &str) -> String {
+        match self {
+            CaseConversion::None => value.to_string(),
+            CaseConversion::Upper => value.to_uppercase(),
+            CaseConversion::Lower => value.to_lowercase(),
+            CaseConversion::Title => value
+                .split(' ')
+                .map(|word| {
+                    let mut chars = word.chars();
+                    match chars.next() {
+                        Some(first) => {
+                            first.to_uppercase().collect::<String>()
+                                + &chars.as_str().to_lowercase()
+                        }
+                        None => String::new(),
+                    }
+                })
+                .collect::<Vec<String>>()
+                .join(" "),
+        }
+    }
+}
+
+//---------------------------------------------------------------------------------------------------
+// FieldEdit struct
+// Holds the value the user is typing for one field. original is None when the selected tracks don't
+// all share the same value, in which case the field is shown as "(multiple values)"
+// --------------------------------------------------------------------------------------------------
+pub struct FieldEdit {
+    pub original: Option<String>,
+    pub value: String,
+}
+
+impl FieldEdit {
+    fn from_values<'a>(mut values: impl Iterator<Item = &'a str>) -> FieldEdit {
+        let first = values.next().unwrap_or_default();
+        let original = if values.all(|v| v == first) {
+            Some(first.to_string())
+        } else {
+            None
+        };
+        FieldEdit {
+            value: original.clone().unwrap_or_default(),
+            original,
+        }
+    }
+
+    pub fn is_mixed(&self) -> bool {
+        self.original.is_none()
+    }
+
+    pub fn is_changed(&self) -> bool {
+        match &self.original {
+            Some(original) => original != &self.value,
+            None => !self.value.is_empty(),
+        }
+    }
+}
+
+//---------------------------------------------------------------------------------------------------
+// BatchTagEditor struct
+// This struct holds the state of the batch tag editor. targets are indices into the music library,
+// in the order the tracks are shown in the library grid (used for auto-numbering)
+// --------------------------------------------------------------------------------------------------
+pub struct BatchTagEditor {
+    pub targets: Vec<usize>,
+    pub fields: [FieldEdit; 3],
+    pub find_field: TagField,
+    pub find: String,
+    pub replace: String,
+    pub case_field: TagField,
+    pub case_conversion: CaseConversion,
+    pub auto_number: bool,
+    pub errors: Vec<String>,
+}
+
+impl BatchTagEditor {
+    pub fn new(library: &[MusicFile], targets: Vec<usize>) -> BatchTagEditor {
+        let selected: Vec<&MusicFile> = targets.iter().filter_map(|&i| library.get(i)).collect();
+        let field_edit =
+            |field: TagField| FieldEdit::from_values(selected.iter().map(|m| field.get(m)));
+
+        BatchTagEditor {
+            fields: [
+                field_edit(TagField::Title),
+                field_edit(TagField::Artist),
+                field_edit(TagField::Album),
+            ],
+            targets,
+            find_field: TagField::Title,
+            find: String::new(),
+            replace: String::new(),
+            case_field: TagField::Title,
+            case_conversion: CaseConversion::None,
+            auto_number: false,
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn field_mut(&mut self, field: TagField) -> &mut FieldEdit {
+        &mut self.fields[field as usize]
+    }
+
+    pub fn apply(&mut self, library: &mut [MusicFile]) -> Vec<usize> {
+        // -----------------------------------------------------------------------------------------------
+        // applies the edits to every target and writes the changed fields back to the files. only the
+        // fields that were actually touched are written, so untouched tags are left exactly as they were.
+        // returns the indices of the tracks that were updated. a track whose file couldn't be written
+        // keeps its old tags in the library too, so the library never shows what isn't on disk
+        // -----------------------------------------------------------------------------------------------
+        self.errors.clear();
+        let mut updated = Vec::new();
+
+        for (position, &i) in self.targets.iter().enumerate() {
+            let mut music_file = match library.get(i) {
+                Some(music_file) => music_file.clone(),
+                None => continue,
+            };
+            let touched = self.edit(position, &mut music_file);
+            if touched.is_empty() && !self.auto_number {
+                continue;
+            }
+
+            match write_tags(&music_file, &touched, self.auto_number) {
+                Ok(()) => {
+                    library[i] = music_file;
+                    updated.push(i);
+                }
+                Err(e) => self.errors.push(format!("{}: {}", music_file.name, e)),
+            }
+        }
+
+        updated
+    }
+
+    fn edit(&self, position: usize, music_file: &mut MusicFile) -> Vec<TagField> {
+        // makes the edits to the target at `position`, returns the tag fields that changed
+        let mut touched: Vec<TagField> = Vec::new();
+
+        for field in TagField::ALL {
+            let edit = &self.fields[field as usize];
+            if edit.is_changed() {
+                field.set(music_file, edit.value.clone());
+                touched.push(field);
+            }
+        }
+
+        if !self.find.is_empty() {
+            let value = self
+                .find_field
+                .get(music_file)
+                .replace(&self.find, &self.replace);
+            if value != self.find_field.get(music_file) {
+                self.find_field.set(music_file, value);
+                if !touched.contains(&self.find_field) {
+                    touched.push(self.find_field);
+                }
+            }
+        }
+
+        if self.case_conversion != CaseConversion::None {
+            let value = self.case_conversion.apply(self.case_field.get(music_file));
+            if value != self.case_field.get(music_file) {
+                self.case_field.set(music_file, value);
+                if !touched.contains(&self.case_field) {
+                    touched.push(self.case_field);
+                }
+            }
+        }
+
+        if self.auto_number {
+            music_file.track_number = Some(position as u16 + 1);
+        }
+        touched
+    }
+}
+
+pub fn write_tags(
+    music_file: &MusicFile,
+    fields: &[TagField],
+    track_number: bool,
+) -> audiotags::Result<()> {
+    let mut tag = Tag::new().read_from_path(&music_file.file_path)?;
+
+    for field in fields {
+        match field {
+            TagField::Title => tag.set_title(&music_file.title),
+            TagField::Artist => tag.set_artist(&music_file.artist),
+            TagField::Album => tag.set_album_title(&music_file.album),
+        }
+    }
+    if track_number {
+        if let Some(n) = music_file.track_number {
+            tag.set_track_number(n);
+        }
+    }
+
+    tag.write_to_path(&music_file.file_path.to_string_lossy())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(title: &str, artist: &str, album: &str) -> MusicFile {
+        MusicFile {
+            title: title.to_string(),
+            artist: artist.to_string(),
+            album: album.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn edited(editor: &BatchTagEditor, library: &[MusicFile]) -> Vec<(MusicFile, Vec<TagField>)> {
+        editor
+            .targets
+            .iter()
+            .enumerate()
+            .map(|(position, &i)| {
+                let mut music_file = library[i].clone();
+                let touched = editor.edit(position, &mut music_file);
+                (music_file, touched)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn case_conversions() {
+        let text = "the  dARK side of THE moon";
+        assert_eq!(CaseConversion::None.apply(text), text);
+        assert_eq!(
+            CaseConversion::Upper.apply(text),
+            "THE  DARK SIDE OF THE MOON"
+        );
+        assert_eq!(
+            CaseConversion::Lower.apply(text),
+            "the  dark side of the moon"
+        );
+        // every word starts with a capital, runs of spaces are kept
+        assert_eq!(
+            CaseConversion::Title.apply(text),
+            "The  Dark Side Of The Moon"
+        );
+        assert_eq!(CaseConversion::Title.apply("éLAN über"), "Élan Über");
+        assert_eq!(CaseConversion::Title.apply(""), "");
+    }
+
+    #[test]
+    fn shared_values_are_shown_and_different_ones_are_mixed() {
+        let library = vec![
+            track("One", "Artist", "Album"),
+            track("Two", "Artist", "Album"),
+        ];
+        let mut editor = BatchTagEditor::new(&library, vec![0, 1]);
+        assert!(editor.field_mut(TagField::Title).is_mixed());
+        assert_eq!(editor.field_mut(TagField::Title).value, "");
+        assert_eq!(
+            editor.field_mut(TagField::Artist).original.as_deref(),
+            Some("Artist")
+        );
+        assert!(!editor.field_mut(TagField::Artist).is_changed());
+
+        // a mixed field only counts as changed once something is typed into it
+        assert!(!editor.field_mut(TagField::Title).is_changed());
+        editor.field_mut(TagField::Title).value = "Same".to_string();
+        assert!(editor.field_mut(TagField::Title).is_changed());
+
+        let single = BatchTagEditor::new(&library, vec![1]);
+        assert_eq!(single.fields[0].original.as_deref(), Some("Two"));
+    }
+
+    #[test]
+    fn only_touched_fields_are_written() {
+        let library = vec![
+            track("One", "Artist", "Album"),
+            track("Two", "Artist", "Album"),
+        ];
+        let mut editor = BatchTagEditor::new(&library, vec![0, 1]);
+        editor.field_mut(TagField::Album).value = "New Album".to_string();
+
+        let edited = edited(&editor, &library);
+        assert_eq!(edited[0].0.title, "One");
+        assert_eq!(edited[1].0.title, "Two");
+        assert_eq!(edited[1].0.album, "New Album");
+        assert_eq!(edited[1].1, vec![TagField::Album]);
+    }
+
+    #[test]
+    fn find_replace_then_case_conversion() {
+        let library = vec![
+            track("live at home (live)", "", ""),
+            track("studio take", "", ""),
+        ];
+        let mut editor = BatchTagEditor::new(&library, vec![0, 1]);
+        editor.find = "live".to_string();
+        editor.replace = "LIVE".to_string();
+
+        let edited_tracks = edited(&editor, &library);
+        assert_eq!(edited_tracks[0].0.title, "LIVE at home (LIVE)");
+        assert_eq!(edited_tracks[0].1, vec![TagField::Title]);
+        // nothing to replace, so nothing to write
+        assert!(edited_tracks[1].1.is_empty());
+
+        editor.case_conversion = CaseConversion::Title;
+        let edited_tracks = edited(&editor, &library);
+        assert_eq!(edited_tracks[0].0.title, "Live At Home (live)");
+        assert_eq!(edited_tracks[0].1, vec![TagField::Title]);
+        assert_eq!(edited_tracks[1].0.title, "Studio Take");
+    }
+
+    #[test]
+    fn auto_number_follows_the_target_order() {
+        let library = vec![track("A", "", ""), track("B", "", ""), track("C", "", "")];
+        let mut editor = BatchTagEditor::new(&library, vec![2, 0, 1]);
+        editor.auto_number = true;
+
+        let numbers: Vec<(String, Option<u16>)> = edited(&editor, &library)
+            .into_iter()
+            .map(|(m, touched)| {
+                assert!(touched.is_empty());
+                (m.title, m.track_number)
+            })
+            .collect();
+        assert_eq!(
+            numbers,
+            vec![
+                ("C".to_string(), Some(1)),
+                ("A".to_string(), Some(2)),
+                ("B".to_string(), Some(3)),
+            ]
+        );
+    }
+}
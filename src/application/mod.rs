@@ -1,2 +1,3 @@
 pub mod app;
-pub mod file_handling;
\ No newline at end of file
+pub mod file_handling;
+pub mod track_table;
\ No newline at end of file
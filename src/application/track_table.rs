@@ -0,0 +1,467 @@
+use super::file_handling::columns::*;
+use super::file_handling::file_handling::MusicFile;
+use super::file_handling::query::Field;
+use super::file_handling::search::highlight_ranges;
+use egui::*;
+use egui_extras::{Column, TableBuilder};
+use std::collections::{BTreeSet, HashSet};
+use std::path::PathBuf;
+
+const ROW_HEIGHT: f32 = 30.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TrackMenuItem {
+    Play,
+    EditTags,
+    AddToPlaylist,
+    AddToQueue,
+    AddToQueueFront,
+    RemoveFromPlaylist,
+}
+
+impl TrackMenuItem {
+    pub fn label(&self) -> &'static str {
+        match self {
+            TrackMenuItem::Play => "Play File",
+            TrackMenuItem::EditTags => "Edit Tags",
+            TrackMenuItem::AddToPlaylist => "Add to Playlist",
+            TrackMenuItem::AddToQueue => "Add to Queue",
+            TrackMenuItem::AddToQueueFront => "Add to beginning of Queue",
+            TrackMenuItem::RemoveFromPlaylist => "Remove from Playlist",
+        }
+    }
+}
+
+pub const LIBRARY_MENU: [TrackMenuItem; 5] = [
+    TrackMenuItem::Play,
+    TrackMenuItem::EditTags,
+    TrackMenuItem::AddToPlaylist,
+    TrackMenuItem::AddToQueue,
+    TrackMenuItem::AddToQueueFront,
+];
+
+pub const COLLECTION_MENU: [TrackMenuItem; 4] = [
+    TrackMenuItem::Play,
+    TrackMenuItem::AddToPlaylist,
+    TrackMenuItem::AddToQueue,
+    TrackMenuItem::AddToQueueFront,
+];
+
+pub const PLAYLIST_MENU: [TrackMenuItem; 5] = [
+    TrackMenuItem::Play,
+    TrackMenuItem::AddToPlaylist,
+    TrackMenuItem::AddToQueue,
+    TrackMenuItem::AddToQueueFront,
+    TrackMenuItem::RemoveFromPlaylist,
+];
+
+// what the user did in the table. the usize is the index of the track in the table's track slice
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TrackEvent {
+    Select(usize, Modifiers),
+    Menu(TrackMenuItem, usize), // double clicking a track is reported as Menu(Play, index)
+    Rate(usize, Option<u8>),
+    Favourite(usize, bool),
+    Move(usize, usize), // a track was dragged to a position in the rows (0 is above the first row)
+}
+
+//-----------------------------------------------------------------------------------------------
+// TrackTable widget
+// The table used for every list of tracks (library, playlists, smart playlists, albums). It shows
+// `rows`, which are indices into `tracks`, so the tracks are borrowed instead of copied, and only
+// the rows that are scrolled into view are laid out each frame, which keeps it fast with very large
+// libraries.
+//
+// The table doesn't change any app state itself. Clicks and context menu choices are returned as
+// TrackEvents for the caller to apply, and only the menu items passed to menu() are offered. The
+// column layout is changed in place when the user sorts, resizes, moves or hides columns; sorting
+// the rows is up to the caller. With reorderable(true) rows can be dragged by their title, and
+// dropping one is reported as a Move for the caller to reorder its tracks.
+//
+// A list that refers to the tracks rather than being a slice of them (a playlist, which can have the
+// same track more than once) passes its track indices to entries(). The rows are then indices into
+// the entries, and the selection and the events use those too.
+//
+//     let events = TrackTable::new("library", &library, &rows, &mut layout)
+//         .selection(&selected)
+//         .menu(&LIBRARY_MENU)
+//         .show(ui);
+//-----------------------------------------------------------------------------------------------
+pub struct TrackTable<'a> {
+    id_source: &'a str,
+    tracks: &'a [MusicFile],
+    rows: &'a [usize],
+    layout: &'a mut ColumnLayout,
+    selection: Option<&'a BTreeSet<usize>>,
+    highlight: &'a str,
+    menu: &'a [TrackMenuItem],
+    missing: Option<&'a HashSet<PathBuf>>,
+    entries: Option<&'a [usize]>,
+    reorderable: bool,
+    default_layout: fn() -> ColumnLayout,
+}
+
+impl<'a> TrackTable<'a> {
+    pub fn new(
+        id_source: &'a str,
+        tracks: &'a [MusicFile],
+        rows: &'a [usize],
+        layout: &'a mut ColumnLayout,
+    ) -> Self {
+        TrackTable {
+            id_source,
+            tracks,
+            rows,
+            layout,
+            selection: None,
+            highlight: "",
+            menu: &[],
+            missing: None,
+            entries: None,
+            reorderable: false,
+            default_layout: ColumnLayout::default,
+        }
+    }
+
+    // shows which tracks are selected and reports clicks on the title as TrackEvent::Select
+    pub fn selection(mut self, selection: &'a BTreeSet<usize>) -> Self {
+        self.selection = Some(selection);
+        self
+    }
+
+    // highlights the parts of each cell that match a plain search query
+    pub fn highlight(mut self, query: &'a str) -> Self {
+        self.highlight = query;
+        self
+    }
+
+    pub fn menu(mut self, menu: &'a [TrackMenuItem]) -> Self {
+        self.menu = menu;
+        self
+    }
+
+    // flags the tracks whose files can't be found
+    pub fn missing(mut self, missing: &'a HashSet<PathBuf>) -> Self {
+        self.missing = Some(missing);
+        self
+    }
+
+    // the rows index into `entries`, which hold the indices of the tracks shown
+    pub fn entries(mut self, entries: &'a [usize]) -> Self {
+        self.entries = Some(entries);
+        self
+    }
+
+    pub fn reorderable(mut self, reorderable: bool) -> Self {
+        self.reorderable = reorderable;
+        self
+    }
+
+    // the layout Reset Columns goes back to, when the table doesn't start with the library columns
+    pub fn default_layout(mut self, default_layout: fn() -> ColumnLayout) -> Self {
+        self.default_layout = default_layout;
+        self
+    }
+
+    pub fn show(self, ui: &mut Ui) -> Vec<TrackEvent> {
+        let TrackTable {
+            id_source,
+            tracks,
+            rows,
+            layout,
+            selection,
+            highlight,
+            menu,
+            missing,
+            entries,
+            reorderable,
+            default_layout,
+        } = self;
+        let columns = layout.visible();
+        let mut widths: Vec<f32> = Vec::new();
+        let mut events: Vec<TrackEvent> = Vec::new();
+
+        // the dragged track is kept in egui's memory, its row can scroll out of view mid drag
+        let drag_id = ui.make_persistent_id((id_source, "dragged track"));
+        let dragged: Option<usize> = ui.data(|d| d.get_temp(drag_id)).filter(|_| reorderable);
+        let pointer = ui.input(|i| i.pointer.hover_pos());
+        let painter = ui.painter().clone();
+        let mut drop_row: Option<usize> = None;
+
+        // a new id whenever the visible columns change, so the widths start from the saved ones
+        ui.push_id((id_source, layout.id()), |ui| {
+            let mut table = TableBuilder::new(ui)
+                .striped(true)
+                .resizable(true)
+                .auto_shrink([false; 2])
+                .max_scroll_height(f32::INFINITY)
+                .cell_layout(Layout::left_to_right(Align::Center));
+            for column in &columns {
+                table = table.column(Column::initial(column.width).at_least(40.0).clip(true));
+            }
+            table
+                .header(25.0, |mut header| {
+                    for column in &columns {
+                        header.col(|ui| {
+                            column_header_cell(ui, layout, default_layout, column.field);
+                        });
+                    }
+                })
+                .body(|body| {
+                    widths = body.widths().to_vec();
+                    body.rows(ROW_HEIGHT, rows.len(), |row_index, mut row| {
+                        let index = rows[row_index];
+                        let track = match entries {
+                            Some(entries) => &tracks[entries[index]],
+                            None => &tracks[index],
+                        };
+                        let is_missing = missing.map_or(false, |m| m.contains(&track.file_path));
+                        let mut row_rect = Rect::NOTHING;
+                        for column in &columns {
+                            let (rect, _) = row.col(|ui| match column.field {
+                                Field::Title => {
+                                    if is_missing {
+                                        ui.colored_label(
+                                            Color32::from_rgb(230, 150, 30),
+                                            "\u{26A0}",
+                                        )
+                                        .on_hover_text("File not found");
+                                    }
+                                    events.extend(title_cell(
+                                        ui,
+                                        index,
+                                        track,
+                                        selection,
+                                        highlight,
+                                        menu,
+                                        Some(drag_id).filter(|_| reorderable),
+                                    ))
+                                }
+                                Field::Rating => {
+                                    if let Some(rating) = rating_stars(ui, track.rating) {
+                                        events.push(TrackEvent::Rate(index, rating));
+                                    }
+                                }
+                                Field::Favourite => {
+                                    if let Some(favourite) = favourite_toggle(ui, track.favourite) {
+                                        events.push(TrackEvent::Favourite(index, favourite));
+                                    }
+                                }
+                                field => {
+                                    ui.label(highlighted(ui, &cell_text(field, track), highlight));
+                                }
+                            });
+                            row_rect = row_rect.union(rect);
+                        }
+
+                        // a line above or below the row the pointer is over shows where the drop goes
+                        if let (Some(_), Some(pointer)) = (dragged, pointer) {
+                            if row_rect.y_range().contains(&pointer.y) {
+                                let below = pointer.y > row_rect.center().y;
+                                drop_row = Some(row_index + below as usize);
+                                let y = if below {
+                                    row_rect.bottom()
+                                } else {
+                                    row_rect.top()
+                                };
+                                painter.hline(
+                                    row_rect.x_range(),
+                                    y,
+                                    Stroke::new(2.0, Color32::LIGHT_BLUE),
+                                );
+                            }
+                        }
+                    });
+                });
+        });
+
+        if let Some(index) = dragged {
+            ui.ctx().set_cursor_icon(CursorIcon::Grabbing);
+            if !ui.input(|i| i.pointer.any_down()) {
+                ui.data_mut(|d| d.remove::<usize>(drag_id));
+                if let Some(row) = drop_row {
+                    events.push(TrackEvent::Move(index, row));
+                }
+            }
+        }
+
+        // remember the widths the user dragged the columns to
+        for (column, width) in columns.iter().zip(widths) {
+            layout.set_width(column.field, width);
+        }
+        events
+    }
+}
+
+fn title_cell(
+    ui: &mut Ui,
+    index: usize,
+    track: &MusicFile,
+    selection: Option<&BTreeSet<usize>>,
+    highlight: &str,
+    menu: &[TrackMenuItem],
+    drag_id: Option<Id>,
+) -> Option<TrackEvent> {
+    // -----------------------------------------------------------------------------------------------
+    // the title is the part of the row that reacts to the mouse: click to select, double click to
+    // play, right click for the context menu, and drag to reorder when the table has a drag_id to
+    // remember the dragged track under
+    // -----------------------------------------------------------------------------------------------
+    let text = highlighted(ui, display_title(track), highlight);
+    let mut response = match selection {
+        Some(selected) => ui.selectable_label(selected.contains(&index), text),
+        None => ui.add(Label::new(text).sense(Sense::click())),
+    };
+    if let Some(drag_id) = drag_id {
+        response = response.interact(Sense::drag());
+        if response.drag_started() {
+            ui.data_mut(|d| d.insert_temp(drag_id, index));
+        }
+    }
+
+    let mut event = None;
+    if response.clicked() && selection.is_some() {
+        event = Some(TrackEvent::Select(index, ui.input(|i| i.modifiers)));
+    }
+    if response.double_clicked() {
+        event = Some(TrackEvent::Menu(TrackMenuItem::Play, index));
+    }
+    if !menu.is_empty() {
+        response.context_menu(|ui| {
+            for item in menu {
+                if ui.button(item.label()).clicked() {
+                    event = Some(TrackEvent::Menu(*item, index));
+                    ui.close_menu();
+                }
+            }
+        });
+    }
+    event
+}
+
+pub fn rating_stars(ui: &mut Ui, rating: Option<u8>) -> Option<Option<u8>> {
+    // -----------------------------------------------------------------------------------------------
+    // five clickable stars. returns the new rating when one was clicked; clicking the current rating
+    // again clears it
+    // -----------------------------------------------------------------------------------------------
+    let current = rating.unwrap_or(0);
+    let mut clicked = None;
+    ui.spacing_mut().item_spacing.x = 0.0;
+    for star in 1..=5 {
+        let (text, color) = if star <= current {
+            ("\u{2605}", Color32::GOLD)
+        } else {
+            ("\u{2606}", ui.visuals().weak_text_color())
+        };
+        let response = ui.add(Label::new(RichText::new(text).color(color)).sense(Sense::click()));
+        if response.clicked() {
+            clicked = Some(if star == current { None } else { Some(star) });
+        }
+    }
+    clicked
+}
+
+pub fn favourite_toggle(ui: &mut Ui, favourite: bool) -> Option<bool> {
+    // a heart that is filled in for favourites. returns the new value when it was clicked
+    let color = if favourite {
+        Color32::from_rgb(220, 50, 70)
+    } else {
+        ui.visuals().weak_text_color()
+    };
+    let response = ui
+        .add(Label::new(RichText::new("\u{2665}").color(color)).sense(Sense::click()))
+        .on_hover_text(if favourite {
+            "Remove from Favourites"
+        } else {
+            "Add to Favourites"
+        });
+    if response.clicked() {
+        Some(!favourite)
+    } else {
+        None
+    }
+}
+
+fn column_header_cell(
+    ui: &mut Ui,
+    layout: &mut ColumnLayout,
+    default_layout: fn() -> ColumnLayout,
+    field: Field,
+) {
+    // -----------------------------------------------------------------------------------------------
+    // a clickable column header: click to sort, right click to move, hide or show columns
+    // -----------------------------------------------------------------------------------------------
+    let arrow = match layout.sort_direction(field) {
+        Some(false) => " \u{25B2}",
+        Some(true) => " \u{25BC}",
+        None => "",
+    };
+    let response = ui.add(
+        Label::new(RichText::new(format!("{}{}", column_header(field), arrow)).strong())
+            .sense(Sense::click()),
+    );
+    if response.clicked() {
+        layout.click_header(field);
+    }
+    response.context_menu(|ui| {
+        if ui.button("Move Left").clicked() {
+            layout.move_column(field, true);
+            ui.close_menu();
+        }
+        if ui.button("Move Right").clicked() {
+            layout.move_column(field, false);
+            ui.close_menu();
+        }
+        // the title column holds the play/queue actions, so it can't be hidden
+        if field != Field::Title && ui.button("Hide Column").clicked() {
+            layout.set_visible(field, false);
+            ui.close_menu();
+        }
+        ui.separator();
+        ui.menu_button("Columns", |ui| {
+            for column in &mut layout.columns {
+                ui.add_enabled(
+                    column.field != Field::Title,
+                    Checkbox::new(&mut column.visible, column_header(column.field)),
+                );
+            }
+        });
+        if ui.button("Reset Columns").clicked() {
+            *layout = default_layout();
+            ui.close_menu();
+        }
+    });
+}
+
+pub fn highlighted(ui: &Ui, text: &str, query: &str) -> WidgetText {
+    // lays out `text` with the parts that matched the search query highlighted
+    let ranges = highlight_ranges(text, query);
+    if ranges.is_empty() {
+        return text.into();
+    }
+
+    let normal = TextFormat {
+        font_id: TextStyle::Body.resolve(ui.style()),
+        color: ui.visuals().text_color(),
+        ..Default::default()
+    };
+    let highlight = TextFormat {
+        background: Color32::from_rgb(110, 90, 20),
+        color: Color32::WHITE,
+        ..normal.clone()
+    };
+
+    let mut job = egui::text::LayoutJob::default();
+    let mut last = 0;
+    for range in ranges {
+        if range.start > last {
+            job.append(&text[last..range.start], 0.0, normal.clone());
+        }
+        job.append(&text[range.clone()], 0.0, highlight.clone());
+        last = range.end;
+    }
+    if last < text.len() {
+        job.append(&text[last..], 0.0, normal);
+    }
+    job.into()
+}